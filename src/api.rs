@@ -0,0 +1,471 @@
+//! A JSON API over HTTP for dashboards and scripts: the newest screenshot, those of a day, and the images
+//! themselves. It reads the tree for every request, a watcher sorting into it meanwhile is no bother. Web pages can
+//! follow what the watcher sorts over a WebSocket. With a token or a login set, requests without them are refused
+
+use std::{
+	fs,
+	io::{self, BufRead, BufReader, Read, Write},
+	net::{SocketAddr, TcpListener, TcpStream},
+	path::{Component, Path, PathBuf},
+	sync::Mutex,
+	time::{Duration, UNIX_EPOCH},
+};
+
+use crate::{
+	browse::base64,
+	changes,
+	commands::{newest_files, organized_files},
+	date::Date,
+	events::{info, Event},
+	json, metadata, thumbs,
+	upload::percent_encode,
+	websocket, Args,
+};
+
+/// Where the API is served
+#[derive(Clone, Debug)]
+pub enum Listen {
+	Tcp(SocketAddr),
+	Unix(PathBuf),
+}
+
+impl Listen {
+	/// Parses an address like "127.0.0.1:9185", or "unix:" and a path
+	pub fn parse(s: &str) -> Result<Listen, String> {
+		match s.strip_prefix("unix:") {
+			Some("") => Err("\"unix:\" needs the path of the socket".to_owned()),
+			Some(path) => Ok(Listen::Unix(path.into())),
+			None => s.parse().map(Listen::Tcp).map_err(|_| format!("\"{s}\" is not an address or unix: and a path")),
+		}
+	}
+}
+
+/// A client, over TCP or a Unix socket
+trait Connection: Read + Write + Send + 'static {
+	fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl Connection for TcpStream {
+	fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		TcpStream::set_write_timeout(self, timeout)
+	}
+}
+
+#[cfg(unix)]
+impl Connection for std::os::unix::net::UnixStream {
+	fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		std::os::unix::net::UnixStream::set_write_timeout(self, timeout)
+	}
+}
+
+/// Web pages told about every screenshot sorted, over WebSockets
+static CLIENTS: Mutex<Vec<Box<dyn Connection>>> = Mutex::new(vec![]);
+
+/// Longest request line or header read, and most headers. Those of browsers are far shorter
+const MAX_LINE: u64 = 8 * 1024;
+const MAX_HEADERS: usize = 100;
+
+/// How long to wait before subscribing to the watcher again, after it went away or before it started
+const RESUBSCRIBE: Duration = Duration::from_secs(5);
+
+/// Answers requests on `listen` until the program is stopped, each in a thread of its own so a slow download
+/// doesn't hold up the rest
+pub fn serve(args: &Args, root: &Path, listen: &Listen) -> anyhow::Result<()> {
+	if let Listen::Tcp(addr) = listen {
+		if !addr.ip().is_loopback() && args.serve_token.is_none() && args.serve_login.is_none() {
+			anyhow::bail!("anyone who can reach {addr} would see the screenshots, give --serve-token or --serve-login");
+		}
+	}
+
+	if let Some(socket) = args.events_socket.clone() {
+		let (args, root) = (args.clone(), root.to_path_buf());
+		std::thread::spawn(move || follow(&args, &root, &socket));
+	}
+
+	match listen {
+		Listen::Tcp(addr) => {
+			let listener = TcpListener::bind(addr).map_err(|e| anyhow::anyhow!("listening on {addr}: {e}"))?;
+			info!("Serving \"{}\" on http://{addr}/api/latest", root.display());
+
+			for stream in listener.incoming().flatten() {
+				// A client that stops talking must not keep its thread forever
+				_ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+				handle(args, root, stream);
+			}
+		}
+		Listen::Unix(path) => serve_unix(args, root, path)?,
+	}
+
+	Ok(())
+}
+
+#[cfg(unix)]
+fn serve_unix(args: &Args, root: &Path, path: &Path) -> anyhow::Result<()> {
+	use std::os::unix::{
+		fs::{DirBuilderExt, FileTypeExt, PermissionsExt},
+		net::UnixListener,
+	};
+
+	// Left behind by a previous run
+	if fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+		fs::remove_file(path)?;
+	}
+
+	// Bound in a folder only this user can enter and moved into place once only they can connect, the socket needs
+	// no token
+	let mut private = path.as_os_str().to_owned();
+	private.push(".partial");
+	let private = PathBuf::from(private);
+	let socket = private.join("socket");
+
+	// Left behind by a crash. One of someone else can't be removed, and isn't used
+	_ = fs::remove_file(&socket);
+	_ = fs::remove_dir(&private);
+	fs::DirBuilder::new().mode(0o700).create(&private)?;
+
+	let bound = UnixListener::bind(&socket).and_then(|listener| {
+		fs::set_permissions(&socket, fs::Permissions::from_mode(0o600))?;
+		fs::rename(&socket, path)?;
+		Ok(listener)
+	});
+	_ = fs::remove_file(&socket);
+	_ = fs::remove_dir(&private);
+
+	let listener = bound.map_err(|e| anyhow::anyhow!("listening on \"{}\": {e}", path.display()))?;
+	info!("Serving \"{}\" on \"{}\"", root.display(), path.display());
+
+	for stream in listener.incoming().flatten() {
+		_ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+		handle(args, root, stream);
+	}
+
+	Ok(())
+}
+
+#[cfg(windows)]
+fn serve_unix(_: &Args, _: &Path, _: &Path) -> anyhow::Result<()> {
+	anyhow::bail!("Unix sockets are not supported on Windows")
+}
+
+/// Answers the requests of `stream` in a thread of its own
+fn handle(args: &Args, root: &Path, stream: impl Connection) {
+	let (args, root) = (args.clone(), root.to_path_buf());
+
+	std::thread::spawn(move || {
+		_ = stream.set_write_timeout(Some(Duration::from_secs(30)));
+		_ = respond(&args, &root, stream);
+	});
+}
+
+/// Whether a request with the `authorization` header and the `query` may be answered, with --serve-token or
+/// --serve-login it needs them
+fn authorized(args: &Args, authorization: Option<&str>, query: &str) -> bool {
+	// Compares all of it, how long it takes tells nothing of where the first wrong character is
+	let same = |a: &str, b: &str| a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |d, (a, b)| d | (a ^ b)) == 0;
+	let credentials = authorization.and_then(|a| a.split_once(' '));
+
+	let token = args.serve_token.as_deref().is_some_and(|token| {
+		let given = match credentials {
+			Some((scheme, given)) if scheme.eq_ignore_ascii_case("bearer") => Some(given.trim().to_owned()),
+			_ => query.split('&').find_map(|pair| percent_decode(pair.strip_prefix("token=")?)),
+		};
+
+		given.is_some_and(|given| same(&given, token))
+	});
+
+	let login = args.serve_login.as_deref().is_some_and(|login| match credentials {
+		Some((scheme, given)) if scheme.eq_ignore_ascii_case("basic") => same(given.trim(), &base64(login.as_bytes())),
+		_ => false,
+	});
+
+	token || login || (args.serve_token.is_none() && args.serve_login.is_none())
+}
+
+/// Id of `file` in the URLs of the API, its path in the tree with each folder percent encoded
+fn id(root: &Path, file: &Path) -> String {
+	let relative = file.strip_prefix(root).unwrap_or(file);
+	let parts: Vec<_> = relative.iter().map(|part| percent_encode(&part.to_string_lossy())).collect();
+
+	parts.join("/")
+}
+
+/// The file of the tree `id` is of, if it is one. Folders going up and files of this program aren't
+fn file_of(args: &Args, root: &Path, id: &str) -> Option<PathBuf> {
+	let relative: PathBuf = id.split('/').map(percent_decode).collect::<Option<_>>()?;
+
+	if !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+		return None;
+	}
+
+	let file = root.join(relative);
+	(file.is_file() && changes::in_tree(args, root, &file)).then_some(file)
+}
+
+fn percent_decode(s: &str) -> Option<String> {
+	let mut bytes = vec![];
+	let mut rest = s.as_bytes();
+
+	while let Some((&b, after)) = rest.split_first() {
+		rest = after;
+
+		if b != b'%' {
+			bytes.push(b);
+			continue;
+		}
+
+		let hex = std::str::from_utf8(rest.get(..2)?).ok()?;
+		bytes.push(u8::from_str_radix(hex, 16).ok()?);
+		rest = &rest[2..];
+	}
+
+	String::from_utf8(bytes).ok()
+}
+
+/// `file` as a JSON object, with the URL of its image
+fn describe(args: &Args, root: &Path, file: &Path) -> io::Result<String> {
+	let id = id(root, file);
+	let date = file.parent().and_then(|d| args.layout.folder_date(d.strip_prefix(root).ok()?));
+	let date = date.map_or("null".to_owned(), |(date, _)| json::string(&date.to_string()));
+	let metadata = file.metadata()?;
+	let modified = metadata.modified()?.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+	let thumbnail = match metadata::image_type(file)? {
+		Some(_) => json::string(&format!("/api/thumb/{id}")),
+		None => "null".to_owned(),
+	};
+
+	Ok(format!(
+		"{{\"id\": {}, \"path\": {}, \"date\": {date}, \"size\": {}, \"modified\": {modified}, \"url\": {}, \
+		 \"thumbnail\": {thumbnail}}}",
+		json::string(&id),
+		json::string(&file.to_string_lossy()),
+		metadata.len(),
+		json::string(&format!("/api/file/{id}"))
+	))
+}
+
+/// The screenshots of the day `date`, oldest first
+fn day_files(args: &Args, root: &Path, date: Date) -> anyhow::Result<Vec<PathBuf>> {
+	let mut files: Vec<_> = organized_files(args, root)?
+		.into_iter()
+		.filter(|file| {
+			let dir = file.parent().and_then(|d| d.strip_prefix(root).ok());
+			dir.and_then(|d| args.layout.folder_date(d)).is_some_and(|(day, _)| day == date)
+		})
+		.filter_map(|file| Some((file.metadata().and_then(|m| m.modified()).ok()?, file)))
+		.collect();
+
+	files.sort();
+
+	Ok(files.into_iter().map(|(_, file)| file).collect())
+}
+
+/// Subscribes to the events of the watcher on the Unix socket at `socket`, and tells the WebSocket clients about
+/// every file it sorts into the tree in `root`. Again whenever the watcher goes away
+#[cfg(unix)]
+fn follow(args: &Args, root: &Path, socket: &Path) {
+	use std::os::unix::net::UnixStream;
+
+	let mut reported = false;
+
+	loop {
+		let stream = match UnixStream::connect(socket) {
+			Ok(stream) => stream,
+			Err(e) => {
+				if !reported {
+					let message = format!("subscribing to \"{}\": {e}, trying again until it works", socket.display());
+					Event::Error { path: None, message }.emit();
+					reported = true;
+				}
+
+				std::thread::sleep(RESUBSCRIBE);
+				continue;
+			}
+		};
+
+		info!("Following the watcher on \"{}\"", socket.display());
+		reported = false;
+
+		for line in BufReader::new(stream).lines().map_while(Result::ok) {
+			let event = json::parse(&line);
+			let to = event.as_ref().and_then(|e| e.get("to")?.as_str().map(PathBuf::from));
+
+			let Some(file) = to.filter(|to| to.starts_with(root)) else {
+				continue;
+			};
+
+			if let Ok(description) = describe(args, root, &file) {
+				let frame = websocket::text_frame(&format!("{{\"event\": \"sorted\", \"file\": {description}}}"));
+				// Clients that went away are dropped
+				CLIENTS.lock().unwrap().retain_mut(|client| client.write_all(&frame).is_ok());
+			}
+		}
+
+		info!("The watcher on \"{}\" went away", socket.display());
+		std::thread::sleep(RESUBSCRIBE);
+	}
+}
+
+#[cfg(windows)]
+fn follow(_: &Args, _: &Path, _: &Path) {
+	Event::Error { path: None, message: "event sockets need Unix domain sockets".to_owned() }.emit();
+}
+
+/// Headers of a request, with their names in lowercase
+type Headers = Vec<(String, String)>;
+
+/// Request line and headers of a request. `None` if they are longer than [`MAX_LINE`] or more than [`MAX_HEADERS`],
+/// a client can't make it hold on to more than that
+fn read_head(stream: &mut impl Read) -> io::Result<Option<(String, Headers)>> {
+	let mut reader = BufReader::new(stream);
+	// Whether the line read was cut short
+	let mut too_long = |line: &mut String| {
+		let n = (&mut reader).take(MAX_LINE).read_line(line)?;
+		Ok::<_, io::Error>(n as u64 == MAX_LINE && !line.ends_with('\n'))
+	};
+
+	let mut request = String::new();
+
+	if too_long(&mut request)? {
+		return Ok(None);
+	}
+
+	// Bodies are of no interest
+	let mut headers = vec![];
+
+	loop {
+		let mut line = String::new();
+
+		if too_long(&mut line)? || headers.len() == MAX_HEADERS {
+			return Ok(None);
+		}
+
+		if line.trim().is_empty() {
+			break;
+		}
+
+		if let Some((name, value)) = line.split_once(':') {
+			headers.push((name.trim().to_lowercase(), value.trim().to_owned()));
+		}
+	}
+
+	Ok(Some((request, headers)))
+}
+
+fn respond(args: &Args, root: &Path, mut stream: impl Connection) -> anyhow::Result<()> {
+	let error = |status: &str, message: &str| (status.to_owned(), format!("{{\"error\": {}}}", json::string(message)));
+
+	let Some((request, headers)) = read_head(&mut stream)? else {
+		let (status, body) = error("431 Request Header Fields Too Large", "the request is too large");
+		write!(
+			stream,
+			"HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+			 Connection: close\r\n\r\n{body}",
+			body.len()
+		)?;
+
+		return Ok(());
+	};
+
+	let header = |name: &str| headers.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str());
+
+	// "GET /api/latest HTTP/1.1"
+	let mut parts = request.split_whitespace();
+	let (method, target) = (parts.next(), parts.next().unwrap_or_default());
+	let (route, query) = target.split_once('?').unwrap_or((target, ""));
+
+	if !authorized(args, header("authorization"), query) {
+		// Browsers ask for a login when told to
+		let scheme = if args.serve_login.is_some() { "Basic realm=\"screenshot-manager\"" } else { "Bearer" };
+		let (status, body) = error("401 Unauthorized", "this needs the token or the login of the server");
+
+		write!(
+			stream,
+			"HTTP/1.1 {status}\r\nWWW-Authenticate: {scheme}\r\nContent-Type: application/json\r\n\
+			 Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+			body.len()
+		)?;
+
+		return Ok(());
+	}
+
+	let (status, body) = match (method, route) {
+		(Some("GET"), "/api/latest") => match newest_files(args, root)?.first() {
+			Some(file) => ("200 OK".to_owned(), describe(args, root, file)?),
+			None => error("404 Not Found", "there are no screenshots"),
+		},
+		(Some("GET"), route) if route.starts_with("/api/days/") => match Date::parse(&route["/api/days/".len()..]) {
+			Ok(date) => {
+				let files: Vec<_> =
+					day_files(args, root, date)?.iter().map(|f| describe(args, root, f)).collect::<Result<_, _>>()?;
+				("200 OK".to_owned(), format!("[{}]", files.join(", ")))
+			}
+			Err(e) => error("400 Bad Request", &e),
+		},
+		(Some("GET"), route) if route.starts_with("/api/file/") => {
+			match file_of(args, root, &route["/api/file/".len()..]) {
+				Some(file) => return send_file(&mut stream, &file),
+				None => error("404 Not Found", "there is no such screenshot"),
+			}
+		}
+		(Some("GET"), route) if route.starts_with("/api/thumb/") => {
+			let file = file_of(args, root, &route["/api/thumb/".len()..]);
+
+			match file.filter(|f| metadata::image_type(f).ok().flatten().is_some()) {
+				Some(file) => return send_file(&mut stream, &thumbs::current(root, &file)?),
+				None => error("404 Not Found", "there is no such image"),
+			}
+		}
+		(Some("GET"), "/ws") if args.events_socket.is_none() => {
+			error("503 Service Unavailable", "live events need the --events-socket of the watcher")
+		}
+		(Some("GET"), "/ws") => match header("sec-websocket-key") {
+			Some(key) if header("upgrade").is_some_and(|u| u.eq_ignore_ascii_case("websocket")) => {
+				write!(
+					stream,
+					"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+					 Sec-WebSocket-Accept: {}\r\n\r\n",
+					websocket::accept_key(key)
+				)?;
+
+				// Nothing is read from clients, one that stops reading must not hold up the others
+				stream.set_write_timeout(Some(Duration::from_secs(1)))?;
+				CLIENTS.lock().unwrap().push(Box::new(stream));
+				return Ok(());
+			}
+			_ => error("400 Bad Request", "this is a WebSocket"),
+		},
+		(Some("GET"), _) => error("404 Not Found", "not found"),
+		_ => error("405 Method Not Allowed", "method not allowed"),
+	};
+
+	write!(
+		stream,
+		"HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nCache-Control: no-cache\r\n\
+		 Connection: close\r\n\r\n{body}",
+		body.len()
+	)?;
+
+	Ok(())
+}
+
+/// Streams `file` to the client, as the kind of image its contents are
+fn send_file(stream: &mut impl Write, file: &Path) -> anyhow::Result<()> {
+	let content_type = match metadata::image_type(file)? {
+		Some("png") => "image/png",
+		Some("jpg") => "image/jpeg",
+		Some("webp") => "image/webp",
+		Some("gif") => "image/gif",
+		_ => "application/octet-stream",
+	};
+
+	let mut f = fs::File::open(file)?;
+	let len = f.metadata()?.len();
+
+	write!(
+		stream,
+		"HTTP/1.1 200 OK\r\nContent-Type: {content_type}\r\nContent-Length: {len}\r\nConnection: close\r\n\r\n"
+	)?;
+	io::copy(&mut f, stream)?;
+
+	Ok(())
+}
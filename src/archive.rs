@@ -0,0 +1,82 @@
+//! Archives of folders of the tree, packed with tar and encrypted with age or rage
+
+use std::{
+	fs, io,
+	path::{Path, PathBuf},
+	process::{Command, Stdio},
+};
+
+/// Packs `dir` of the tree in `root` into `file`, encrypted to the recipients and recipient files given
+pub fn write(root: &Path, dir: &Path, file: &Path, recipients: Option<(&[String], &[PathBuf])>) -> anyhow::Result<()> {
+	let mut name = std::ffi::OsString::from(".");
+	name.push(file.file_name().unwrap_or_default());
+	name.push(".partial");
+	let temp = file.with_file_name(name);
+
+	// Looked for before tar is started, which would be left behind
+	let program = recipients.map(|_| encryptor()).transpose()?;
+
+	let res = (|| -> anyhow::Result<()> {
+		let mut tar = Command::new("tar")
+			.arg("-cf")
+			.arg("-")
+			.arg("-C")
+			.arg(root)
+			.arg(dir)
+			.stdout(Stdio::piped())
+			.spawn()
+			.map_err(|e| anyhow::anyhow!("running tar: {e}"))?;
+		let mut packed = tar.stdout.take().unwrap(); // Piped above
+
+		let encrypted = match recipients.zip(program) {
+			Some(((recipients, files), program)) => {
+				let mut age = Command::new(program);
+
+				for recipient in recipients {
+					age.arg("-r").arg(recipient);
+				}
+
+				for file in files {
+					age.arg("-R").arg(file);
+				}
+
+				Some((program, age.arg("-o").arg(&temp).stdin(packed).status()?))
+			}
+			None => {
+				io::copy(&mut packed, &mut fs::File::create(&temp)?)?;
+				None
+			}
+		};
+
+		let status = tar.wait()?;
+
+		if !status.success() {
+			anyhow::bail!("tar failed: {status}");
+		}
+
+		if let Some((program, status)) = encrypted.filter(|(_, status)| !status.success()) {
+			anyhow::bail!("{program} failed: {status}");
+		}
+
+		fs::File::open(&temp)?.sync_all()?;
+		fs::rename(&temp, file)?;
+
+		Ok(())
+	})();
+
+	if res.is_err() {
+		_ = fs::remove_file(&temp);
+	}
+
+	res
+}
+
+/// The age implementation there is, rage is a drop-in replacement
+fn encryptor() -> anyhow::Result<&'static str> {
+	["age", "rage"]
+		.into_iter()
+		.find(|program| {
+			Command::new(program).arg("--version").stdout(Stdio::null()).stderr(Stdio::null()).status().is_ok()
+		})
+		.ok_or_else(|| anyhow::anyhow!("encrypting needs age or rage installed"))
+}
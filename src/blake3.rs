@@ -0,0 +1,267 @@
+//! BLAKE3 hashing, after the reference implementation. Only plain hashing with 32 byte output is needed
+
+use std::io::{self, Read};
+
+const BLOCK_LEN: usize = 64;
+const CHUNK_LEN: usize = 1024;
+
+const CHUNK_START: u32 = 1 << 0;
+const CHUNK_END: u32 = 1 << 1;
+const PARENT: u32 = 1 << 2;
+const ROOT: u32 = 1 << 3;
+
+const IV: [u32; 8] = [0x6A09E667, 0xBB67AE85, 0x3C6EF372, 0xA54FF53A, 0x510E527F, 0x9B05688C, 0x1F83D9AB, 0x5BE0CD19];
+
+const MSG_PERMUTATION: [usize; 16] = [2, 6, 3, 10, 7, 0, 4, 13, 1, 11, 12, 5, 9, 14, 15, 8];
+
+fn g(state: &mut [u32; 16], (a, b, c, d): (usize, usize, usize, usize), mx: u32, my: u32) {
+	state[a] = state[a].wrapping_add(state[b]).wrapping_add(mx);
+	state[d] = (state[d] ^ state[a]).rotate_right(16);
+	state[c] = state[c].wrapping_add(state[d]);
+	state[b] = (state[b] ^ state[c]).rotate_right(12);
+	state[a] = state[a].wrapping_add(state[b]).wrapping_add(my);
+	state[d] = (state[d] ^ state[a]).rotate_right(8);
+	state[c] = state[c].wrapping_add(state[d]);
+	state[b] = (state[b] ^ state[c]).rotate_right(7);
+}
+
+fn round(state: &mut [u32; 16], m: &[u32; 16]) {
+	// Columns, then diagonals
+	g(state, (0, 4, 8, 12), m[0], m[1]);
+	g(state, (1, 5, 9, 13), m[2], m[3]);
+	g(state, (2, 6, 10, 14), m[4], m[5]);
+	g(state, (3, 7, 11, 15), m[6], m[7]);
+	g(state, (0, 5, 10, 15), m[8], m[9]);
+	g(state, (1, 6, 11, 12), m[10], m[11]);
+	g(state, (2, 7, 8, 13), m[12], m[13]);
+	g(state, (3, 4, 9, 14), m[14], m[15]);
+}
+
+fn compress(chaining_value: &[u32; 8], block: &[u32; 16], counter: u64, block_len: u32, flags: u32) -> [u32; 16] {
+	let mut state = [
+		chaining_value[0],
+		chaining_value[1],
+		chaining_value[2],
+		chaining_value[3],
+		chaining_value[4],
+		chaining_value[5],
+		chaining_value[6],
+		chaining_value[7],
+		IV[0],
+		IV[1],
+		IV[2],
+		IV[3],
+		counter as u32,
+		(counter >> 32) as u32,
+		block_len,
+		flags,
+	];
+	let mut block = *block;
+
+	for i in 0..7 {
+		round(&mut state, &block);
+
+		if i < 6 {
+			block = MSG_PERMUTATION.map(|j| block[j]);
+		}
+	}
+
+	for i in 0..8 {
+		state[i] ^= state[i + 8];
+		state[i + 8] ^= chaining_value[i];
+	}
+
+	state
+}
+
+fn first_8_words(words: [u32; 16]) -> [u32; 8] {
+	words[..8].try_into().unwrap() // Half of 16
+}
+
+fn words(bytes: &[u8; BLOCK_LEN]) -> [u32; 16] {
+	// Slices of four bytes
+	std::array::from_fn(|i| u32::from_le_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap()))
+}
+
+/// What is needed to compress a node, a chunk or a parent, once it's known whether it's the root
+struct Output {
+	chaining_value: [u32; 8],
+	block: [u32; 16],
+	counter: u64,
+	block_len: u32,
+	flags: u32,
+}
+
+impl Output {
+	fn chaining_value(&self) -> [u32; 8] {
+		first_8_words(compress(&self.chaining_value, &self.block, self.counter, self.block_len, self.flags))
+	}
+
+	fn root_hash(&self) -> [u8; 32] {
+		let words = compress(&self.chaining_value, &self.block, 0, self.block_len, self.flags | ROOT);
+		let mut hash = [0; 32];
+
+		for (word, bytes) in words.iter().zip(hash.chunks_mut(4)) {
+			bytes.copy_from_slice(&word.to_le_bytes());
+		}
+
+		hash
+	}
+}
+
+struct ChunkState {
+	chaining_value: [u32; 8],
+	counter: u64,
+	block: [u8; BLOCK_LEN],
+	block_len: usize,
+	blocks_compressed: usize,
+}
+
+impl ChunkState {
+	fn new(counter: u64) -> ChunkState {
+		ChunkState { chaining_value: IV, counter, block: [0; BLOCK_LEN], block_len: 0, blocks_compressed: 0 }
+	}
+
+	fn len(&self) -> usize {
+		BLOCK_LEN * self.blocks_compressed + self.block_len
+	}
+
+	fn start_flag(&self) -> u32 {
+		if self.blocks_compressed == 0 {
+			CHUNK_START
+		} else {
+			0
+		}
+	}
+
+	fn update(&mut self, mut input: &[u8]) {
+		while !input.is_empty() {
+			// The last block is compressed differently, a full one waits for more input to be sure it's not it
+			if self.block_len == BLOCK_LEN {
+				let block = words(&self.block);
+				let flags = self.start_flag();
+				self.chaining_value =
+					first_8_words(compress(&self.chaining_value, &block, self.counter, BLOCK_LEN as u32, flags));
+				self.blocks_compressed += 1;
+				self.block = [0; BLOCK_LEN];
+				self.block_len = 0;
+			}
+
+			let take = (BLOCK_LEN - self.block_len).min(input.len());
+			self.block[self.block_len..self.block_len + take].copy_from_slice(&input[..take]);
+			self.block_len += take;
+			input = &input[take..];
+		}
+	}
+
+	fn output(&self) -> Output {
+		Output {
+			chaining_value: self.chaining_value,
+			block: words(&self.block),
+			counter: self.counter,
+			block_len: self.block_len as u32,
+			flags: self.start_flag() | CHUNK_END,
+		}
+	}
+}
+
+fn parent_output(left: [u32; 8], right: [u32; 8]) -> Output {
+	let mut block = [0; 16];
+	block[..8].copy_from_slice(&left);
+	block[8..].copy_from_slice(&right);
+
+	Output { chaining_value: IV, block, counter: 0, block_len: BLOCK_LEN as u32, flags: PARENT }
+}
+
+#[derive(Default)]
+struct Hasher {
+	chunk: Option<ChunkState>,
+	/// Chaining values of complete subtrees still waiting for a sibling, largest first
+	stack: Vec<[u32; 8]>,
+}
+
+impl Hasher {
+	fn update(&mut self, mut input: &[u8]) {
+		while !input.is_empty() {
+			let chunk = self.chunk.get_or_insert_with(|| ChunkState::new(0));
+
+			if chunk.len() == CHUNK_LEN {
+				let mut chaining_value = chunk.output().chaining_value();
+				let mut total_chunks = chunk.counter + 1;
+
+				// Each trailing zero bit of the count is a subtree that is complete now
+				while total_chunks & 1 == 0 {
+					chaining_value = parent_output(self.stack.pop().unwrap(), chaining_value).chaining_value();
+					total_chunks >>= 1;
+				}
+
+				self.stack.push(chaining_value);
+				*chunk = ChunkState::new(chunk.counter + 1);
+			}
+
+			let take = (CHUNK_LEN - chunk.len()).min(input.len());
+			chunk.update(&input[..take]);
+			input = &input[take..];
+		}
+	}
+
+	fn finalize(&self) -> [u8; 32] {
+		let mut output = self.chunk.as_ref().unwrap_or(&ChunkState::new(0)).output();
+
+		for &left in self.stack.iter().rev() {
+			output = parent_output(left, output.chaining_value());
+		}
+
+		output.root_hash()
+	}
+}
+
+/// Hash of everything read from `reader`, as lowercase hex
+pub fn hash(mut reader: impl Read) -> io::Result<String> {
+	let mut hasher = Hasher::default();
+	let mut buf = vec![0; 64 * 1024];
+
+	loop {
+		match reader.read(&mut buf) {
+			Ok(0) => break,
+			Ok(n) => hasher.update(&buf[..n]),
+			Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+			Err(e) => return Err(e),
+		}
+	}
+
+	Ok(hasher.finalize().iter().map(|b| format!("{b:02x}")).collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Hash of the official test input of `len` bytes, which repeat 0 to 250
+	fn of_len(len: usize) -> String {
+		let input: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+		hash(&input[..]).unwrap()
+	}
+
+	// From test_vectors.json of the BLAKE3 repository, the first 32 bytes of each hash
+	#[test]
+	fn official_vectors() {
+		let vectors = [
+			(0, "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"),
+			(1, "2d3adedff11b61f14c886e35afa036736dcd87a74d27b5c1510225d0f592e213"),
+			(1023, "10108970eeda3eb932baac1428c7a2163b0e924c9a9e25b35bba72b28f70bd11"),
+			(1024, "42214739f095a406f3fc83deb889744ac00df831c10daa55189b5d121c855af7"),
+			(1025, "d00278ae47eb27b34faecf67b4fe263f82d5412916c1ffd97c8cb7fb814b8444"),
+			(2048, "e776b6028c7cd22a4d0ba182a8bf62205d2ef576467e838ed6f2529b85fba24a"),
+			(2049, "5f4d72f40d7a5f82b15ca2b2e44b1de3c2ef86c426c95c1af0b6879522563030"),
+			(3072, "b98cb0ff3623be03326b373de6b9095218513e64f1ee2edd2525c7ad1e5cffd2"),
+			(8192, "aae792484c8efe4f19e2ca7d371d8c467ffb10748d8a5a1ae579948f718a2a63"),
+			(31744, "62b6960e1a44bcc1eb1a611a8d6235b6b4b78f32e7abc4fb4c6cdcce94895c47"),
+			(102400, "bc3e3d41a1146b069abffad3c0d44860cf664390afce4d9661f7902e7943e085"),
+		];
+
+		for (len, expected) in vectors {
+			assert_eq!(of_len(len), expected, "{len} bytes");
+		}
+	}
+}
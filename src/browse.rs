@@ -0,0 +1,462 @@
+//! A terminal browser for the organized tree, drawn with plain escape codes on a raw terminal
+
+use std::{
+	fs,
+	io::{self, Write},
+	path::{Path, PathBuf},
+	time::UNIX_EPOCH,
+};
+
+use crate::{
+	commands::{is_screenshot, organized_dirs, tag, trash_file},
+	platform, tags, update_latest, windows, Args, TagAction,
+};
+
+const HELP: &str = "↑↓ move  → open  ← back  s star  t tag  d delete  c copy path  q quit";
+
+/// Lines below the list: the details of the selected entry and the help or a message
+const FOOTER: usize = 4;
+
+pub fn run(args: &Args, path: &Path) -> anyhow::Result<()> {
+	let terminal = Terminal::new()?;
+	let mut browser =
+		Browser { args, root: path, dir: path.to_path_buf(), entries: vec![], selected: 0, message: None };
+	browser.load()?;
+
+	loop {
+		browser.draw(terminal.size())?;
+
+		let res = match read_key()? {
+			Key::Char('q') => break,
+			Key::Up | Key::Char('k') => {
+				browser.selected = browser.selected.saturating_sub(1);
+				Ok(())
+			}
+			Key::Down | Key::Char('j') => {
+				browser.selected = (browser.selected + 1).min(browser.entries.len().saturating_sub(1));
+				Ok(())
+			}
+			Key::Right | Key::Enter | Key::Char('l') => browser.enter(),
+			Key::Left | Key::Backspace | Key::Char('h') => browser.back(),
+			Key::Char('s') => browser.star(),
+			Key::Char('t') => browser.tag(terminal.size()),
+			Key::Char('d') => browser.delete(terminal.size()),
+			Key::Char('c') => browser.copy_path(),
+			_ => Ok(()),
+		};
+
+		if let Err(e) = res {
+			browser.message = Some(format!("Error: {e}"));
+		}
+	}
+
+	Ok(())
+}
+
+struct Browser<'a> {
+	args: &'a Args,
+	root: &'a Path,
+	dir: PathBuf,
+	entries: Vec<PathBuf>,
+	selected: usize,
+	/// Shown instead of the help until the next key
+	message: Option<String>,
+}
+
+impl Browser<'_> {
+	/// Lists `dir`, only the folders this program sorts into at the top
+	fn load(&mut self) -> anyhow::Result<()> {
+		self.entries = if self.dir == self.root {
+			organized_dirs(self.args, self.root)?
+		} else {
+			fs::read_dir(&self.dir)?.map(|e| Ok(e?.path())).collect::<io::Result<Vec<_>>>()?
+		};
+
+		self.entries.retain(|e| is_screenshot(e));
+		self.entries.sort();
+		self.selected = self.selected.min(self.entries.len().saturating_sub(1));
+
+		Ok(())
+	}
+
+	fn current(&self) -> Option<&PathBuf> {
+		self.entries.get(self.selected)
+	}
+
+	fn enter(&mut self) -> anyhow::Result<()> {
+		match self.current() {
+			Some(dir) if dir.is_dir() => {
+				self.dir = dir.clone();
+				self.selected = 0;
+				self.load()
+			}
+			_ => Ok(()),
+		}
+	}
+
+	fn back(&mut self) -> anyhow::Result<()> {
+		if self.dir == self.root {
+			return Ok(());
+		}
+
+		let parent = self.dir.parent().unwrap_or(self.root).to_path_buf();
+		let left = std::mem::replace(&mut self.dir, parent);
+		self.load()?;
+		// Back on the folder that was left
+		self.selected = self.entries.iter().position(|e| *e == left).unwrap_or(0);
+
+		Ok(())
+	}
+
+	/// The selected entry if it's a file, only files have tags
+	fn file(&self) -> anyhow::Result<PathBuf> {
+		match self.current() {
+			Some(file) if file.is_file() => Ok(file.clone()),
+			_ => anyhow::bail!("only files can be starred, tagged or deleted"),
+		}
+	}
+
+	fn star(&mut self) -> anyhow::Result<()> {
+		let file = self.file()?;
+		let starred = tags::read(self.root)?.get(&file).is_some_and(|t| t.contains(tags::STAR));
+		let tags = vec![tags::STAR.to_owned()];
+
+		match starred {
+			true => tag(self.args, self.root, &TagAction::Rm { file, tags }),
+			false => tag(self.args, self.root, &TagAction::Add { file, tags }),
+		}
+	}
+
+	fn tag(&mut self, size: (usize, usize)) -> anyhow::Result<()> {
+		let file = self.file()?;
+		let Some(line) = prompt("Tags to add, with a - in front to remove: ", size)? else {
+			return Ok(());
+		};
+
+		let (removed, added): (Vec<_>, Vec<_>) = line.split_whitespace().partition(|t| t.starts_with('-'));
+		let added: Vec<String> = added.into_iter().map(str::to_owned).collect();
+		let removed: Vec<String> = removed.into_iter().map(|t| t[1..].to_owned()).filter(|t| !t.is_empty()).collect();
+
+		if !added.is_empty() {
+			tag(self.args, self.root, &TagAction::Add { file: file.clone(), tags: added })?;
+		}
+
+		if !removed.is_empty() {
+			tag(self.args, self.root, &TagAction::Rm { file, tags: removed })?;
+		}
+
+		Ok(())
+	}
+
+	fn delete(&mut self, size: (usize, usize)) -> anyhow::Result<()> {
+		let file = self.file()?;
+		let name = file.file_name().unwrap_or_default().to_string_lossy().into_owned();
+
+		if prompt(&format!("Move \"{name}\" to the trash? Type y to confirm: "), size)?.as_deref() != Some("y") {
+			return Ok(());
+		}
+
+		trash_file(self.root, &file)?;
+		update_latest(self.args, self.root)?;
+
+		self.message = Some(format!("Moved \"{name}\" to the trash"));
+		self.load()
+	}
+
+	fn copy_path(&mut self) -> anyhow::Result<()> {
+		let Some(path) = self.current() else {
+			return Ok(());
+		};
+		let path = path.to_string_lossy().into_owned();
+
+		// Terminals that support OSC 52 set the clipboard themselves, even over SSH
+		if platform::copy_text(&path).is_err() {
+			print!("\x1b]52;c;{}\x07", base64(path.as_bytes()));
+		}
+
+		self.message = Some(format!("Copied \"{path}\""));
+
+		Ok(())
+	}
+
+	fn tags_of(&self, file: &Path) -> anyhow::Result<Vec<String>> {
+		Ok(tags::read(self.root)?.remove(file).unwrap_or_default().into_iter().collect())
+	}
+
+	/// What there is to know about the selected entry, a line each
+	fn details(&self) -> anyhow::Result<Vec<String>> {
+		let Some(entry) = self.current() else {
+			return Ok(vec!["Nothing here".to_owned()]);
+		};
+
+		let metadata = entry.metadata()?;
+
+		if metadata.is_dir() {
+			let count = fs::read_dir(entry)?.count();
+			return Ok(vec![format!("Folder, {count} entries")]);
+		}
+
+		let modified = metadata.modified()?.duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or_default();
+		let (date, time) = self.args.timezone.wall_clock(modified);
+		let tags = self.tags_of(entry)?;
+		let window = windows::read(self.root)?.remove(entry).unwrap_or_default();
+
+		let mut lines = vec![format!(
+			"{} bytes, modified {date} {:02}:{:02}:{:02}",
+			metadata.len(),
+			time.hour,
+			time.minute,
+			time.second
+		)];
+
+		if !tags.is_empty() {
+			lines.push(format!("Tags: {}", tags.join(", ")));
+		}
+
+		if let Some(title) = window.title {
+			lines.push(format!("Window: {title} on {}", window.workspace.as_deref().unwrap_or("?")));
+		}
+
+		Ok(lines)
+	}
+
+	fn draw(&mut self, (rows, cols): (usize, usize)) -> anyhow::Result<()> {
+		let mut screen = String::from("\x1b[H\x1b[2J");
+		let fit = |s: &str| s.chars().take(cols).collect::<String>();
+
+		let title = self.dir.strip_prefix(self.root).ok().filter(|d| !d.as_os_str().is_empty());
+		let title = title.map_or(self.root.display().to_string(), |d| d.display().to_string());
+		screen.push_str(&format!("\x1b[1m{}\x1b[0m\r\n", fit(&title)));
+
+		// Scrolled so the selection stays in view
+		let height = rows.saturating_sub(FOOTER + 1).max(1);
+		let offset = self.selected.saturating_sub(height - 1);
+		let starred = tags::read(self.root)?;
+
+		for (i, entry) in self.entries.iter().enumerate().skip(offset).take(height) {
+			let mut name = entry.file_name().unwrap_or_default().to_string_lossy().into_owned();
+
+			if entry.is_dir() {
+				name.push('/');
+			}
+
+			let star = if starred.get(entry).is_some_and(|t| t.contains(tags::STAR)) { "★ " } else { "  " };
+			let line = fit(&format!("{star}{name}"));
+
+			match i == self.selected {
+				true => screen.push_str(&format!("\x1b[7m{line}\x1b[0m\r\n")),
+				false => screen.push_str(&format!("{line}\r\n")),
+			}
+		}
+
+		let details = self.details().unwrap_or_else(|e| vec![format!("Error: {e}")]);
+		screen.push_str(&format!("\x1b[{};1H", rows.saturating_sub(FOOTER) + 1));
+
+		for line in details.iter().take(FOOTER - 1) {
+			screen.push_str(&format!("\x1b[2m{}\x1b[0m\r\n", fit(line)));
+		}
+
+		let footer = self.message.take().unwrap_or_else(|| HELP.to_owned());
+		screen.push_str(&format!("\x1b[{rows};1H{}", fit(&footer)));
+
+		let mut stdout = io::stdout();
+		stdout.write_all(screen.as_bytes())?;
+		stdout.flush()?;
+
+		Ok(())
+	}
+}
+
+/// Reads a line typed on the last row, `None` if Escape was pressed
+fn prompt(question: &str, (rows, _): (usize, usize)) -> io::Result<Option<String>> {
+	let mut line = String::new();
+
+	loop {
+		print!("\x1b[{rows};1H\x1b[2K{question}{line}\x1b[?25h");
+		io::stdout().flush()?;
+
+		let key = read_key()?;
+		print!("\x1b[?25l");
+
+		match key {
+			Key::Enter => return Ok(Some(line)),
+			Key::Escape => return Ok(None),
+			Key::Backspace => _ = line.pop(),
+			Key::Char(c) => line.push(c),
+			_ => (),
+		}
+	}
+}
+
+enum Key {
+	Up,
+	Down,
+	Left,
+	Right,
+	Enter,
+	Backspace,
+	Escape,
+	Char(char),
+	Other,
+}
+
+fn read_key() -> io::Result<Key> {
+	Ok(match read_byte()? {
+		b'\r' | b'\n' => Key::Enter,
+		0x7f | 0x08 => Key::Backspace,
+		0x1b => {
+			// Arrows come as "ESC [ A" all at once, Escape by itself has nothing after it
+			if !input_pending()? {
+				return Ok(Key::Escape);
+			}
+
+			match [read_byte()?, read_byte()?] {
+				[b'[', b'A'] => Key::Up,
+				[b'[', b'B'] => Key::Down,
+				[b'[', b'C'] => Key::Right,
+				[b'[', b'D'] => Key::Left,
+				_ => Key::Other,
+			}
+		}
+		first => {
+			// The rest of a UTF-8 character
+			let len = match first {
+				0xf0.. => 4,
+				0xe0.. => 3,
+				0xc0.. => 2,
+				_ => 1,
+			};
+			let mut bytes = vec![first];
+			for _ in 1..len {
+				bytes.push(read_byte()?);
+			}
+
+			match std::str::from_utf8(&bytes).ok().and_then(|s| s.chars().next()) {
+				Some(c) if !c.is_control() => Key::Char(c),
+				_ => Key::Other,
+			}
+		}
+	})
+}
+
+pub fn base64(bytes: &[u8]) -> String {
+	const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+	let mut out = String::new();
+
+	for chunk in bytes.chunks(3) {
+		let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | u32::from(b) << (16 - 8 * i));
+
+		for i in 0..4 {
+			match i <= chunk.len() {
+				true => out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char),
+				false => out.push('='),
+			}
+		}
+	}
+
+	out
+}
+
+/// The terminal in raw mode on the alternate screen, as it was before once dropped
+#[cfg(unix)]
+struct Terminal {
+	original: libc::termios,
+}
+
+#[cfg(unix)]
+impl Terminal {
+	fn new() -> io::Result<Terminal> {
+		// SAFETY: termios is plain old data, all zeroes is a valid value
+		let mut original: libc::termios = unsafe { std::mem::zeroed() };
+
+		// SAFETY: The pointer is valid for the call, tcgetattr doesn't keep it
+		if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+			return Err(io::Error::new(io::ErrorKind::Unsupported, "browse needs a terminal"));
+		}
+
+		let mut raw = original;
+		// SAFETY: As above, for both calls
+		unsafe {
+			libc::cfmakeraw(&mut raw);
+			if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+				return Err(io::Error::last_os_error());
+			}
+		}
+
+		print!("\x1b[?1049h\x1b[?25l");
+
+		Ok(Terminal { original })
+	}
+
+	/// Rows and columns
+	fn size(&self) -> (usize, usize) {
+		// SAFETY: winsize is plain old data, all zeroes is a valid value
+		let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+
+		// SAFETY: TIOCGWINSZ writes a winsize to the pointer and doesn't keep it
+		match unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } {
+			0 if size.ws_row > 0 => (size.ws_row.into(), size.ws_col.into()),
+			_ => (24, 80),
+		}
+	}
+}
+
+#[cfg(unix)]
+impl Drop for Terminal {
+	fn drop(&mut self) {
+		print!("\x1b[?25h\x1b[?1049l");
+		_ = io::stdout().flush();
+
+		// SAFETY: The pointer is valid for the call, tcsetattr doesn't keep it
+		unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original) };
+	}
+}
+
+/// Reads a byte typed, unbuffered so `input_pending` sees what's left
+#[cfg(unix)]
+fn read_byte() -> io::Result<u8> {
+	let mut byte = 0;
+
+	// SAFETY: Reads at most one byte into `byte`
+	match unsafe { libc::read(libc::STDIN_FILENO, (&mut byte as *mut u8).cast(), 1) } {
+		1 => Ok(byte),
+		0 => Err(io::ErrorKind::UnexpectedEof.into()),
+		_ => Err(io::Error::last_os_error()),
+	}
+}
+
+/// Whether more input can be read right away
+#[cfg(unix)]
+fn input_pending() -> io::Result<bool> {
+	let mut fd = libc::pollfd { fd: libc::STDIN_FILENO, events: libc::POLLIN, revents: 0 };
+
+	// SAFETY: One valid pollfd, poll doesn't keep the pointer
+	match unsafe { libc::poll(&mut fd, 1, 30) } {
+		-1 => Err(io::Error::last_os_error()),
+		n => Ok(n > 0),
+	}
+}
+
+#[cfg(windows)]
+struct Terminal;
+
+#[cfg(windows)]
+impl Terminal {
+	fn new() -> io::Result<Terminal> {
+		Err(io::Error::new(io::ErrorKind::Unsupported, "browse needs a Unix terminal"))
+	}
+
+	fn size(&self) -> (usize, usize) {
+		(24, 80)
+	}
+}
+
+#[cfg(windows)]
+fn read_byte() -> io::Result<u8> {
+	Err(io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(windows)]
+fn input_pending() -> io::Result<bool> {
+	Ok(false)
+}
@@ -0,0 +1,50 @@
+//! Finding bursts of screenshots that look alike, like the same window taken again and again while reporting a bug.
+//! Images are told apart by a difference hash of a tiny grayscale copy that ImageMagick makes
+
+use std::{
+	path::{Path, PathBuf},
+	time::{Duration, SystemTime},
+};
+
+use crate::magick;
+
+/// Bits two hashes may differ in for their images to look alike, of 64
+const ALIKE_BITS: u32 = 10;
+
+/// Difference hash of the image `file`: for each pixel of a 9x8 grayscale copy, whether it's brighter than the one
+/// to its right. Resizing and compression change it little
+pub fn fingerprint(file: &Path) -> anyhow::Result<u64> {
+	let pixels = magick::convert(file, &["-resize", "9x8!", "-colorspace", "Gray", "-depth", "8"], "gray:-".as_ref())?;
+
+	if pixels.len() != 9 * 8 {
+		anyhow::bail!("ImageMagick gave {} pixels instead of 72", pixels.len());
+	}
+
+	Ok(pixels.chunks(9).flat_map(|row| row.windows(2).map(|p| p[0] > p[1])).fold(0, |hash, bit| hash << 1 | bit as u64))
+}
+
+/// Groups `files`, with when each was taken and its fingerprint, into bursts of images that look like the one
+/// before and were taken at most `within` after it. Only those of more than one file are returned, oldest first
+pub fn group(mut files: Vec<(SystemTime, u64, PathBuf)>, within: Duration) -> Vec<Vec<PathBuf>> {
+	files.sort();
+
+	let mut bursts: Vec<Vec<PathBuf>> = vec![];
+	let mut last: Option<(SystemTime, u64)> = None;
+
+	for (taken, hash, file) in files {
+		let alike = last.is_some_and(|(time, previous)| {
+			taken.duration_since(time).unwrap_or_default() <= within && (hash ^ previous).count_ones() <= ALIKE_BITS
+		});
+
+		match bursts.last_mut() {
+			Some(burst) if alike => burst.push(file),
+			_ => bursts.push(vec![file]),
+		}
+
+		last = Some((taken, hash));
+	}
+
+	bursts.retain(|b| b.len() > 1);
+
+	bursts
+}
@@ -0,0 +1,178 @@
+//! Taking screenshots, through the XDG Desktop Portal or grim and slurp on wlroots compositors, and getting them
+//! off the clipboard
+
+use std::{
+	io::{BufRead, BufReader},
+	path::{Path, PathBuf},
+	process::{Command, Stdio},
+};
+
+const PORTAL: &str = "org.freedesktop.portal.Desktop";
+
+/// Asks the portal for a screenshot and returns the file it saved. `interactive` lets the user pick what to take,
+/// and confirm. Talks D-Bus through gdbus, which comes with GLib
+pub fn portal(interactive: bool) -> anyhow::Result<PathBuf> {
+	let token = format!("screenshot_manager_{}", std::process::id());
+
+	// The answer comes as a signal, listening has to start before asking
+	let mut monitor = Command::new("gdbus")
+		.args(["monitor", "--session", "--dest", PORTAL])
+		.stdout(Stdio::piped())
+		.spawn()
+		.map_err(|e| anyhow::anyhow!("running gdbus: {e}"))?;
+
+	let res = (|| -> anyhow::Result<PathBuf> {
+		let mut lines = BufReader::new(monitor.stdout.take().unwrap()).lines(); // Piped above
+
+		// Its first line tells it's listening
+		lines.next().transpose()?.ok_or_else(|| anyhow::anyhow!("gdbus monitor quit"))?;
+
+		let options = format!("{{'handle_token': <'{token}'>, 'interactive': <{interactive}>}}");
+		let call = Command::new("gdbus")
+			.args(["call", "--session", "--dest", PORTAL, "--object-path", "/org/freedesktop/portal/desktop"])
+			.args(["--method", "org.freedesktop.portal.Screenshot.Screenshot", "", &options])
+			.stdout(Stdio::null())
+			.output()?;
+
+		if !call.status.success() {
+			anyhow::bail!("asking the portal for a screenshot: {}", String::from_utf8_lossy(&call.stderr).trim());
+		}
+
+		// "/org/freedesktop/portal/desktop/request/1_42/<token>: org.freedesktop.portal.Request.Response
+		// (uint32 0, {'uri': <'file:///home/user/Pictures/Screenshot.png'>})"
+		let response = format!("/{token}: org.freedesktop.portal.Request.Response");
+
+		for line in lines {
+			let line = line?;
+
+			if let Some(rest) = line.split_once(&response).map(|(_, rest)| rest) {
+				return response_file(rest);
+			}
+		}
+
+		anyhow::bail!("gdbus monitor quit before the portal answered")
+	})();
+
+	_ = monitor.kill();
+	_ = monitor.wait();
+
+	res
+}
+
+/// Takes a screenshot into `file` with grim, of a region picked with slurp or of one output. The whole screen
+/// without either
+pub fn grim(file: &Path, region: bool, output: Option<&str>) -> anyhow::Result<()> {
+	let mut grim = Command::new("grim");
+
+	if region {
+		let slurp = Command::new("slurp")
+			.stderr(Stdio::inherit())
+			.output()
+			.map_err(|e| anyhow::anyhow!("running slurp: {e}"))?;
+
+		if !slurp.status.success() {
+			anyhow::bail!("no region was selected");
+		}
+
+		grim.arg("-g").arg(String::from_utf8_lossy(&slurp.stdout).trim());
+	}
+
+	if let Some(output) = output {
+		grim.args(["-o", output]);
+	}
+
+	let status = grim.arg(file).status().map_err(|e| anyhow::anyhow!("running grim: {e}"))?;
+
+	if !status.success() {
+		anyhow::bail!("grim failed: {status}");
+	}
+
+	Ok(())
+}
+
+/// Image types to take off the clipboard, by preference, with their extensions
+const CLIPBOARD_TYPES: &[(&str, &str)] =
+	&[("image/png", "png"), ("image/jpeg", "jpg"), ("image/webp", "webp"), ("image/gif", "gif"), ("image/bmp", "bmp")];
+
+/// Writes the image on the clipboard next to `stem`, named like it with the extension of its type
+pub fn clipboard(stem: &Path) -> anyhow::Result<PathBuf> {
+	let wayland = std::env::var_os("WAYLAND_DISPLAY").is_some();
+	let (program, list, get): (_, &[&str], &[&str]) = if wayland {
+		("wl-paste", &["--list-types"], &["--no-newline", "--type"])
+	} else {
+		(
+			"xclip",
+			&["-selection", "clipboard", "-target", "TARGETS", "-out"],
+			&["-selection", "clipboard", "-out", "-target"],
+		)
+	};
+
+	let run = |args: &[&str]| -> anyhow::Result<Vec<u8>> {
+		let output =
+			Command::new(program).args(args).output().map_err(|e| anyhow::anyhow!("running {program}: {e}"))?;
+
+		if !output.status.success() {
+			anyhow::bail!("{program} failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+		}
+
+		Ok(output.stdout)
+	};
+
+	let types = String::from_utf8_lossy(&run(list)?).into_owned();
+	let offered: Vec<_> = types.lines().map(str::trim).collect();
+	let (kind, ext) = CLIPBOARD_TYPES
+		.iter()
+		.find(|(kind, _)| offered.contains(kind))
+		.ok_or_else(|| anyhow::anyhow!("there is no image on the clipboard"))?;
+
+	let image = run(&[get, &[kind]].concat())?;
+	let mut name = stem.file_name().unwrap_or_default().to_owned();
+	name.push(format!(".{ext}"));
+	let file = stem.with_file_name(name);
+	std::fs::write(&file, image)?;
+
+	Ok(file)
+}
+
+/// File in the arguments of a Response signal
+fn response_file(args: &str) -> anyhow::Result<PathBuf> {
+	match args.trim_start().strip_prefix("(uint32 ").and_then(|s| s.chars().next()) {
+		Some('0') => (),
+		Some('1') => anyhow::bail!("the screenshot was cancelled"),
+		_ => anyhow::bail!("the portal couldn't take a screenshot"),
+	}
+
+	let uri = args
+		.split_once("'uri': <'")
+		.and_then(|(_, rest)| rest.split_once("'>"))
+		.map(|(uri, _)| uri)
+		.ok_or_else(|| anyhow::anyhow!("the portal answered without a file"))?;
+
+	let path = uri.strip_prefix("file://").ok_or_else(|| anyhow::anyhow!("\"{uri}\" is not a local file"))?;
+
+	Ok(PathBuf::from(percent_decode(path)))
+}
+
+/// `s` with its %XX escapes replaced by the bytes they stand for
+fn percent_decode(s: &str) -> String {
+	let bytes = s.as_bytes();
+	let mut out = Vec::with_capacity(bytes.len());
+	let mut i = 0;
+
+	while i < bytes.len() {
+		let escaped = bytes.get(i + 1..i + 3).and_then(|h| u8::from_str_radix(std::str::from_utf8(h).ok()?, 16).ok());
+
+		match (bytes[i], escaped) {
+			(b'%', Some(byte)) => {
+				out.push(byte);
+				i += 3;
+			}
+			(byte, _) => {
+				out.push(byte);
+				i += 1;
+			}
+		}
+	}
+
+	String::from_utf8_lossy(&out).into_owned()
+}
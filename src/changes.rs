@@ -0,0 +1,186 @@
+//! Keeping what is recorded about the tree in step with files removed and renamed in it by other programs, like
+//! a file manager
+
+use std::{
+	collections::{BTreeMap, HashMap},
+	fs, io,
+	path::{Path, PathBuf},
+	time::UNIX_EPOCH,
+};
+
+use crate::{
+	checksums, commands,
+	events::info,
+	is_temporary,
+	journal::{escape, unescape},
+	metrics, sidecar, tags, windows, Args, STATE_DIR,
+};
+
+/// Size and modification time of a file of the tree, what it is told apart by between runs without reading it
+type Stamp = (u64, u64);
+
+/// `path` moved from below `from` to below `to`, `None` if it wasn't below `from`
+pub fn rebase(path: &Path, from: &Path, to: &Path) -> Option<PathBuf> {
+	match path.strip_prefix(from).ok()? {
+		rest if rest.as_os_str().is_empty() => Some(to.to_path_buf()),
+		rest => Some(to.join(rest)),
+	}
+}
+
+/// Whether `path` is a screenshot or folder of the tree in `root`, and not something this program keeps there
+pub fn in_tree(args: &Args, root: &Path, path: &Path) -> bool {
+	let Some(first) = path.strip_prefix(root).ok().and_then(|p| p.components().next()) else {
+		return false;
+	};
+	let first = first.as_os_str().to_string_lossy();
+	let name = path.file_name().unwrap_or_default().to_string_lossy();
+
+	// Manifests and files being written are no screenshots
+	!is_temporary(&name)
+		&& first != STATE_DIR
+		&& (first == args.other_name || !args.is_reserved(&first))
+		&& !sidecar::named_like(path)
+}
+
+/// Forgets the tags of `path`, which is gone, or of everything in it if it was a folder. Its sidecar is removed
+pub fn removed(root: &Path, path: &Path) {
+	info!("\"{}\" was removed", path.display());
+	metrics::removed();
+	forget(root, path);
+}
+
+/// Forgets the tags and checksum of `path`, which this program removed, and removes its sidecar
+pub fn forget(root: &Path, path: &Path) {
+	if let Err(e) = tags::removed(root, path) {
+		eprintln!("Error while forgetting the tags of \"{}\": {e}", path.display());
+	}
+
+	if let Err(e) = checksums::removed(path) {
+		eprintln!("Error while forgetting the checksum of \"{}\": {e}", path.display());
+	}
+
+	if let Err(e) = fs::remove_file(sidecar::path(path)) {
+		if e.kind() != std::io::ErrorKind::NotFound {
+			eprintln!("Error while removing the sidecar of \"{}\": {e}", path.display());
+		}
+	}
+}
+
+/// Carries the tags, window and sidecar of `from` over to `to`, or of everything in it if it's a folder
+pub fn renamed(root: &Path, from: &Path, to: &Path) {
+	info!("\"{}\" was renamed to \"{}\"", from.display(), to.display());
+
+	if let Err(e) = tags::moved(root, from, to) {
+		eprintln!("Error while moving the tags of \"{}\": {e}", from.display());
+	}
+
+	if let Err(e) = windows::moved(root, from, to) {
+		eprintln!("Error while moving the window of \"{}\": {e}", from.display());
+	}
+
+	if let Err(e) = sidecar::moved(from, to) {
+		eprintln!("Error while moving the sidecar of \"{}\": {e}", from.display());
+	}
+
+	if let Err(e) = checksums::moved(from, to) {
+		eprintln!("Error while moving the checksum of \"{}\": {e}", from.display());
+	}
+}
+
+fn snapshot_file(root: &Path) -> PathBuf {
+	root.join(STATE_DIR).join("snapshot")
+}
+
+/// The files of the tree in `root` as they are now
+fn scan(args: &Args, root: &Path) -> anyhow::Result<BTreeMap<PathBuf, Stamp>> {
+	let mut files = BTreeMap::new();
+
+	for file in commands::organized_files(args, root)? {
+		let Ok(metadata) = file.metadata() else {
+			continue; // Gone meanwhile
+		};
+		let modified = metadata.modified()?.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+
+		files.insert(file, (metadata.len(), modified));
+	}
+
+	Ok(files)
+}
+
+/// Remembers the files of the tree in `root`, for the next start to tell what changed while nothing watched it
+pub fn save(args: &Args, root: &Path) -> anyhow::Result<()> {
+	write_snapshot(root, &scan(args, root)?)
+}
+
+fn write_snapshot(root: &Path, files: &BTreeMap<PathBuf, Stamp>) -> anyhow::Result<()> {
+	let file = snapshot_file(root);
+	fs::create_dir_all(file.parent().unwrap())?; // Always has one
+
+	let contents: String = files
+		.iter()
+		.map(|(path, (size, modified))| format!("{}\t{size}\t{modified}\n", escape(&path.to_string_lossy())))
+		.collect();
+
+	// Like the tags, a crash leaves the old snapshot whole
+	let temp = file.with_extension("partial");
+	fs::write(&temp, contents)?;
+	fs::rename(temp, file)?;
+
+	Ok(())
+}
+
+/// Compares the tree in `root` with the snapshot of the last run, to catch up with what other programs did while
+/// nothing watched it. Files gone are removed, unless one of the same size and time turned up elsewhere, then they
+/// were renamed. Returns the files that are new, the first start has nothing to compare with and finds none
+pub fn catch_up(args: &Args, root: &Path) -> anyhow::Result<Vec<PathBuf>> {
+	let now = scan(args, root)?;
+
+	let contents = match fs::read_to_string(snapshot_file(root)) {
+		Err(e) if e.kind() == io::ErrorKind::NotFound => {
+			write_snapshot(root, &now)?;
+			return Ok(vec![]);
+		}
+		res => res?,
+	};
+
+	let before: BTreeMap<PathBuf, Stamp> = contents
+		.lines()
+		.filter_map(|line| {
+			let mut fields = line.split('\t');
+			let path = PathBuf::from(unescape(fields.next()?));
+
+			Some((path, (fields.next()?.parse().ok()?, fields.next()?.parse().ok()?)))
+		})
+		.collect();
+
+	let mut appeared: Vec<_> = now.keys().filter(|f| !before.contains_key(*f)).cloned().collect();
+
+	// Only a single candidate is taken for a rename, otherwise there's no telling which
+	let mut by_stamp: HashMap<Stamp, Vec<&PathBuf>> = HashMap::new();
+	for file in &appeared {
+		by_stamp.entry(now[file]).or_default().push(file);
+	}
+
+	let mut renamed_to = vec![];
+
+	for (file, stamp) in before.iter().filter(|(f, _)| !now.contains_key(*f)) {
+		match by_stamp.get_mut(stamp) {
+			Some(candidates) if candidates.len() == 1 => {
+				let to = candidates.remove(0);
+				renamed(root, file, to);
+				renamed_to.push(to.clone());
+			}
+			_ => removed(root, file),
+		}
+	}
+
+	appeared.retain(|f| !renamed_to.contains(f));
+
+	if !appeared.is_empty() {
+		info!("{} files appeared in \"{}\" since the last run", appeared.len(), root.display());
+	}
+
+	write_snapshot(root, &now)?;
+
+	Ok(appeared)
+}
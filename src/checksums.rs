@@ -0,0 +1,129 @@
+//! BLAKE3 checksums of sorted files, kept with --checksums in a manifest in each folder for scrub to verify
+
+use std::{
+	collections::BTreeMap,
+	fs, io,
+	path::{Path, PathBuf},
+	sync::Mutex,
+};
+
+use crate::{
+	blake3,
+	journal::{escape, unescape},
+};
+
+/// Name of the manifests, hidden so they are never sorted themselves
+pub const MANIFEST: &str = ".checksums";
+
+pub fn is_manifest(file: &Path) -> bool {
+	file.file_name().is_some_and(|n| n == MANIFEST)
+}
+
+/// Held while changing a manifest, files are sorted in parallel
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// What a file was like when it was sorted
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Checksum {
+	pub size: u64,
+	pub hash: String,
+}
+
+impl Checksum {
+	pub fn of(file: &Path) -> io::Result<Checksum> {
+		let file = fs::File::open(file)?;
+		Ok(Checksum { size: file.metadata()?.len(), hash: blake3::hash(io::BufReader::new(file))? })
+	}
+}
+
+/// Checksums of the files in `dir`, by name
+pub type Manifest = BTreeMap<String, Checksum>;
+
+fn manifest_file(dir: &Path) -> PathBuf {
+	dir.join(MANIFEST)
+}
+
+pub fn read(dir: &Path) -> io::Result<Manifest> {
+	let contents = match fs::read_to_string(manifest_file(dir)) {
+		Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Manifest::new()),
+		res => res?,
+	};
+
+	Ok(contents
+		.lines()
+		.filter_map(|line| {
+			let mut fields = line.split('\t');
+			let name = unescape(fields.next()?);
+			let size = fields.next()?.parse().ok()?;
+
+			Some((name, Checksum { size, hash: fields.next()?.to_owned() }))
+		})
+		.collect())
+}
+
+/// Replaces the manifest of `dir`, an empty one is removed so the folder can be
+fn write(dir: &Path, manifest: &Manifest) -> io::Result<()> {
+	let file = manifest_file(dir);
+
+	if manifest.is_empty() {
+		return match fs::remove_file(file) {
+			Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+			res => res,
+		};
+	}
+
+	let contents: String =
+		manifest.iter().map(|(name, c)| format!("{}\t{}\t{}\n", escape(name), c.size, c.hash)).collect();
+
+	// Like the tags, renamed over the old one so a crash leaves either whole
+	let temp = file.with_extension("partial");
+	fs::write(&temp, contents)?;
+	fs::rename(temp, file)
+}
+
+/// Changes the checksum of `file` in its folder's manifest with `change`
+fn update(file: &Path, change: impl FnOnce(&mut Manifest, String) -> bool) -> io::Result<()> {
+	let (Some(dir), Some(name)) = (file.parent(), file.file_name()) else {
+		return Ok(());
+	};
+
+	let _lock = LOCK.lock().unwrap();
+	let mut manifest = read(dir)?;
+
+	if change(&mut manifest, name.to_string_lossy().into_owned()) {
+		write(dir, &manifest)?;
+	}
+
+	Ok(())
+}
+
+/// Checksums `file` and records it
+pub fn add(file: &Path) -> io::Result<()> {
+	let checksum = Checksum::of(file)?;
+	update(file, |manifest, name| manifest.insert(name, checksum.clone()) != Some(checksum))
+}
+
+/// Forgets the checksum of `file`, it's gone
+pub fn removed(file: &Path) -> io::Result<()> {
+	update(file, |manifest, name| manifest.remove(&name).is_some())
+}
+
+/// Carries the checksum of `from` over to `to`, where it was moved. Whether it had one
+pub fn moved(from: &Path, to: &Path) -> io::Result<bool> {
+	let mut checksum = None;
+	update(from, |manifest, name| {
+		checksum = manifest.remove(&name);
+		checksum.is_some()
+	})?;
+
+	let Some(checksum) = checksum else {
+		return Ok(false);
+	};
+
+	update(to, |manifest, name| {
+		manifest.insert(name, checksum);
+		true
+	})?;
+
+	Ok(true)
+}
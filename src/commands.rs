@@ -0,0 +1,1354 @@
+//! One-off operations on an organized tree, run instead of watching it
+
+use std::{
+	collections::HashMap,
+	fs,
+	io::{self, Write},
+	path::{Path, PathBuf},
+	time::{Duration, SystemTime},
+};
+
+use clap::{CommandFactory, ValueEnum};
+
+use crate::{
+	api, archive, browse, bursts, capture, checksums, clean_directory, config,
+	date::Date,
+	destination,
+	events::{info, Event},
+	format_size, hooks,
+	index::{self, Shape},
+	journal, json,
+	layout::{FolderDates, Layout},
+	locale::Locale,
+	metadata, move_files, platform, pointer_file, same_contents, sidecar,
+	source::Source,
+	space, tags, thumbs, trash, update_file, update_latest, update_starred, upload, watching_instance, webdav, windows,
+	Args, Backend, Command, Format, Keep, Mode, TagAction, ThumbsAction, Undated, QUARANTINE, STARRED, STATE_DIR,
+};
+
+/// Folder structures of other screenshot tools and of hand organized collections, most specific first
+const IMPORT_LAYOUTS: &[&str] = &[
+	"{year}/{month}/{day}",
+	"{year}/{month_name}/{day}",
+	"{year}-{month}-{day}",
+	"{year}/{month}",
+	"{year}/{month_name}",
+	"{year}-{month}",
+	"{year}",
+];
+
+pub fn run(args: &Args, command: &Command, path: &Path) -> anyhow::Result<()> {
+	match command {
+		Command::Clean { all, interactive } => clean(&Args { confirm: *interactive, ..args.clone() }, path, *all),
+		Command::Repair { interactive } => repair(&Args { confirm: *interactive, ..args.clone() }, path),
+		Command::Verify => verify(args, path),
+		Command::Health => health(args, path),
+		Command::Delete { file, date, older_than } => delete(args, path, file.as_deref(), *date, *older_than),
+		Command::Bursts { within, keep, interactive } => bursts(args, path, *within, *keep, *interactive),
+		Command::Thumbs { action: ThumbsAction::Rebuild { all } } => thumbs::rebuild(args, path, *all),
+		Command::Thumbs { action: ThumbsAction::Prune { max_size } } => thumbs::prune(args, path, *max_size),
+		Command::Thumbs { action: ThumbsAction::Verify } => thumbs::verify(args, path),
+		Command::Scrub { add_missing } => scrub(args, path, *add_missing),
+		Command::Sync { url, user } => sync(args, path, url, user.as_deref()),
+		Command::Browse => browse::run(args, path),
+		Command::Open { latest, date, file } => open(args, path, *latest, *date, file.as_deref()),
+		Command::Tag { action } => tag(args, path, action),
+		Command::Search { tags, starred, min_width, min_height, min_megapixels, orientation } => {
+			let shape = Shape {
+				min_width: *min_width,
+				min_height: *min_height,
+				min_megapixels: *min_megapixels,
+				orientation: *orientation,
+			};
+
+			search(args, path, tags, *starred, &shape)
+		}
+		Command::Latest { list, json, null } => latest(args, path, *list, *json, *null),
+		Command::Last => last(args, path),
+		Command::Serve { listen } => api::serve(args, path, listen),
+		Command::Star { file } => {
+			tag(args, path, &TagAction::Add { file: file.clone(), tags: vec![tags::STAR.to_owned()] })
+		}
+		Command::Unstar { file } => {
+			tag(args, path, &TagAction::Rm { file: file.clone(), tags: vec![tags::STAR.to_owned()] })
+		}
+		Command::Paste => paste(args, path),
+		Command::Capture { backend, interactive, region, output } => {
+			capture(args, path, *backend, *interactive, *region, output.as_deref())
+		}
+		Command::Migrate { from, dry_run, interactive } => {
+			migrate(&Args { confirm: *interactive, ..args.clone() }, path, from.clone(), *dry_run)
+		}
+		Command::Flatten => flatten(args, path),
+		Command::Import { source } => import(args, path, source),
+		Command::Export { format } => export(args, path, *format),
+		Command::Archive { month, output, recipients, recipients_files } => {
+			archive(args, path, *month, output, recipients, recipients_files)
+		}
+		Command::Undo { last, session } => undo(args, path, *last, *session),
+		Command::Doctor => doctor(args, path),
+		Command::Init { systemd } => init(args, path, *systemd),
+		Command::Completions { .. } => unreachable!(), // Printed before any directory is opened
+	}
+}
+
+fn clean(args: &Args, path: &Path, all: bool) -> anyhow::Result<()> {
+	let dir = Path::new(args.screenshot_dir.as_deref().unwrap_or_default()).canonicalize()?;
+	let args = Args { newer_than: args.newer_than.filter(|_| !all), ..args.clone() };
+
+	let failed = clean_directory(&args, &dir, path)?;
+
+	if !failed.is_empty() {
+		anyhow::bail!("{} files could not be sorted", failed.len());
+	}
+
+	Ok(())
+}
+
+fn doctor(args: &Args, path: &Path) -> anyhow::Result<()> {
+	let dir = Path::new(args.screenshot_dir.as_deref().unwrap_or_default()).canonicalize()?;
+	let mut problems = vec![];
+
+	// Options
+
+	match config::dir_options(&dir)? {
+		Some(options) if options.is_empty() => info!("\"{}\" sets no options", dir.join(config::DIR_CONFIG).display()),
+		Some(options) => info!("Options of \"{}\": {}", dir.join(config::DIR_CONFIG).display(), options.join(" ")),
+		None => (),
+	}
+
+	for var in config::unknown_env(&Args::command()) {
+		problems.push(format!("${var} is not an option, check its spelling against --help"));
+	}
+
+	// Watching
+
+	let mut folders = 0;
+	count_dirs(path, &mut folders)?;
+
+	if !path.starts_with(&dir) && !dir.starts_with(path) {
+		folders += 1;
+	}
+
+	match platform::watch_limit() {
+		Some(limit) if folders * 10 > limit * 9 => problems.push(format!(
+			"watching takes {folders} of the {limit} inotify watches allowed, which other programs use too. Raise \
+			the limit with \"sysctl fs.inotify.max_user_watches={}\"",
+			(folders * 2).max(524288)
+		)),
+		Some(limit) => info!("Watching takes {folders} of the {limit} inotify watches allowed"),
+		None => (),
+	}
+
+	// Permissions
+
+	let state = path.join(STATE_DIR);
+
+	for (dir, what) in [(dir.as_path(), "files can't be moved out of it"), (&state, "nothing can be recorded")] {
+		let probe = dir.join(".screenshot-manager-doctor");
+		let res = fs::create_dir_all(dir).and_then(|_| fs::write(&probe, "")).and_then(|_| fs::remove_file(&probe));
+
+		if let Err(e) = res {
+			problems.push(format!("\"{}\" can't be written to, {what}: {e}", dir.display()));
+		}
+	}
+
+	// Links
+
+	let link = state.join("doctor-link");
+	_ = platform::remove_link(&link);
+
+	match platform::link_dir(path, &link) {
+		Ok(()) => _ = platform::remove_link(&link),
+		Err(e) if platform::links_unsupported(&e) => {
+			let fallback = args.link_fallback.to_possible_value().unwrap(); // None are skipped
+			info!(
+				"\"{}\" can't hold links, --link-fallback {} stands in for them",
+				path.display(),
+				fallback.get_name()
+			);
+		}
+		Err(e) => problems.push(format!("links can't be made in \"{}\": {e}", path.display())),
+	}
+
+	// Names of the files waiting to be sorted
+
+	let (mut dated, mut undated, mut left) = (0, vec![], 0);
+	let mut unmatched: Vec<_> = args.exclude.iter().collect();
+
+	for entry in fs::read_dir(&dir)? {
+		let file = entry?.path();
+
+		let name = file.file_name().unwrap_or_default().to_string_lossy();
+
+		if !file.is_file() || name.starts_with('.') {
+			continue; // Hidden ones too, like the config
+		}
+
+		unmatched.retain(|g| !g.matches(&name));
+
+		match destination(args, path, &file)? {
+			Some(to)
+				if to.starts_with(&args.other_name)
+					|| to == Path::new(QUARANTINE) && args.undated == Undated::Quarantine =>
+			{
+				undated.push(name.into_owned())
+			}
+			Some(_) => dated += 1,
+			None => left += 1,
+		}
+	}
+
+	info!("Files in \"{}\": {dated} with a date, {} without, {left} left alone", dir.display(), undated.len());
+
+	if !undated.is_empty() {
+		let examples: Vec<_> = undated.iter().take(3).map(|n| format!("\"{n}\"")).collect();
+		let fix = if args.mtime_fallback { "" } else { ", --mtime-fallback would date them by when they were changed" };
+		let examples = examples.join(", ");
+		let to = if args.undated == Undated::Quarantine { QUARANTINE } else { &args.other_name };
+		problems.push(format!("names without a date like {examples} go to \"{to}\"{fix}"));
+	}
+
+	for glob in unmatched {
+		info!("--exclude \"{}\" matches no file in \"{}\"", glob.as_str(), dir.display());
+	}
+
+	for problem in &problems {
+		println!("{problem}");
+	}
+
+	match problems.len() {
+		0 => {
+			println!("No problems found with \"{}\"", dir.display());
+			Ok(())
+		}
+		1 => anyhow::bail!("1 problem found"),
+		n => anyhow::bail!("{n} problems found"),
+	}
+}
+
+fn count_dirs(dir: &Path, count: &mut u64) -> anyhow::Result<()> {
+	*count += 1;
+
+	for entry in fs::read_dir(dir)? {
+		let path = entry?.path();
+
+		if path.is_dir() && !path.is_symlink() {
+			count_dirs(&path, count)?;
+		}
+	}
+
+	Ok(())
+}
+
+/// Creates the screenshot directory and the tree of `args`, for init
+pub fn create_dirs(args: &Args) -> anyhow::Result<()> {
+	let dir = Path::new(args.screenshot_dir.as_deref().unwrap_or_default()); // Commands are given one
+
+	for dir in [Some(dir), args.into.as_deref()].into_iter().flatten() {
+		if !dir.is_dir() {
+			info!("Create \"{}\"", dir.display());
+			fs::create_dir_all(dir)?;
+		}
+	}
+
+	Ok(())
+}
+
+/// Options of a new screenshot directory, as examples
+const STARTER_CONFIG: &str = r#"# Options for this screenshot directory, written like those of the command line:
+# layout = "{year}/{month}" is --layout {year}/{month}. SCREENSHOT_MANAGER_* environment variables and the command
+# line override them
+
+# Folders files are sorted into
+# layout = "{year}/{month}/{day}"
+
+# Whether to move files, or leave the originals and sort copies or hardlinks of them: move, copy or hardlink
+# mode = "move"
+
+# What to do with a file that has the name of one already there: rename, hash, skip, overwrite or error
+# on_conflict = "rename"
+
+# Files to leave alone
+# exclude = ["*.kra", "wallpaper*"]
+
+# Show a notification for each screenshot sorted
+# notify = true
+"#;
+
+fn init(args: &Args, path: &Path, systemd: bool) -> anyhow::Result<()> {
+	let dir = Path::new(args.screenshot_dir.as_deref().unwrap_or_default()).canonicalize()?;
+
+	let other = path.join(&args.other_name);
+
+	if !other.is_dir() {
+		info!("Create \"{}\"", other.display());
+		fs::create_dir_all(&other)?;
+	}
+
+	let config = dir.join(config::DIR_CONFIG);
+
+	match fs::OpenOptions::new().write(true).create_new(true).open(&config) {
+		Ok(mut file) => {
+			info!("Write \"{}\"", config.display());
+			file.write_all(STARTER_CONFIG.as_bytes())?;
+		}
+		Err(e) if e.kind() == io::ErrorKind::AlreadyExists => info!("Keep \"{}\", it exists", config.display()),
+		Err(e) => return Err(e.into()),
+	}
+
+	if systemd {
+		write_unit(args, &dir, path)?;
+	}
+
+	Ok(())
+}
+
+/// Writes a systemd user unit watching `dir` into the tree in `path`
+fn write_unit(args: &Args, dir: &Path, path: &Path) -> anyhow::Result<()> {
+	if !cfg!(target_os = "linux") {
+		anyhow::bail!("systemd units are for Linux");
+	}
+
+	let units = match std::env::var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+		Some(config) => PathBuf::from(config),
+		None => {
+			PathBuf::from(std::env::var_os("HOME").ok_or_else(|| anyhow::anyhow!("HOME is not set"))?).join(".config")
+		}
+	}
+	.join("systemd/user");
+	let unit = units.join("screenshot-manager.service");
+
+	// Quoted for systemd, which expands "%" specifiers and "$" variables in command lines
+	let quote = |s: &Path| {
+		let s = s.to_string_lossy().replace('\\', "\\\\").replace('"', "\\\"");
+		format!("\"{}\"", s.replace('%', "%%").replace('$', "$$"))
+	};
+
+	let mut command = format!("{} {}", quote(&std::env::current_exe()?), quote(dir));
+
+	if args.into.is_some() {
+		command.push_str(&format!(" --into {}", quote(path)));
+	}
+
+	let contents = format!(
+		"[Unit]\nDescription=Sort the screenshots of {}\n\n[Service]\nExecStart={command}\nRestart=on-failure\n\n\
+		[Install]\nWantedBy=default.target\n",
+		dir.display()
+	);
+
+	fs::create_dir_all(&units)?;
+
+	match fs::OpenOptions::new().write(true).create_new(true).open(&unit) {
+		Ok(mut file) => file.write_all(contents.as_bytes())?,
+		Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+			anyhow::bail!("\"{}\" exists already, remove it to write a new one", unit.display())
+		}
+		Err(e) => return Err(e.into()),
+	}
+
+	info!("Wrote \"{}\", start it now and on every login with:", unit.display());
+	info!("  systemctl --user daemon-reload && systemctl --user enable --now screenshot-manager.service");
+
+	Ok(())
+}
+
+fn repair(args: &Args, path: &Path) -> anyhow::Result<()> {
+	info!("Started repairing \"{}\"", path.display());
+
+	// Files already in the tree are never the originals, leaving copies behind would only duplicate them
+	let args = Args { mode: Mode::Move, ..args.clone() };
+
+	refile(&args, path)?;
+	update_latest(&args, path)?;
+
+	info!("Repair done");
+
+	Ok(())
+}
+
+fn migrate(args: &Args, path: &Path, mut from: Option<Layout>, dry_run: bool) -> anyhow::Result<()> {
+	info!("Started migrating \"{}\"", path.display());
+
+	if let Some(layout) = &mut from {
+		layout.set_locale(args.locale.unwrap_or_else(Locale::from_env));
+	}
+
+	let folder_dates = from.map(|l| FolderDates { root: path.to_path_buf(), layouts: vec![l] });
+	let args = Args { mode: Mode::Move, folder_dates, ..args.clone() };
+
+	if dry_run {
+		let mut moves = 0;
+
+		for file in organized_files(&args, path)? {
+			match destination(&args, path, &file)? {
+				Some(to) if file.parent() != Some(path.join(&to).as_path()) => {
+					println!("Would move \"{}\" -> \"{}\"", file.display(), path.join(to).display());
+					moves += 1;
+				}
+				_ => (),
+			}
+		}
+
+		println!("Migration would move {moves} files");
+		return Ok(());
+	}
+
+	let dirs = organized_dirs(&args, path)?;
+
+	refile(&args, path)?;
+
+	for dir in dirs {
+		remove_empty(&dir)?;
+	}
+
+	update_latest(&args, path)?;
+
+	info!("Migration done");
+
+	Ok(())
+}
+
+fn flatten(args: &Args, path: &Path) -> anyhow::Result<()> {
+	info!("Started flattening \"{}\"", path.display());
+
+	let args = Args { mode: Mode::Move, ..args.clone() };
+
+	for file in organized_files(&args, path)? {
+		if let Err(e) = move_files(&args, path, &file, Path::new("")) {
+			Event::Error { path: Some(&file), message: e.to_string() }.emit();
+		}
+	}
+
+	for link in args.link_names().map(|l| path.join(l)) {
+		let pointer = pointer_file(&link);
+
+		if link.is_symlink() {
+			platform::remove_link(&link)?;
+			Event::LatestUpdated { link: &link, target: None }.emit();
+		} else if pointer.is_file() {
+			if link.is_dir() {
+				info!("Remove copy \"{}\"", link.display());
+				fs::remove_dir_all(&link)?;
+			}
+
+			fs::remove_file(&pointer)?;
+		}
+	}
+
+	for dir in organized_dirs(&args, path)? {
+		remove_empty(&dir)?;
+	}
+
+	info!("Flattening done");
+
+	Ok(())
+}
+
+fn import(args: &Args, path: &Path, source: &Path) -> anyhow::Result<()> {
+	let source = source.canonicalize()?;
+
+	if source.starts_with(path) {
+		anyhow::bail!("\"{}\" is already in \"{}\"", source.display(), path.display());
+	}
+
+	info!("Started importing \"{}\"", source.display());
+
+	let locale = args.locale.unwrap_or_else(Locale::from_env);
+	let layouts = IMPORT_LAYOUTS
+		.iter()
+		.map(|l| {
+			let mut layout = Layout::parse(l).unwrap(); // Known to be valid
+			layout.set_locale(locale);
+			layout
+		})
+		.collect();
+
+	let folder_dates = Some(FolderDates { root: source.clone(), layouts });
+	let args = Args { mtime_fallback: true, folder_dates, ..args.clone() };
+
+	let mut files = vec![];
+	tree_files(&source, &mut files)?;
+
+	// The screenshot directory may be inside the one imported from
+	for file in files.iter().filter(|f| !f.starts_with(path)) {
+		let res = match destination(&args, path, file) {
+			Ok(Some(to)) => move_files(&args, path, file, &to),
+			Ok(None) => Ok(None),
+			Err(e) => Err(e),
+		};
+
+		if let Err(e) = res {
+			Event::Error { path: Some(file), message: e.to_string() }.emit();
+		}
+	}
+
+	update_latest(&args, path)?;
+
+	info!("Import done");
+
+	Ok(())
+}
+
+fn undo(args: &Args, path: &Path, last: usize, session: bool) -> anyhow::Result<()> {
+	let mut entries = journal::read(path)?;
+
+	let count = match entries.last() {
+		Some(newest) if session => entries.iter().rev().take_while(|e| e.session == newest.session).count(),
+		_ => last.min(entries.len()),
+	};
+
+	if count == 0 {
+		info!("Nothing to undo");
+		return Ok(());
+	}
+
+	let mut failed = vec![];
+
+	for entry in entries.drain(entries.len() - count..).rev() {
+		match undo_entry(path, &entry) {
+			Ok(()) => remove_empty_parents(path, &entry.to)?,
+			Err(e) => {
+				Event::Error { path: Some(&entry.to), message: e.to_string() }.emit();
+				failed.push(entry);
+			}
+		}
+	}
+
+	// What couldn't be undone stays for another try
+	failed.reverse();
+	entries.extend(failed);
+	journal::write(path, &entries)?;
+
+	update_latest(args, path)?;
+
+	info!("Undo done");
+
+	Ok(())
+}
+
+fn undo_entry(path: &Path, entry: &journal::Entry) -> anyhow::Result<()> {
+	let (from, to) = (&entry.from, &entry.to);
+
+	if !to.is_file() {
+		anyhow::bail!("\"{}\" is gone", to.display());
+	}
+
+	match entry.action.as_str() {
+		"move" => {
+			if from.exists() {
+				anyhow::bail!("\"{}\" exists again", from.display());
+			}
+
+			if let Some(parent) = from.parent() {
+				fs::create_dir_all(parent)?;
+			}
+
+			fs::rename(to, from).or_else(|_| fs::copy(to, from).and_then(|_| fs::remove_file(to)))?;
+			Event::Moved { action: "Move", from: to, to: from }.emit();
+
+			if let Err(e) = tags::moved(path, to, from) {
+				eprintln!("Error while moving the tags of \"{}\": {e}", to.display());
+			}
+
+			if let Err(e) = sidecar::moved(to, from) {
+				eprintln!("Error while moving the sidecar of \"{}\": {e}", to.display());
+			}
+
+			if let Err(e) = checksums::moved(to, from) {
+				eprintln!("Error while moving the checksum of \"{}\": {e}", to.display());
+			}
+		}
+		"copy" | "link" => {
+			// The original is still there, only the copy goes
+			if from.is_file() && !same_contents(from, to)? {
+				anyhow::bail!("\"{}\" changed since it was made", to.display());
+			}
+
+			info!("Remove \"{}\"", to.display());
+			fs::remove_file(to)?;
+			checksums::removed(to)?;
+		}
+		action => anyhow::bail!("unknown journal action \"{action}\""),
+	}
+
+	Ok(())
+}
+
+/// Removes the folders `file` was in up to the screenshot directory `path`, as long as they are empty
+fn remove_empty_parents(path: &Path, file: &Path) -> anyhow::Result<()> {
+	for dir in file.ancestors().skip(1).take_while(|d| d.starts_with(path) && *d != path) {
+		if fs::read_dir(dir)?.next().is_some() {
+			break;
+		}
+
+		info!("Remove empty \"{}\"", dir.display());
+		fs::remove_dir(dir)?;
+	}
+
+	Ok(())
+}
+
+fn export(args: &Args, path: &Path, format: Format) -> anyhow::Result<()> {
+	let mut files = organized_files(args, path)?;
+	files.sort();
+
+	let windows = windows::read(path)?;
+	// The --checksums manifest of each folder, read once
+	let mut manifests = HashMap::new();
+
+	if format == Format::Csv {
+		println!("path,date,size,hash,window,workspace");
+	} else {
+		println!("[");
+	}
+
+	for (i, file) in files.iter().enumerate() {
+		let relative = file.strip_prefix(path).unwrap_or(file);
+		let date = relative.parent().and_then(|d| args.layout.folder_date(d)).map(|(d, _)| d.to_string());
+		let size = file.metadata()?.len();
+		let name = file.to_string_lossy();
+		let window = windows.get(file).cloned().unwrap_or_default();
+
+		let dir = file.parent().unwrap(); // Files of the tree are in folders of it
+		if !manifests.contains_key(dir) {
+			manifests.insert(dir.to_path_buf(), checksums::read(dir)?);
+		}
+
+		let hash = file.file_name().and_then(|n| manifests[dir].get(n.to_str()?)).map(|c| c.hash.clone());
+
+		match format {
+			Format::Json => {
+				let optional = |s: Option<&str>| s.map_or("null".to_owned(), json::string);
+				let (date, hash) = (optional(date.as_deref()), optional(hash.as_deref()));
+				let (title, workspace) = (optional(window.title.as_deref()), optional(window.workspace.as_deref()));
+				let separator = if i + 1 < files.len() { "," } else { "" };
+				println!(
+					"  {{\"path\": {}, \"date\": {date}, \"size\": {size}, \"hash\": {hash}, \"window\": {title}, \
+					 \"workspace\": {workspace}}}{separator}",
+					json::string(&name)
+				);
+			}
+			Format::Csv => {
+				let field = |s: Option<String>| s.as_deref().map(csv_field).unwrap_or_default();
+				println!(
+					"{},{},{size},{},{},{}",
+					csv_field(&name),
+					date.unwrap_or_default(),
+					hash.unwrap_or_default(),
+					field(window.title),
+					field(window.workspace)
+				)
+			}
+		}
+	}
+
+	if format == Format::Json {
+		println!("]");
+	}
+
+	Ok(())
+}
+
+fn archive(
+	args: &Args,
+	path: &Path,
+	month: Date,
+	output: &Path,
+	recipients: &[String],
+	recipients_files: &[PathBuf],
+) -> anyhow::Result<()> {
+	let depth = args.layout.month_depth().ok_or_else(|| anyhow::anyhow!("--layout has no month folders"))?;
+	let dir: PathBuf = args.layout.dir(&month, None, &Source::default()).iter().take(depth).collect();
+	let label = format!("{:04}-{:02}", month.year, month.month);
+
+	if !path.join(&dir).is_dir() {
+		anyhow::bail!("there are no screenshots from {label}");
+	}
+
+	fs::create_dir_all(output)?;
+
+	let encrypt = !recipients.is_empty() || !recipients_files.is_empty();
+	let file = output.join(format!("{label}.tar{}", if encrypt { ".age" } else { "" }));
+
+	info!("Archive \"{}\" -> \"{}\"", path.join(&dir).display(), file.display());
+	archive::write(path, &dir, &file, encrypt.then_some((recipients, recipients_files)))?;
+	info!("Archive done");
+
+	Ok(())
+}
+
+fn sync(args: &Args, path: &Path, url: &str, user: Option<&str>) -> anyhow::Result<()> {
+	info!("Started syncing \"{}\" to {url}", path.display());
+
+	// Sidecars are part of the tree there too
+	let mut files = vec![];
+
+	for dir in organized_dirs(args, path)? {
+		tree_files(&dir, &mut files)?;
+	}
+
+	files.retain(|f| !checksums::is_manifest(f) && !f.is_symlink());
+	files.sort();
+
+	match webdav::mirror(path, &files, url, user)? {
+		0 => Ok(()),
+		1 => anyhow::bail!("1 file was changed on the server and left alone"),
+		n => anyhow::bail!("{n} files were changed on the server and left alone"),
+	}
+}
+
+fn csv_field(s: &str) -> String {
+	if s.contains([',', '"', '\n', '\r']) {
+		format!("\"{}\"", s.replace('"', "\"\""))
+	} else {
+		s.to_owned()
+	}
+}
+
+/// Puts every file in the organized tree where it belongs
+fn refile(args: &Args, path: &Path) -> anyhow::Result<()> {
+	for file in organized_files(args, path)? {
+		if let Err(e) = update_file(args, path, &file) {
+			Event::Error { path: Some(&file), message: e.to_string() }.emit();
+		}
+	}
+
+	Ok(())
+}
+
+/// Every file in the organized tree
+pub fn organized_files(args: &Args, path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+	let mut files = vec![];
+
+	for dir in organized_dirs(args, path)? {
+		tree_files(&dir, &mut files)?;
+	}
+
+	files.retain(|f| is_screenshot(f));
+
+	Ok(files)
+}
+
+/// Whether `file` of the tree is a screenshot, not a sidecar or a checksum manifest. Those go where their files go
+pub fn is_screenshot(file: &Path) -> bool {
+	!sidecar::is_sidecar(file) && !checksums::is_manifest(file)
+}
+
+/// Folders of the screenshot directory this program sorts files into
+pub fn organized_dirs(args: &Args, path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+	let mut dirs = vec![];
+
+	for entry in fs::read_dir(path)? {
+		let entry = entry?;
+		let name = entry.file_name().to_string_lossy().into_owned();
+
+		if is_managed(args, &name) && entry.path().is_dir() {
+			dirs.push(entry.path());
+		}
+	}
+
+	Ok(dirs)
+}
+
+/// Whether the folder `name` at the top of the tree is one this program sorts files into
+pub fn is_managed(args: &Args, name: &str) -> bool {
+	// Links and their stand-ins lead back into the tree, other folders are not ours
+	if args.is_reserved(name) {
+		!args.link_names().contains(&name) && name != STARRED
+	} else {
+		let previous = args.folder_dates.as_ref().and_then(|f| f.folder_key(name));
+		args.layout.folder_key(0, name).or(previous).is_some()
+	}
+}
+
+/// Removes the folders below `dir` that have nothing but other folders in them, and `dir` itself if that leaves it
+/// empty
+pub fn remove_empty(dir: &Path) -> anyhow::Result<bool> {
+	let mut empty = true;
+
+	for entry in fs::read_dir(dir)? {
+		let entry = entry?;
+		empty &= entry.file_type()?.is_dir() && remove_empty(&entry.path())?;
+	}
+
+	if empty {
+		info!("Remove empty \"{}\"", dir.display());
+		fs::remove_dir(dir)?;
+	}
+
+	Ok(empty)
+}
+
+/// Every file below `dir`
+pub fn tree_files(dir: &Path, files: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+	for entry in fs::read_dir(dir)? {
+		let path = entry?.path();
+
+		if path.is_dir() {
+			tree_files(&path, files)?;
+		} else {
+			files.push(path);
+		}
+	}
+
+	Ok(())
+}
+
+fn verify(args: &Args, path: &Path) -> anyhow::Result<()> {
+	let mut problems = vec![];
+
+	check_links(args, path, &mut problems);
+	check_dir(args, path, path, 0, &mut problems)?;
+
+	for problem in &problems {
+		println!("{problem}");
+	}
+
+	match problems.len() {
+		0 => {
+			println!("No problems found in \"{}\"", path.display());
+			Ok(())
+		}
+		1 => anyhow::bail!("1 problem found"),
+		n => anyhow::bail!("{n} problems found"),
+	}
+}
+
+fn capture(
+	args: &Args,
+	path: &Path,
+	backend: Option<Backend>,
+	interactive: bool,
+	region: bool,
+	output: Option<&str>,
+) -> anyhow::Result<()> {
+	// Portals call them all "Screenshot". The output is there for "{monitor}"
+	let name = match output {
+		Some(output) => format!("{}_{output}", dated_name(args)),
+		None => dated_name(args),
+	};
+
+	let named = match backend.unwrap_or(if region || output.is_some() { Backend::Grim } else { Backend::Portal }) {
+		Backend::Portal if region || output.is_some() => anyhow::bail!("--region and --output need --backend grim"),
+		Backend::Portal => {
+			let shot = capture::portal(interactive)?;
+			let ext = shot.extension().map_or("png".into(), |e| e.to_string_lossy());
+			let named = shot.with_file_name(format!("{name}.{ext}"));
+			fs::rename(&shot, &named)?;
+			named
+		}
+		// Outside the watched directory, so a running watcher doesn't sort it first
+		Backend::Grim => {
+			let shot = std::env::temp_dir().join(format!("{name}.png"));
+			capture::grim(&shot, region, output)?;
+			shot
+		}
+	};
+
+	save(args, path, &named)
+}
+
+fn paste(args: &Args, path: &Path) -> anyhow::Result<()> {
+	let image = capture::clipboard(&std::env::temp_dir().join(dated_name(args)))?;
+	save(args, path, &image)
+}
+
+/// Name for a screenshot taken now, without extension
+fn dated_name(args: &Args) -> String {
+	let (date, time) = args.timezone.local(SystemTime::now());
+	format!("{date}_{:02}-{:02}-{:02}", time.hour, time.minute, time.second)
+}
+
+/// Sorts the new screenshot `named` into the tree and runs the hooks for it
+fn save(args: &Args, path: &Path, named: &Path) -> anyhow::Result<()> {
+	// It was made to be moved, just now. Names only date some types, the modification time always works for it
+	let args = Args { mode: Mode::Move, mtime_fallback: true, ..args.clone() };
+
+	let Some(to) = destination(&args, path, named)? else {
+		_ = fs::remove_file(named);
+		anyhow::bail!("\"{}\" is left out by --extensions or --exclude", named.display());
+	};
+
+	let Some(file) = move_files(&args, path, named, &to)? else {
+		return Ok(()); // Skipped, --on-conflict told why
+	};
+
+	info!("Saved \"{}\"", file.display());
+	update_latest(&args, path)?;
+	hooks::run(&args, &file);
+	upload::wait(); // This process ends here, the upload would be cut short
+
+	Ok(())
+}
+
+pub fn tag(args: &Args, path: &Path, action: &TagAction) -> anyhow::Result<()> {
+	let (TagAction::Add { file, tags: changed } | TagAction::Rm { file, tags: changed }) = action;
+	let file = tree_file(path, file)?;
+
+	let mut tags = tags::read(path)?;
+	let file_tags = tags.entry(file.clone()).or_default();
+
+	match action {
+		TagAction::Add { .. } => file_tags.extend(changed.iter().cloned()),
+		TagAction::Rm { .. } => file_tags.retain(|t| !changed.contains(t)),
+	}
+
+	tags::write(path, &tags)?;
+	sidecar::refresh(path, &file)?;
+	update_starred(args, path)
+}
+
+/// `file` as it is recorded, if it is a file in the tree
+fn tree_file(path: &Path, file: &Path) -> anyhow::Result<PathBuf> {
+	let file = file.canonicalize().map_err(|e| anyhow::anyhow!("\"{}\": {e}", file.display()))?;
+
+	if !file.is_file() || !file.starts_with(path) {
+		anyhow::bail!("\"{}\" is not a file in \"{}\"", file.display(), path.display());
+	}
+
+	Ok(file)
+}
+
+fn search(args: &Args, path: &Path, wanted: &[String], starred: bool, shape: &Shape) -> anyhow::Result<()> {
+	let wanted: Vec<&str> = wanted.iter().map(String::as_str).chain(starred.then_some(tags::STAR)).collect();
+	let tags = tags::read(path)?;
+
+	// Sorted by path like the files are
+	let files = if shape.is_any() {
+		index::dimensions(args, path)?
+	} else {
+		organized_files(args, path)?.into_iter().map(|file| (file, None)).collect()
+	};
+
+	for (file, dimensions) in files {
+		let file_tags = tags.get(&file);
+
+		if wanted.iter().all(|&t| file_tags.is_some_and(|f| f.contains(t))) && shape.fits(dimensions) {
+			println!("{}", file.display());
+		}
+	}
+
+	Ok(())
+}
+
+/// The screenshots of the tree, newest first. By the date of their folder, then when they were written
+pub fn newest_files(args: &Args, path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+	// Those without a date are no screenshots to show
+	let mut files: Vec<_> = organized_files(args, path)?
+		.into_iter()
+		.filter_map(|file| {
+			let date = args.layout.folder_date(file.parent()?.strip_prefix(path).ok()?)?;
+			let modified = file.metadata().and_then(|m| m.modified()).ok()?;
+			Some((date, modified, file))
+		})
+		.collect();
+
+	files.sort_by(|a, b| b.cmp(a));
+
+	Ok(files.into_iter().map(|(_, _, file)| file).collect())
+}
+
+fn last(args: &Args, path: &Path) -> anyhow::Result<()> {
+	let Some(file) = newest_files(args, path)?.into_iter().next() else {
+		anyhow::bail!("there are no screenshots in \"{}\"", path.display());
+	};
+
+	println!("{}", file.display());
+
+	Ok(())
+}
+
+fn latest(args: &Args, path: &Path, count: usize, json: bool, null: bool) -> anyhow::Result<()> {
+	let files = newest_files(args, path)?;
+	let files: Vec<_> = files.iter().take(count).map(|file| file.to_string_lossy()).collect();
+
+	let mut out = io::stdout().lock();
+
+	if json {
+		let paths: Vec<_> = files.iter().map(|f| json::string(f)).collect();
+		writeln!(out, "[{}]", paths.join(", "))?;
+	} else {
+		for file in files {
+			write!(out, "{file}{}", if null { '\0' } else { '\n' })?;
+		}
+	}
+
+	Ok(())
+}
+
+fn open(args: &Args, path: &Path, latest: bool, date: Option<Date>, file: Option<&Path>) -> anyhow::Result<()> {
+	let target = match (latest, date, file) {
+		(true, _, _) => newest_file(args, path)?,
+		(_, Some(date), _) => {
+			let dir = path.join(args.layout.dir(&date, None, &Source::default()));
+
+			if !dir.is_dir() {
+				anyhow::bail!("there are no screenshots from {date}");
+			}
+
+			dir
+		}
+		(_, _, Some(file)) if file.exists() => file.to_path_buf(),
+		(_, _, Some(file)) if path.join(file).exists() => path.join(file),
+		(_, _, Some(file)) => anyhow::bail!("\"{}\" does not exist", file.display()),
+		_ => unreachable!(), // clap requires one of them
+	};
+
+	info!("Open \"{}\"", target.display());
+	platform::open(&target)?;
+
+	Ok(())
+}
+
+fn delete(
+	args: &Args,
+	path: &Path,
+	file: Option<&Path>,
+	date: Option<Date>,
+	older_than: Option<Duration>,
+) -> anyhow::Result<()> {
+	let files = match (file, date, older_than) {
+		(Some(file), _, _) => vec![tree_file(path, file)?],
+		(_, Some(date), _) => {
+			let dir = path.join(args.layout.dir(&date, None, &Source::default()));
+
+			if !dir.is_dir() {
+				anyhow::bail!("there are no screenshots from {date}");
+			}
+
+			let mut files = vec![];
+			tree_files(&dir, &mut files)?;
+			files.retain(|f| is_screenshot(f));
+			files
+		}
+		(_, _, Some(age)) => expired(args, path, age)?,
+		_ => unreachable!(), // clap requires one of them
+	};
+
+	for file in &files {
+		trash_file(path, file)?;
+		remove_empty_parents(path, file)?;
+	}
+
+	update_latest(args, path)?;
+
+	info!("Moved {} files to the trash", files.len());
+
+	Ok(())
+}
+
+/// Moves `file` to the trash along with its sidecar, and forgets its tags
+/// Screenshots not modified for `age`, but those starred. Those survive pruning and only go when named
+fn expired(args: &Args, path: &Path, age: Duration) -> anyhow::Result<Vec<PathBuf>> {
+	let cutoff = SystemTime::now() - age;
+	let tags = tags::read(path)?;
+	let starred = |f: &PathBuf| tags.get(f).is_some_and(|t| t.contains(tags::STAR));
+
+	let mut files = organized_files(args, path)?;
+	files.retain(|f| !starred(f) && f.metadata().and_then(|m| m.modified()).is_ok_and(|m| m < cutoff));
+
+	Ok(files)
+}
+
+pub fn trash_file(path: &Path, file: &Path) -> anyhow::Result<()> {
+	info!("Trash \"{}\"", file.display());
+	trash::put(file).map_err(|e| anyhow::anyhow!("trashing \"{}\": {e}", file.display()))?;
+
+	let sidecar = sidecar::path(file);
+
+	if sidecar.is_file() {
+		trash::put(&sidecar)?;
+	}
+
+	tags::removed(path, file)?;
+	checksums::removed(file)?;
+
+	Ok(())
+}
+
+fn bursts(args: &Args, path: &Path, within: Duration, keep: Option<Keep>, interactive: bool) -> anyhow::Result<()> {
+	let mut files = vec![];
+
+	for file in organized_files(args, path)? {
+		if metadata::image_type(&file)?.is_none() {
+			continue;
+		}
+
+		let taken = file.metadata()?.modified()?;
+
+		match bursts::fingerprint(&file) {
+			Ok(hash) => files.push((taken, hash, file)),
+			Err(e) => Event::Error { path: Some(&file), message: e.to_string() }.emit(),
+		}
+	}
+
+	let bursts = bursts::group(files, within);
+	let mut trashed = 0;
+
+	for burst in &bursts {
+		println!("Burst of {} in \"{}\":", burst.len(), burst[0].parent().unwrap_or(path).display());
+
+		for file in burst {
+			println!("  {}", file.file_name().unwrap_or_default().to_string_lossy());
+		}
+
+		let keep = match (keep, interactive) {
+			(Some(keep), _) => keep,
+			(None, true) => match ask("Keep only the last of them? [y,n,q] ")?.as_str() {
+				"y" => Keep::Last,
+				"q" => break,
+				_ => continue,
+			},
+			(None, false) => continue,
+		};
+
+		let kept = if keep == Keep::First { 0 } else { burst.len() - 1 };
+
+		for (_, file) in burst.iter().enumerate().filter(|&(i, _)| i != kept) {
+			trash_file(path, file)?;
+			remove_empty_parents(path, file)?;
+			trashed += 1;
+		}
+	}
+
+	info!("Found {} bursts, moved {trashed} files to the trash", bursts.len());
+
+	if trashed > 0 {
+		update_latest(args, path)?;
+	}
+
+	Ok(())
+}
+
+/// Asks `question` on the terminal, the answer is what comes before the newline. Nothing is an answer too
+fn ask(question: &str) -> anyhow::Result<String> {
+	print!("{question}");
+	io::stdout().flush()?;
+
+	let mut answer = String::new();
+	io::stdin().read_line(&mut answer)?;
+
+	Ok(answer.trim().to_owned())
+}
+
+fn scrub(args: &Args, path: &Path, add_missing: bool) -> anyhow::Result<()> {
+	let (mut problems, mut checked, mut added) = (vec![], 0, 0);
+	let mut dirs = organized_dirs(args, path)?;
+
+	while let Some(dir) = dirs.pop() {
+		let manifest = checksums::read(&dir)?;
+
+		for (name, expected) in &manifest {
+			let file = dir.join(name);
+			checked += 1;
+
+			match checksums::Checksum::of(&file) {
+				Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+					problems.push(format!("\"{}\" is missing", file.display()))
+				}
+				Err(e) => problems.push(format!("\"{}\" can't be read: {e}", file.display())),
+				Ok(actual) if actual.size < expected.size => problems.push(format!(
+					"\"{}\" is truncated, {} of {} bytes are left",
+					file.display(),
+					actual.size,
+					expected.size
+				)),
+				Ok(actual) if actual.size != expected.size => problems.push(format!(
+					"\"{}\" grew from {} to {} bytes",
+					file.display(),
+					expected.size,
+					actual.size
+				)),
+				Ok(actual) if actual.hash != expected.hash => {
+					problems.push(format!("\"{}\" is corrupted, its contents don't match its checksum", file.display()))
+				}
+				Ok(_) => (),
+			}
+		}
+
+		for entry in fs::read_dir(&dir)? {
+			let file = entry?.path();
+			let name = file.file_name().unwrap_or_default().to_string_lossy().into_owned();
+
+			if file.is_dir() {
+				dirs.push(file);
+			} else if add_missing && !manifest.contains_key(&name) && is_screenshot(&file) {
+				info!("Checksum \"{}\"", file.display());
+				checksums::add(&file)?;
+				added += 1;
+			}
+		}
+	}
+
+	for problem in &problems {
+		println!("{problem}");
+	}
+
+	info!("Checked {checked} files, added {added} checksums");
+
+	match problems.len() {
+		0 => {
+			println!("No problems found in \"{}\"", path.display());
+			Ok(())
+		}
+		1 => anyhow::bail!("1 problem found"),
+		n => anyhow::bail!("{n} problems found"),
+	}
+}
+
+/// The screenshot modified last in the folder "latest" points to
+fn newest_file(args: &Args, path: &Path) -> anyhow::Result<PathBuf> {
+	let link = path.join(&args.latest_name);
+
+	// Copies and pointer files stand in for links where there are none, they know the real folder
+	let dir = match fs::read_to_string(pointer_file(&link)) {
+		Ok(target) => link.parent().unwrap_or(path).join(target.trim()),
+		Err(_) => link,
+	};
+
+	if !dir.is_dir() {
+		anyhow::bail!("there are no screenshots yet");
+	}
+
+	let mut files = vec![];
+	tree_files(&dir, &mut files)?;
+
+	files
+		.into_iter()
+		.filter(|f| is_screenshot(f))
+		.filter_map(|f| Some((f.metadata().and_then(|m| m.modified()).ok()?, f)))
+		.max()
+		.map(|(_, f)| f)
+		.ok_or_else(|| anyhow::anyhow!("\"{}\" is empty", dir.display()))
+}
+
+fn health(args: &Args, path: &Path) -> anyhow::Result<()> {
+	let mut problems = vec![];
+
+	match watching_instance(path) {
+		Ok(Some(pid)) => info!("Watched by pid {pid}"),
+		Ok(None) => problems.push("no instance is watching it".to_owned()),
+		Err(e) => problems.push(format!("checking for a watching instance: {e}")),
+	}
+
+	problems.extend(health_problems(args, path));
+
+	if !problems.is_empty() {
+		anyhow::bail!("\"{}\" is unhealthy: {}", path.display(), problems.join(", "));
+	}
+
+	info!("\"{}\" is healthy", path.display());
+
+	Ok(())
+}
+
+/// What keeps a watched directory from working as it should, besides the watcher itself
+pub fn health_problems(args: &Args, path: &Path) -> Vec<String> {
+	let mut problems = vec![];
+
+	if let Err(e) = fs::read_dir(path) {
+		problems.push(format!("\"{}\" can't be read: {e}", path.display()));
+	}
+
+	check_links(args, path, &mut problems);
+
+	if let Some(free) = space::low(args, path) {
+		problems.push(format!("only {} free in \"{}\"", format_size(free), path.display()));
+	}
+
+	problems
+}
+
+fn check_links(args: &Args, path: &Path, problems: &mut Vec<String>) {
+	for link in args.link_names().map(|l| path.join(l)) {
+		let pointer = pointer_file(&link);
+
+		if link.is_symlink() && !link.exists() {
+			problems.push(format!("\"{}\" points to \"{}\", which does not exist", link.display(), resolve(&link)));
+		} else if let Ok(target) = fs::read_to_string(&pointer) {
+			if !link.parent().unwrap_or(path).join(&target).is_dir() {
+				problems.push(format!("\"{}\" names \"{target}\", which does not exist", pointer.display()));
+			}
+		} else if link.symlink_metadata().is_ok() && !link.is_symlink() {
+			problems.push(format!("\"{}\" is not a link", link.display()));
+		}
+	}
+}
+
+fn resolve(link: &Path) -> String {
+	fs::read_link(link).map(|t| t.display().to_string()).unwrap_or_default()
+}
+
+/// Checks `dir`, `depth` levels below the screenshot directory `path`
+fn check_dir(args: &Args, path: &Path, dir: &Path, depth: usize, problems: &mut Vec<String>) -> anyhow::Result<()> {
+	for entry in fs::read_dir(dir)? {
+		let entry = entry?;
+		let (file, name) = (entry.path(), entry.file_name().to_string_lossy().into_owned());
+
+		if file.is_dir() {
+			if depth == 0 && (args.link_names().contains(&name.as_str()) || name == STARRED || name.starts_with('.')) {
+				continue; // Checked separately, or hidden state
+			}
+
+			if depth == 0 && args.is_reserved(&name) {
+				let mut files = vec![];
+				tree_files(&file, &mut files)?;
+
+				for file in files {
+					check_file(args, path, &file, problems)?;
+				}
+			} else if args.layout.folder_key(depth, &name).is_some() {
+				check_dir(args, path, &file, depth + 1, problems)?;
+			} else {
+				problems.push(format!("\"{}\" is not a dated folder", file.display()));
+			}
+		} else if depth > 0 || args.mode == Mode::Move {
+			// Other modes leave the originals in the screenshot directory
+			check_file(args, path, &file, problems)?;
+		}
+	}
+
+	Ok(())
+}
+
+fn check_file(args: &Args, path: &Path, file: &Path, problems: &mut Vec<String>) -> anyhow::Result<()> {
+	match destination(args, path, file)? {
+		Some(to) if file.parent() != Some(path.join(&to).as_path()) => {
+			problems.push(format!("\"{}\" belongs in \"{}\"", file.display(), path.join(to).display()));
+		}
+		_ => (),
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use clap::Parser;
+
+	use super::*;
+
+	#[test]
+	fn expired_spares_starred_files() {
+		let root = std::env::temp_dir().join(format!("screenshot-manager-expired-{}", std::process::id()));
+		let day = root.join("2024/05/01");
+		fs::create_dir_all(&day).unwrap();
+
+		let old = SystemTime::now() - Duration::from_secs(60 * 60 * 24 * 60);
+		let [plain, starred, recent] = ["plain.png", "starred.png", "recent.png"].map(|name| day.join(name));
+
+		for (file, modified) in [(&plain, old), (&starred, old), (&recent, SystemTime::now())] {
+			fs::File::create(file).unwrap().set_modified(modified).unwrap();
+		}
+
+		let tags = tags::Tags::from([(starred.clone(), [tags::STAR.to_owned()].into())]);
+		tags::write(&root, &tags).unwrap();
+
+		let args = Args::parse_from(["screenshot-manager".as_ref(), root.as_os_str()]);
+		let files = expired(&args, &root, Duration::from_secs(60 * 60 * 24 * 30));
+		fs::remove_dir_all(&root).unwrap();
+
+		assert_eq!(files.unwrap(), [plain]);
+	}
+}
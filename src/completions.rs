@@ -0,0 +1,169 @@
+//! Completion scripts for bash, zsh and fish. They ask the program what can follow the words typed so far, it knows
+//! the options from clap and can look up the profiles of the config and the dates of the tree
+
+use std::{collections::BTreeSet, ffi::OsString};
+
+use clap::{Arg, CommandFactory};
+
+use crate::{commands, config, date::Date, open_source, parse_args, source_args, Args, Shell};
+
+const BASH: &str = r#"_screenshot_manager() {
+	local cur="${COMP_WORDS[COMP_CWORD]}" answer
+	mapfile -t answer < <("${COMP_WORDS[0]}" completions bash --complete -- "${COMP_WORDS[@]:0:COMP_CWORD}" 2>/dev/null)
+	COMPREPLY=($(compgen -W "${answer[*]:1}" -- "$cur"))
+
+	if [[ ${answer[0]} == files ]]; then
+		compopt -o filenames
+		COMPREPLY+=($(compgen -f -- "$cur"))
+	fi
+}
+
+complete -F _screenshot_manager screenshot-manager
+"#;
+
+const ZSH: &str = r#"#compdef screenshot-manager
+
+_screenshot_manager() {
+	local -a answer
+	answer=("${(@f)$(${words[1]} completions zsh --complete -- "${(@)words[1,CURRENT-1]}" 2>/dev/null)}")
+	compadd -- ${answer[2,-1]}
+	[[ $answer[1] == files ]] && _files
+}
+
+if [[ $funcstack[1] == _screenshot_manager ]]; then
+	_screenshot_manager "$@"
+else
+	compdef _screenshot_manager screenshot-manager
+fi
+"#;
+
+const FISH: &str = r#"function __screenshot_manager_complete
+	set -l words (commandline -opc)
+	set -l answer ($words[1] completions fish --complete -- $words 2>/dev/null)
+	set -l kind $answer[1]
+	set -e answer[1]
+	printf '%s\n' $answer
+
+	if test "$kind" = files
+		__fish_complete_path (commandline -ct)
+	end
+end
+
+complete -c screenshot-manager -f -a '(__screenshot_manager_complete)'
+"#;
+
+pub fn script(shell: Shell) -> &'static str {
+	match shell {
+		Shell::Bash => BASH,
+		Shell::Zsh => ZSH,
+		Shell::Fish => FISH,
+	}
+}
+
+/// Prints what can follow `words`, which start with the program: "files" if file names can or "values" if not, then
+/// an option, command or value per line. The shell picks those starting like the word being typed
+pub fn complete(words: &[String]) {
+	let mut root = Args::command();
+	root.build();
+
+	let mut command = &root;
+	// Words before the command, those name the screenshot directory and the tree
+	let mut top = words.len();
+	// The option the word being typed is the value of, and where it is
+	let mut expecting: Option<(&Arg, usize)> = None;
+	let mut positionals = 0;
+
+	for (i, word) in words.iter().enumerate().skip(1) {
+		// Bash splits "--option=value" into three words
+		if word == "=" || expecting.take().is_some() {
+			continue;
+		}
+
+		let option = match (word.strip_prefix("--"), word.strip_prefix('-').map(|s| s.chars().collect::<Vec<_>>())) {
+			(Some(long), _) => command.get_arguments().find(|a| a.get_long() == Some(long)),
+			(None, Some(short)) if short.len() == 1 => {
+				command.get_arguments().find(|a| a.get_short() == Some(short[0]))
+			}
+			(None, Some(_)) => None,
+			(None, None) => {
+				match command.find_subcommand(word) {
+					Some(sub) => {
+						top = top.min(i);
+						command = sub;
+						positionals = 0;
+					}
+					None => positionals += 1,
+				}
+
+				continue;
+			}
+		};
+
+		expecting = option.filter(|a| a.get_action().takes_values()).map(|a| (a, i));
+	}
+
+	let given: Vec<OsString> = words[..top.min(expecting.map_or(top, |(_, i)| i))].iter().map(Into::into).collect();
+
+	let (files, values) = match expecting {
+		Some((arg, _)) => values(arg, &given),
+		None => {
+			let (files, mut values) = match command.get_positionals().nth(positionals) {
+				Some(arg) => values(arg, &given),
+				None => (false, vec![]),
+			};
+
+			let options = command.get_arguments().filter(|a| !a.is_hide_set()).filter_map(|a| a.get_long());
+			values.extend(options.map(|long| format!("--{long}")));
+			values.extend(command.get_subcommands().filter(|c| !c.is_hide_set()).map(|c| c.get_name().to_owned()));
+
+			(files, values)
+		}
+	};
+
+	println!("{}", if files { "files" } else { "values" });
+
+	for value in values {
+		println!("{value}");
+	}
+}
+
+/// Values `arg` can have, and whether file names can be one. `given` are the options before it
+fn values(arg: &Arg, given: &[OsString]) -> (bool, Vec<String>) {
+	let possible: Vec<_> =
+		arg.get_possible_values().iter().filter(|v| !v.is_hide_set()).map(|v| v.get_name().to_owned()).collect();
+
+	if !possible.is_empty() {
+		return (false, possible);
+	}
+
+	match arg.get_id().as_str() {
+		"profile" => (false, profiles(given)),
+		"date" => (false, dates(given, |date| date.to_string())),
+		"month" => (false, dates(given, |date| format!("{:04}-{:02}", date.year, date.month))),
+		_ => (true, vec![]),
+	}
+}
+
+/// Names of the profiles in the --config of `given`
+fn profiles(given: &[OsString]) -> Vec<String> {
+	let config = parse_args(given, &[], None).ok().and_then(|args| args.config);
+	config.and_then(|config| config::profiles(&config).ok()).unwrap_or_default()
+}
+
+/// Days or months there are folders for in the tree of `given`, newest first
+fn dates(given: &[OsString], format: impl Fn(&Date) -> String) -> Vec<String> {
+	let (args, _, tree) = open_source(source_args(given, None, &[], None));
+	let files = commands::organized_files(&args, &tree).unwrap_or_default();
+
+	let dates: BTreeSet<_> = files
+		.iter()
+		.filter_map(|file| file.parent()?.strip_prefix(&tree).ok())
+		.filter_map(|dir| args.layout.folder_date(dir))
+		.map(|(date, _)| date)
+		.collect();
+
+	let mut values: Vec<_> = dates.iter().rev().map(format).collect();
+	values.dedup();
+
+	values
+}
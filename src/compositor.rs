@@ -0,0 +1,127 @@
+//! Asking the Wayland compositor what was on screen, over the IPC sockets of Sway and Hyprland
+
+use std::io;
+#[cfg(unix)]
+use std::{env, path::PathBuf};
+
+#[cfg(unix)]
+use crate::json::{self, Value};
+
+/// The focused window when a screenshot came in
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Window {
+	pub title: Option<String>,
+	pub workspace: Option<String>,
+}
+
+/// The focused window of the compositor this runs under, `None` without a supported one
+#[cfg(unix)]
+pub fn focused() -> io::Result<Option<Window>> {
+	if let Some(socket) = env::var_os("SWAYSOCK") {
+		return sway(&PathBuf::from(socket)).map(Some);
+	}
+
+	if let Some(signature) = env::var_os("HYPRLAND_INSTANCE_SIGNATURE") {
+		// Moved to the runtime directory in 0.40, /tmp before that
+		let runtime = env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from).unwrap_or_default();
+		let socket = [runtime, PathBuf::from("/tmp")]
+			.into_iter()
+			.map(|dir| dir.join("hypr").join(&signature).join(".socket.sock"))
+			.find(|s| s.exists())
+			.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Hyprland socket not found"))?;
+
+		return hyprland(&socket).map(Some);
+	}
+
+	Ok(None)
+}
+
+#[cfg(windows)]
+pub fn focused() -> io::Result<Option<Window>> {
+	Ok(None)
+}
+
+#[cfg(unix)]
+fn invalid(what: &str) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, format!("unexpected {what} from the compositor"))
+}
+
+/// Asks for the whole tree with i3's GET_TREE and looks for the focused node in it
+#[cfg(unix)]
+fn sway(socket: &std::path::Path) -> io::Result<Window> {
+	use std::{
+		io::{Read, Write},
+		os::unix::net::UnixStream,
+	};
+
+	const MAGIC: &[u8] = b"i3-ipc";
+	const GET_TREE: u32 = 4;
+
+	let mut stream = UnixStream::connect(socket)?;
+	stream.set_read_timeout(Some(std::time::Duration::from_secs(1)))?;
+
+	// Magic, payload length and message type, the last two in native byte order
+	let mut request = MAGIC.to_vec();
+	request.extend(0u32.to_ne_bytes());
+	request.extend(GET_TREE.to_ne_bytes());
+	stream.write_all(&request)?;
+
+	let mut header = [0; 14];
+	stream.read_exact(&mut header)?;
+
+	if &header[..6] != MAGIC {
+		return Err(invalid("reply header"));
+	}
+
+	let len = u32::from_ne_bytes(header[6..10].try_into().unwrap()); // Four bytes
+	let mut payload = vec![0; len as usize];
+	stream.read_exact(&mut payload)?;
+
+	let tree = json::parse(&String::from_utf8_lossy(&payload)).ok_or_else(|| invalid("tree"))?;
+
+	Ok(focused_node(&tree, None).unwrap_or_default())
+}
+
+/// The focused node below `node`, with the workspace it is on
+#[cfg(unix)]
+fn focused_node(node: &Value, workspace: Option<&str>) -> Option<Window> {
+	let name = node.get("name").and_then(Value::as_str);
+	let is_workspace = node.get("type").and_then(Value::as_str) == Some("workspace");
+	let workspace = if is_workspace { name } else { workspace };
+
+	if node.get("focused").and_then(Value::as_bool) == Some(true) {
+		// An empty workspace is focused itself, there is no window then
+		let title = name.filter(|_| !is_workspace).map(str::to_owned);
+		return Some(Window { title, workspace: workspace.map(str::to_owned) });
+	}
+
+	["nodes", "floating_nodes"]
+		.iter()
+		.filter_map(|key| node.get(key))
+		.flat_map(Value::elements)
+		.find_map(|child| focused_node(child, workspace))
+}
+
+/// Asks for the active window, as JSON with the "j/" prefix
+#[cfg(unix)]
+fn hyprland(socket: &std::path::Path) -> io::Result<Window> {
+	use std::{
+		io::{Read, Write},
+		os::unix::net::UnixStream,
+	};
+
+	let mut stream = UnixStream::connect(socket)?;
+	stream.set_read_timeout(Some(std::time::Duration::from_secs(1)))?;
+	stream.write_all(b"j/activewindow")?;
+
+	let mut reply = String::new();
+	stream.read_to_string(&mut reply)?;
+
+	let window = json::parse(&reply).ok_or_else(|| invalid("active window"))?;
+	let text = |v: Option<&Value>| v.and_then(Value::as_str).filter(|s| !s.is_empty()).map(str::to_owned);
+
+	Ok(Window {
+		title: text(window.get("title")),
+		workspace: text(window.get("workspace").and_then(|w| w.get("name"))),
+	})
+}
@@ -0,0 +1,337 @@
+//! Config files for watching several screenshot directories, each a `[[source]]` with its own options, and those
+//! kept in a screenshot directory for it alone. Options are written like TOML and given like on the command line, so
+//! `layout = "{year}/{month}"` is `--layout {year}/{month}`. Environment variables like `SCREENSHOT_MANAGER_LAYOUT`
+//! can give them too
+
+use std::{collections::BTreeMap, fs, io, path::Path};
+
+use clap::ArgAction;
+
+/// Options a screenshot directory has for itself, over those of the config
+pub const DIR_CONFIG: &str = ".screenshot-manager.toml";
+
+/// What environment variables with options start with, the rest is the option in capitals with "_" for "-"
+const ENV_PREFIX: &str = "SCREENSHOT_MANAGER_";
+
+/// Environment variable with the screenshot directory, for when the command line has none
+pub const ENV_DIR: &str = "SCREENSHOT_MANAGER_DIR";
+
+/// A value of the config, as far as options need them
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+	Text(String),
+	Bool(bool),
+	List(Vec<String>),
+}
+
+/// Options of one screenshot directory, as command line arguments
+#[derive(Clone, Debug)]
+pub struct Source {
+	pub path: String,
+	pub args: Vec<String>,
+}
+
+/// A config as written, before a profile is chosen
+#[derive(Default)]
+struct Config {
+	/// Options before the first section
+	shared: Vec<String>,
+	/// Options of each `[profile.NAME]`, by its name
+	profiles: BTreeMap<String, Vec<String>>,
+	sources: Vec<Section>,
+}
+
+/// A `[[source]]`, or a `[[profile.NAME.source]]` of the profile
+struct Section {
+	profile: Option<String>,
+	path: Option<String>,
+	args: Vec<String>,
+}
+
+/// Reads the sources of the config `file`, with those of `profile`. Options before the first section are given to
+/// all of them, then those of the profile
+pub fn read(file: &Path, profile: Option<&str>) -> anyhow::Result<Vec<Source>> {
+	let contents = fs::read_to_string(file).map_err(|e| anyhow::anyhow!("reading \"{}\": {e}", file.display()))?;
+	let in_file = |e| anyhow::anyhow!("in \"{}\": {e}", file.display());
+	let mut config = parse(&contents).map_err(in_file)?;
+
+	let mut options = config.shared;
+
+	if let Some(profile) = profile {
+		let Some(own) = config.profiles.remove(profile) else {
+			let names: Vec<_> = config.profiles.keys().map(String::as_str).collect();
+			let known = if names.is_empty() { "none".to_owned() } else { names.join(", ") };
+			anyhow::bail!("there is no profile \"{profile}\" in \"{}\", it has {known}", file.display());
+		};
+
+		options.extend(own);
+	}
+
+	config.sources.retain(|s| s.profile.is_none() || s.profile.as_deref() == profile);
+
+	if config.sources.is_empty() {
+		let of = profile.map(|p| format!(" or [[profile.{p}.source]]")).unwrap_or_default();
+		anyhow::bail!("there is no [[source]]{of} in \"{}\"", file.display());
+	}
+
+	config
+		.sources
+		.into_iter()
+		.enumerate()
+		.map(|(i, section)| {
+			let path = section.path.ok_or_else(|| in_file(format!("source {} has no \"path\"", i + 1)))?;
+			Ok(Source { path, args: options.iter().cloned().chain(section.args).collect() })
+		})
+		.collect()
+}
+
+/// Names of the profiles of the config `file`
+pub fn profiles(file: &Path) -> anyhow::Result<Vec<String>> {
+	let contents = fs::read_to_string(file)?;
+	let config = parse(&contents).map_err(|e| anyhow::anyhow!("in \"{}\": {e}", file.display()))?;
+
+	Ok(config.profiles.into_keys().collect())
+}
+
+/// The options in the `DIR_CONFIG` of the screenshot directory `dir`, if it has one
+pub fn dir_options(dir: &Path) -> anyhow::Result<Option<Vec<String>>> {
+	let file = dir.join(DIR_CONFIG);
+	let contents = match fs::read_to_string(&file) {
+		Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+		res => res.map_err(|e| anyhow::anyhow!("reading \"{}\": {e}", file.display()))?,
+	};
+
+	match parse(&contents).map_err(|e| anyhow::anyhow!("in \"{}\": {e}", file.display()))? {
+		Config { shared, profiles, sources } if profiles.is_empty() && sources.is_empty() => Ok(Some(shared)),
+		_ => anyhow::bail!("\"{}\" is for the directory it's in, it can't have sources or profiles", file.display()),
+	}
+}
+
+/// Options given in the environment to `command`, as command line arguments. Flags are "true" or "false", and
+/// options that can be given more than once take a list separated by commas
+pub fn env_options(command: &clap::Command) -> Result<Vec<String>, String> {
+	let mut args = vec![];
+
+	for arg in command.get_arguments() {
+		let Some(long) = arg.get_long().filter(|long| !matches!(*long, "help" | "version")) else {
+			continue;
+		};
+
+		let var = format!("{ENV_PREFIX}{}", long.to_uppercase().replace('-', "_"));
+		let Some(value) = std::env::var_os(&var) else {
+			continue;
+		};
+
+		let value = value.into_string().map_err(|_| format!("${var} is not valid Unicode"))?;
+		let flag = format!("--{long}");
+
+		match arg.get_action() {
+			ArgAction::SetTrue => match value.as_str() {
+				"true" | "1" => args.push(flag),
+				"false" | "0" | "" => (),
+				_ => return Err(format!("${var} is \"{value}\", not true or false")),
+			},
+			ArgAction::Append => {
+				let items = value.split(',').filter(|item| !item.is_empty());
+				args.extend(items.flat_map(|item| [flag.clone(), item.to_owned()]));
+			}
+			_ => args.extend([flag, value]),
+		}
+	}
+
+	Ok(args)
+}
+
+/// Environment variables that look like they are for `command` but name none of its options, like misspelled ones
+pub fn unknown_env(command: &clap::Command) -> Vec<String> {
+	let known: Vec<_> = command.get_arguments().filter_map(|a| a.get_long()).map(|l| l.replace('-', "_")).collect();
+
+	std::env::vars_os()
+		.filter_map(|(var, _)| var.into_string().ok())
+		.filter(|var| {
+			var.strip_prefix(ENV_PREFIX)
+				.is_some_and(|option| var != ENV_DIR && !known.iter().any(|k| k.eq_ignore_ascii_case(option)))
+		})
+		.collect()
+}
+
+/// The options and sources of a config
+fn parse(contents: &str) -> Result<Config, String> {
+	let mut config = Config::default();
+	// The `[profile.NAME]` options are given in, if they aren't in the last source
+	let mut profile: Option<String> = None;
+
+	for (n, line) in contents.lines().enumerate() {
+		let line = strip_comment(line).trim();
+		let at = |e: String| format!("line {}: {e}", n + 1);
+
+		if line.is_empty() {
+			continue;
+		}
+
+		if line.starts_with('[') {
+			let of_profile = |name: &str| name.strip_prefix("profile.").filter(|name| is_key(name)).map(str::to_owned);
+			let array = line.strip_prefix("[[").and_then(|l| l.strip_suffix("]]"));
+			let table = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')).filter(|_| array.is_none());
+			let source_of = array.and_then(|a| a.strip_suffix(".source")).and_then(of_profile);
+
+			if array == Some("source") || source_of.is_some() {
+				if let Some(name) = &source_of {
+					config.profiles.entry(name.clone()).or_default();
+				}
+
+				config.sources.push(Section { profile: source_of, path: None, args: vec![] });
+				profile = None;
+			} else if let Some(name) = table.and_then(of_profile) {
+				config.profiles.entry(name.clone()).or_default();
+				profile = Some(name);
+			} else {
+				let known = "only [[source]], [profile.NAME] and [[profile.NAME.source]] are";
+				return Err(at(format!("unknown section \"{line}\", {known}")));
+			}
+
+			continue;
+		}
+
+		let (key, value) =
+			line.split_once('=').ok_or_else(|| at(format!("expected \"key = value\", got \"{line}\"")))?;
+		let (key, value) = (key.trim(), parse_value(value.trim()).map_err(at)?);
+
+		if !is_key(key) {
+			return Err(at(format!("\"{key}\" is not a key")));
+		}
+
+		let section = config.sources.last_mut().filter(|_| profile.is_none());
+
+		match (section, key, value) {
+			(Some(section), "path", Value::Text(p)) => section.path = Some(p),
+			(_, "path", _) => return Err(at("\"path\" belongs in a source and is a string".to_owned())),
+			(section, key, value) => {
+				let args = match (section, &profile) {
+					(Some(section), _) => &mut section.args,
+					(None, Some(name)) => config.profiles.get_mut(name).unwrap(), // Added with its section
+					(None, None) => &mut config.shared,
+				};
+				let flag = format!("--{}", key.replace('_', "-"));
+
+				match value {
+					Value::Bool(true) => args.push(flag),
+					Value::Bool(false) => (),
+					Value::Text(text) => args.extend([flag, text]),
+					Value::List(items) => args.extend(items.into_iter().flat_map(|item| [flag.clone(), item])),
+				}
+			}
+		}
+	}
+
+	Ok(config)
+}
+
+fn is_key(key: &str) -> bool {
+	!key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// `line` without a `#` comment, `#` in strings are kept
+fn strip_comment(line: &str) -> &str {
+	let mut quote = None;
+	let mut escaped = false;
+
+	for (i, c) in line.char_indices() {
+		match (quote, c) {
+			(Some('"'), '\\') if !escaped => {
+				escaped = true;
+				continue;
+			}
+			(Some(q), c) if c == q && !escaped => quote = None,
+			(None, '"' | '\'') => quote = Some(c),
+			(None, '#') => return &line[..i],
+			_ => (),
+		}
+
+		escaped = false;
+	}
+
+	line
+}
+
+fn parse_value(s: &str) -> Result<Value, String> {
+	match s {
+		"true" => return Ok(Value::Bool(true)),
+		"false" => return Ok(Value::Bool(false)),
+		_ => (),
+	}
+
+	if let Some(items) = s.strip_prefix('[') {
+		let items = items.strip_suffix(']').ok_or_else(|| format!("unclosed list \"{s}\""))?;
+		let mut list = vec![];
+		let mut rest = items.trim();
+
+		while !rest.is_empty() {
+			let (item, after) = parse_string(rest)?;
+			list.push(item);
+
+			rest = after.trim_start();
+			rest = match rest.strip_prefix(',') {
+				Some(after) => after.trim_start(),
+				None if rest.is_empty() => rest,
+				None => return Err(format!("expected \",\" between the strings of \"{s}\"")),
+			};
+		}
+
+		return Ok(Value::List(list));
+	}
+
+	if s.starts_with(['"', '\'']) {
+		return match parse_string(s)? {
+			(text, "") => Ok(Value::Text(text)),
+			(_, rest) => Err(format!("unexpected \"{rest}\" after a string")),
+		};
+	}
+
+	// Numbers are taken as they are, like sizes and counts on the command line
+	if !s.is_empty() && s.chars().all(|c| c.is_ascii_digit()) {
+		return Ok(Value::Text(s.to_owned()));
+	}
+
+	Err(format!("\"{s}\" is not a string, number, boolean or list of strings"))
+}
+
+/// The string `s` starts with, and what follows it. Basic strings in double quotes have escapes, literal ones in
+/// single quotes don't
+fn parse_string(s: &str) -> Result<(String, &str), String> {
+	let mut chars = s.char_indices();
+
+	let quote = match chars.next() {
+		Some((_, q @ ('"' | '\''))) => q,
+		_ => return Err(format!("expected a string, got \"{s}\"")),
+	};
+
+	let mut text = String::new();
+
+	while let Some((i, c)) = chars.next() {
+		match c {
+			c if c == quote => return Ok((expand_home(text), &s[i + 1..])),
+			'\\' if quote == '"' => match chars.next().map(|(_, c)| c) {
+				Some('"') => text.push('"'),
+				Some('\\') => text.push('\\'),
+				Some('n') => text.push('\n'),
+				Some('t') => text.push('\t'),
+				Some(c) => return Err(format!("unknown escape \"\\{c}\" in {s}")),
+				None => break,
+			},
+			c => text.push(c),
+		}
+	}
+
+	Err(format!("unclosed string {s}"))
+}
+
+/// `text` with a leading "~/" standing for the home folder, as the shell would have it on the command line
+fn expand_home(text: String) -> String {
+	let home = std::env::var("HOME").or_else(|_| std::env::var("USERPROFILE"));
+
+	match (text.strip_prefix("~/"), home) {
+		(Some(rest), Ok(home)) => Path::new(&home).join(rest).to_string_lossy().into_owned(),
+		_ => text,
+	}
+}
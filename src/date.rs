@@ -0,0 +1,106 @@
+//! Just enough calendar arithmetic to turn points in time into folder names
+
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+	pub year: i32,
+	pub month: u32,
+	pub day: u32,
+}
+
+impl Date {
+	/// Date `days` days after 1970-01-01, see <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>
+	pub fn from_days(days: i64) -> Date {
+		let z = days + 719468;
+		let era = z.div_euclid(146097);
+		let doe = z.rem_euclid(146097);
+		let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+		let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+		let mp = (5 * doy + 2) / 153;
+		let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+		let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+		let year = (yoe + era * 400 + i64::from(month <= 2)) as i32;
+
+		Date { year, month, day }
+	}
+
+	/// Inverse of [`Date::from_days`]
+	pub fn days(&self) -> i64 {
+		let year = i64::from(self.year) - i64::from(self.month <= 2);
+		let month = i64::from(self.month);
+		let era = year.div_euclid(400);
+		let yoe = year.rem_euclid(400);
+		let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(self.day) - 1;
+		let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+		era * 146097 + doe - 719468
+	}
+
+	/// Parses a "YYYY-MM-DD" date that exists
+	pub fn parse(s: &str) -> Result<Date, String> {
+		let invalid = || format!("\"{s}\" is not a date like 2024-05-01");
+		let parts: Vec<u32> = s.split('-').map(|p| p.parse().ok()).collect::<Option<_>>().ok_or_else(invalid)?;
+		let [year, month, day] = parts[..] else {
+			return Err(invalid());
+		};
+		let date = Date { year: year as i32, month, day };
+
+		if !date.exists() {
+			return Err(invalid());
+		}
+
+		Ok(date)
+	}
+
+	/// Whether there is such a day, not a month 13 or a February 30th
+	pub fn exists(&self) -> bool {
+		// Days past the end of the month roll over into the next one
+		(1..=12).contains(&self.month) && self.day >= 1 && Date::from_days(self.days()) == *self
+	}
+
+	/// Parses a "YYYY-MM" month into its first day
+	pub fn parse_month(s: &str) -> Result<Date, String> {
+		Date::parse(&format!("{s}-01")).map_err(|_| format!("\"{s}\" is not a month like 2024-05"))
+	}
+
+	/// ISO 8601 week-numbering year and week, weeks start on Monday and belong to the year their Thursday is in
+	pub fn iso_week(&self) -> (i32, u32) {
+		let days = self.days();
+		// 1970-01-01 was a Thursday, day 4 counting from Monday as 1
+		let weekday = (days + 3).rem_euclid(7) + 1;
+		let thursday = Date::from_days(days + 4 - weekday);
+		let jan1 = Date { year: thursday.year, month: 1, day: 1 };
+
+		(thursday.year, ((thursday.days() - jan1.days()) / 7 + 1) as u32)
+	}
+}
+
+impl fmt::Display for Date {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Time {
+	pub hour: u32,
+	pub minute: u32,
+	pub second: u32,
+}
+
+impl Time {
+	/// Whether it is a time of day, not a 25:61
+	pub fn exists(&self) -> bool {
+		self.hour < 24 && self.minute < 60 && self.second < 60
+	}
+
+	pub fn from_secs(secs: u32) -> Time {
+		Time { hour: secs / (60 * 60), minute: secs / 60 % 60, second: secs % 60 }
+	}
+
+	/// Seconds since midnight
+	pub fn secs(&self) -> u32 {
+		self.hour * 60 * 60 + self.minute * 60 + self.second
+	}
+}
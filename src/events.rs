@@ -0,0 +1,191 @@
+//! What happened to files, told as prose or, with --json-events, as one JSON object per line
+
+use std::{
+	io::{self, IsTerminal, Write},
+	path::Path,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Mutex,
+	},
+};
+
+use crate::{json, metrics};
+
+static JSON: AtomicBool = AtomicBool::new(false);
+static QUIET: AtomicBool = AtomicBool::new(false);
+/// Whether lines on stdout and stderr can have colors
+static COLOR_OUT: AtomicBool = AtomicBool::new(false);
+static COLOR_ERR: AtomicBool = AtomicBool::new(false);
+
+/// Connections told about every file sorted
+static SUBSCRIBERS: Mutex<Vec<Box<dyn Write + Send>>> = Mutex::new(vec![]);
+
+pub fn set_json(json: bool) {
+	JSON.store(json, Ordering::Relaxed);
+}
+
+pub fn json() -> bool {
+	JSON.load(Ordering::Relaxed)
+}
+
+/// Leaves out the lines for files with `quiet`, and colors with `no_color` or where they wouldn't show
+pub fn set_style(quiet: bool, no_color: bool) {
+	QUIET.store(quiet, Ordering::Relaxed);
+
+	// See https://no-color.org
+	let color = !no_color && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty());
+	COLOR_OUT.store(color && io::stdout().is_terminal(), Ordering::Relaxed);
+	COLOR_ERR.store(color && io::stderr().is_terminal(), Ordering::Relaxed);
+}
+
+pub fn quiet() -> bool {
+	QUIET.load(Ordering::Relaxed)
+}
+
+#[derive(Clone, Copy)]
+enum Color {
+	Red = 31,
+	Green = 32,
+	Yellow = 33,
+	Cyan = 36,
+}
+
+/// `text` in `color` if the stream it goes to, stderr or not, can show it
+fn paint(text: &str, color: Color, stderr: bool) -> String {
+	match if stderr { &COLOR_ERR } else { &COLOR_OUT }.load(Ordering::Relaxed) {
+		true => format!("\x1b[{}m{text}\x1b[0m", color as u8),
+		false => text.to_owned(),
+	}
+}
+
+/// Accepts subscribers on a Unix socket at `path`, each gets a JSON line for every file sorted from then on
+#[cfg(unix)]
+pub fn serve_subscribers(path: &Path) -> io::Result<()> {
+	use std::os::unix::{fs::FileTypeExt, net::UnixListener};
+
+	// Left behind by a previous run
+	if std::fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+		std::fs::remove_file(path)?;
+	}
+
+	let listener = UnixListener::bind(path)?;
+
+	std::thread::spawn(move || {
+		for stream in listener.incoming().flatten() {
+			// A subscriber that stops reading must not hold up sorting
+			_ = stream.set_write_timeout(Some(std::time::Duration::from_secs(1)));
+			SUBSCRIBERS.lock().unwrap().push(Box::new(stream));
+		}
+	});
+
+	Ok(())
+}
+
+#[cfg(windows)]
+pub fn serve_subscribers(_: &Path) -> io::Result<()> {
+	Err(io::Error::new(io::ErrorKind::Unsupported, "event sockets need Unix domain sockets"))
+}
+
+/// Progress for people, left out of JSON output and with --quiet
+macro_rules! info {
+	($($arg:tt)*) => {
+		if !$crate::events::json() && !$crate::events::quiet() {
+			println!($($arg)*);
+		}
+	};
+}
+
+pub(crate) use info;
+
+pub enum Event<'a> {
+	/// Moved, copied or linked, depending on `action`
+	Moved {
+		action: &'a str,
+		from: &'a Path,
+		to: &'a Path,
+	},
+	Skipped {
+		path: &'a Path,
+		reason: String,
+	},
+	Error {
+		path: Option<&'a Path>,
+		message: String,
+	},
+	/// A link now points to `target`, or was removed
+	LatestUpdated {
+		link: &'a Path,
+		target: Option<&'a Path>,
+	},
+}
+
+impl Event<'_> {
+	pub fn emit(&self) {
+		if let Event::Error { .. } = self {
+			metrics::error();
+		}
+
+		if let Event::Moved { .. } = self {
+			let line = format!("{}\n", self.to_json());
+			// Subscribers that went away are dropped
+			SUBSCRIBERS.lock().unwrap().retain_mut(|s| s.write_all(line.as_bytes()).is_ok());
+		}
+
+		if json() {
+			println!("{}", self.to_json());
+			return;
+		}
+
+		// Warnings and errors are all that's left with --quiet
+		if quiet() && matches!(self, Event::Moved { .. } | Event::LatestUpdated { .. }) {
+			return;
+		}
+
+		match self {
+			Event::Moved { action, from, to } => {
+				println!("{} \"{}\" -> \"{}\"", paint(action, Color::Green, false), from.display(), to.display())
+			}
+			Event::Skipped { path, reason } => {
+				println!("{} \"{}\", {reason}", paint("Skip", Color::Yellow, false), path.display())
+			}
+			Event::Error { path: Some(path), message } => {
+				eprintln!("{} while processing \"{}\": {message}", paint("Error", Color::Red, true), path.display())
+			}
+			Event::Error { path: None, message } => eprintln!("{}: {message}", paint("Error", Color::Red, true)),
+			Event::LatestUpdated { link, target: Some(target) } => {
+				let symlink = paint("Symlink", Color::Cyan, false);
+				println!("{symlink}: \"{}\" -> \"{}\"", target.display(), link.display())
+			}
+			Event::LatestUpdated { link, target: None } => {
+				println!("{} \"{}\"", paint("Remove link", Color::Cyan, false), link.display())
+			}
+		}
+	}
+
+	fn to_json(&self) -> String {
+		let path = |p: &Path| json::string(&p.to_string_lossy());
+		let optional = |p: Option<&Path>| p.map_or("null".to_owned(), path);
+
+		match self {
+			Event::Moved { action, from, to } => format!(
+				"{{\"event\": \"moved\", \"action\": {}, \"from\": {}, \"to\": {}}}",
+				json::string(&action.to_lowercase()),
+				path(from),
+				path(to)
+			),
+			Event::Skipped { path: p, reason } => {
+				format!("{{\"event\": \"skipped\", \"path\": {}, \"reason\": {}}}", path(p), json::string(reason))
+			}
+			Event::Error { path: p, message } => {
+				format!("{{\"event\": \"error\", \"path\": {}, \"message\": {}}}", optional(*p), json::string(message))
+			}
+			Event::LatestUpdated { link, target } => {
+				format!(
+					"{{\"event\": \"latest-updated\", \"link\": {}, \"target\": {}}}",
+					path(link),
+					optional(*target)
+				)
+			}
+		}
+	}
+}
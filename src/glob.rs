@@ -0,0 +1,66 @@
+//! Shell style wildcards for file names like "*.kra" or "wallpaper?.png"
+
+use regex::Regex;
+
+#[derive(Clone, Debug)]
+pub struct Glob {
+	text: String,
+	pattern: Regex,
+}
+
+impl Glob {
+	/// Parses a pattern where `*` matches any run of characters, `?` any single one and `[...]` any character of a
+	/// set, `[!...]` any character not in it
+	pub fn parse(s: &str) -> Result<Glob, String> {
+		let mut pattern = String::from("^");
+		let mut chars = s.chars();
+
+		while let Some(c) = chars.next() {
+			match c {
+				'*' => pattern.push_str(".*"),
+				'?' => pattern.push('.'),
+				'[' => {
+					let mut set = String::new();
+					let mut closed = false;
+
+					for c in chars.by_ref() {
+						match c {
+							']' if !set.is_empty() && set != "^" => {
+								closed = true;
+								break;
+							}
+							'!' | '^' if set.is_empty() => set.push('^'),
+							'\\' | '[' | ']' | '&' | '~' => {
+								set.push('\\');
+								set.push(c);
+							}
+							c => set.push(c),
+						}
+					}
+
+					if !closed {
+						return Err(format!("unclosed \"[\" in \"{s}\""));
+					}
+
+					pattern.push_str(&format!("[{set}]"));
+				}
+				c => pattern.push_str(&regex::escape(&c.to_string())),
+			}
+		}
+
+		pattern.push('$');
+
+		let pattern = Regex::new(&pattern).map_err(|e| format!("invalid pattern \"{s}\": {e}"))?;
+
+		Ok(Glob { text: s.to_owned(), pattern })
+	}
+
+	pub fn matches(&self, name: &str) -> bool {
+		self.pattern.is_match(name)
+	}
+
+	/// The pattern as it was given
+	pub fn as_str(&self) -> &str {
+		&self.text
+	}
+}
@@ -0,0 +1,72 @@
+//! What else happens to new screenshots once they are sorted: --exec, --notify and --upload. And alerts about the
+//! tree itself
+
+use std::{
+	path::Path,
+	process::{Child, Command, Stdio},
+};
+
+use crate::{events::Event, upload, Args};
+
+/// Runs the hooks for the new screenshot `file`, without waiting for them
+pub fn run(args: &Args, file: &Path) {
+	if let Some(command) = &args.exec {
+		spawn(Some(file), "--exec", shell(command).arg(file));
+	}
+
+	if args.notify {
+		let body = file.file_name().unwrap_or_default().to_string_lossy().into_owned();
+		spawn(
+			Some(file),
+			"notify-send",
+			Command::new("notify-send").args(["--app-name", "screenshot-manager"]).args(["Screenshot saved", &body]),
+		);
+	}
+
+	if let Some(target) = &args.upload {
+		upload::start(target, file, args.notify);
+	}
+}
+
+/// Reports a problem with the tree that needs a hand, in an urgent notification too with --notify
+pub fn alert(args: &Args, summary: &str, message: &str) {
+	Event::Error { path: None, message: message.to_owned() }.emit();
+
+	if args.notify {
+		let mut notify_send = Command::new("notify-send");
+		notify_send.args(["--app-name", "screenshot-manager", "--urgency", "critical", summary, message]);
+		spawn(None, "notify-send", &mut notify_send);
+	}
+}
+
+/// `command` run by the shell, the last argument added becomes $1
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+	let mut shell = Command::new("sh");
+	shell.args(["-c", command, "sh"]);
+	shell
+}
+
+/// `command` run by the shell, the last argument added becomes %1
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+	let mut shell = Command::new("cmd");
+	shell.args(["/C", command]);
+	shell
+}
+
+fn spawn(file: Option<&Path>, what: &'static str, command: &mut Command) {
+	match command.stdin(Stdio::null()).spawn() {
+		// Reaped from another thread, so slow hooks don't hold up sorting
+		Ok(child) => _ = std::thread::spawn(move || wait(child, what)),
+		Err(e) => Event::Error { path: file, message: format!("running {what}: {e}") }.emit(),
+	}
+}
+
+fn wait(mut child: Child, what: &str) {
+	match child.wait() {
+		Ok(status) if !status.success() => eprintln!("Error: {what} failed: {status}"),
+		Err(e) => eprintln!("Error waiting for {what}: {e}"),
+		Ok(_) => (),
+	}
+}
@@ -0,0 +1,129 @@
+//! The index of the tree: the width and height of each screenshot, kept in the state folder with the size and time
+//! of the file they were read from. Only files new or changed since are read again
+
+use std::{
+	collections::BTreeMap,
+	fs, io,
+	path::{Path, PathBuf},
+	time::UNIX_EPOCH,
+};
+
+use crate::{
+	commands,
+	journal::{escape, unescape},
+	metadata, Args, Orientation, STATE_DIR,
+};
+
+/// Width and height of an image, in pixels
+pub type Dimensions = (u32, u32);
+
+/// What is recorded of a file: its size and modification time, and its dimensions if it's an image
+type Entry = ((u64, u64), Option<Dimensions>);
+
+fn index_file(root: &Path) -> PathBuf {
+	root.join(STATE_DIR).join("index")
+}
+
+fn read(root: &Path) -> io::Result<BTreeMap<PathBuf, Entry>> {
+	let contents = match fs::read_to_string(index_file(root)) {
+		Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+		res => res?,
+	};
+
+	Ok(contents
+		.lines()
+		.filter_map(|line| {
+			let mut fields = line.split('\t');
+			let file = root.join(unescape(fields.next()?));
+			let stamp = (fields.next()?.parse().ok()?, fields.next()?.parse().ok()?);
+			// Empty for files that aren't images, so they aren't read every time either
+			let dimensions = fields.next()?.parse().ok().zip(fields.next()?.parse().ok());
+
+			Some((file, (stamp, dimensions)))
+		})
+		.collect())
+}
+
+fn write(root: &Path, index: &BTreeMap<PathBuf, Entry>) -> io::Result<()> {
+	let file = index_file(root);
+	fs::create_dir_all(file.parent().unwrap())?; // Always has one
+
+	let contents: String = index
+		.iter()
+		.map(|(path, ((size, modified), dimensions))| {
+			let path = escape(&path.strip_prefix(root).unwrap_or(path).to_string_lossy());
+			let (width, height) =
+				dimensions.map_or((String::new(), String::new()), |(w, h)| (w.to_string(), h.to_string()));
+			format!("{path}\t{size}\t{modified}\t{width}\t{height}\n")
+		})
+		.collect();
+
+	// Like the tags, a crash leaves the old index whole
+	let temp = file.with_extension("partial");
+	fs::write(&temp, contents)?;
+	fs::rename(temp, file)
+}
+
+/// Dimensions of the screenshots of the tree in `root`, `None` for files they can't be read from. Brings the index
+/// up to date first, files gone are left out of it
+pub fn dimensions(args: &Args, root: &Path) -> anyhow::Result<BTreeMap<PathBuf, Option<Dimensions>>> {
+	let old = read(root)?;
+	let mut index = BTreeMap::new();
+
+	for file in commands::organized_files(args, root)? {
+		let Ok(metadata) = file.metadata() else {
+			continue; // Gone meanwhile
+		};
+		let modified = metadata.modified()?.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+		let stamp = (metadata.len(), modified);
+
+		let dimensions = match old.get(&file) {
+			Some(&(known, dimensions)) if known == stamp => dimensions,
+			_ => metadata::dimensions(&file).unwrap_or_default(),
+		};
+
+		index.insert(file, (stamp, dimensions));
+	}
+
+	if index != old {
+		write(root, &index)?;
+	}
+
+	Ok(index.into_iter().map(|(file, (_, dimensions))| (file, dimensions)).collect())
+}
+
+/// Size and shape a search asks screenshots to have
+pub struct Shape {
+	pub min_width: Option<u32>,
+	pub min_height: Option<u32>,
+	pub min_megapixels: Option<f64>,
+	pub orientation: Option<Orientation>,
+}
+
+impl Shape {
+	/// Whether anything is asked, the index isn't needed otherwise
+	pub fn is_any(&self) -> bool {
+		self.min_width.is_some()
+			|| self.min_height.is_some()
+			|| self.min_megapixels.is_some()
+			|| self.orientation.is_some()
+	}
+
+	/// Whether an image of `dimensions` has it. Those without any only fit if nothing is asked
+	pub fn fits(&self, dimensions: Option<Dimensions>) -> bool {
+		let Some((width, height)) = dimensions else {
+			return !self.is_any();
+		};
+
+		let orientation = match width.cmp(&height) {
+			std::cmp::Ordering::Greater => Orientation::Landscape,
+			std::cmp::Ordering::Less => Orientation::Portrait,
+			std::cmp::Ordering::Equal => Orientation::Square,
+		};
+
+		self.min_width.is_none_or(|min| width >= min)
+			&& self.min_height.is_none_or(|min| height >= min)
+			&& self.min_megapixels.is_none_or(|min| f64::from(width) * f64::from(height) / 1e6 >= min)
+			&& self.orientation.is_none_or(|o| o == orientation)
+	}
+}
@@ -0,0 +1,99 @@
+//! Append-only record of the files put into the tree, so they can be put back
+
+use std::{
+	fs,
+	io::{self, Write},
+	path::{Path, PathBuf},
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+use lazy_static::lazy_static;
+
+use crate::STATE_DIR;
+
+lazy_static! {
+	/// Tells the operations of one run apart from the others
+	static ref SESSION: String = {
+		let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+		format!("{secs}-{}", std::process::id())
+	};
+}
+
+#[derive(Clone, Debug)]
+pub struct Entry {
+	pub session: String,
+	/// "move", "copy" or "link"
+	pub action: String,
+	pub from: PathBuf,
+	pub to: PathBuf,
+}
+
+fn journal_file(root: &Path) -> PathBuf {
+	root.join(STATE_DIR).join("journal")
+}
+
+/// Appends an operation of this run
+pub fn record(root: &Path, action: &str, from: &Path, to: &Path) -> io::Result<()> {
+	let file = journal_file(root);
+	fs::create_dir_all(file.parent().unwrap())?; // Always has one
+
+	let line = line(&SESSION, action, from, to);
+	// A single write, so runs sorting at the same time don't interleave lines
+	fs::OpenOptions::new().create(true).append(true).open(file)?.write_all(line.as_bytes())
+}
+
+/// Every operation recorded, oldest first
+pub fn read(root: &Path) -> io::Result<Vec<Entry>> {
+	let contents = match fs::read_to_string(journal_file(root)) {
+		Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+		res => res?,
+	};
+
+	Ok(contents
+		.lines()
+		.filter_map(|line| {
+			let mut fields = line.split('\t');
+			let (session, action) = (fields.next()?.to_owned(), fields.next()?.to_owned());
+			let (from, to) = (unescape(fields.next()?).into(), unescape(fields.next()?).into());
+
+			Some(Entry { session, action, from, to })
+		})
+		.collect())
+}
+
+/// Replaces the journal with `entries`
+pub fn write(root: &Path, entries: &[Entry]) -> io::Result<()> {
+	let contents: String = entries.iter().map(|e| line(&e.session, &e.action, &e.from, &e.to)).collect();
+
+	fs::write(journal_file(root), contents)
+}
+
+fn line(session: &str, action: &str, from: &Path, to: &Path) -> String {
+	[session, action, &escape(&from.to_string_lossy()), &escape(&to.to_string_lossy())].join("\t") + "\n"
+}
+
+/// `s` with its tabs and newlines escaped, to be a field of a line
+pub fn escape(s: &str) -> String {
+	s.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n")
+}
+
+pub fn unescape(s: &str) -> String {
+	let mut out = String::with_capacity(s.len());
+	let mut chars = s.chars();
+
+	while let Some(c) = chars.next() {
+		if c != '\\' {
+			out.push(c);
+			continue;
+		}
+
+		match chars.next() {
+			Some('t') => out.push('\t'),
+			Some('n') => out.push('\n'),
+			Some(c) => out.push(c),
+			None => out.push('\\'),
+		}
+	}
+
+	out
+}
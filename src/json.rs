@@ -0,0 +1,257 @@
+//! The little JSON this program writes, and reads from compositors
+
+use std::{fmt::Write, iter::Peekable, str::Chars};
+
+/// `s` as a JSON string, quotes included
+pub fn string(s: &str) -> String {
+	let mut out = String::with_capacity(s.len() + 2);
+	out.push('"');
+
+	for c in s.chars() {
+		match c {
+			'"' => out.push_str("\\\""),
+			'\\' => out.push_str("\\\\"),
+			'\n' => out.push_str("\\n"),
+			'\r' => out.push_str("\\r"),
+			'\t' => out.push_str("\\t"),
+			c if c < ' ' => write!(out, "\\u{:04x}", c as u32).unwrap(), // Writing to a String can't fail
+			c => out.push(c),
+		}
+	}
+
+	out.push('"');
+	out
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+	Null,
+	Bool(bool),
+	Number(f64),
+	String(String),
+	Array(Vec<Value>),
+	Object(Vec<(String, Value)>),
+}
+
+impl Value {
+	/// Member `key` of an object
+	pub fn get(&self, key: &str) -> Option<&Value> {
+		match self {
+			Value::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+			_ => None,
+		}
+	}
+
+	pub fn as_str(&self) -> Option<&str> {
+		match self {
+			Value::String(s) => Some(s),
+			_ => None,
+		}
+	}
+
+	pub fn as_bool(&self) -> Option<bool> {
+		match self {
+			Value::Bool(b) => Some(*b),
+			_ => None,
+		}
+	}
+
+	/// Elements of an array, nothing for anything else
+	pub fn elements(&self) -> &[Value] {
+		match self {
+			Value::Array(elements) => elements,
+			_ => &[],
+		}
+	}
+}
+
+/// Parses a whole JSON document, `None` if it isn't one
+pub fn parse(s: &str) -> Option<Value> {
+	let mut chars = s.chars().peekable();
+	let value = parse_value(&mut chars)?;
+
+	skip_whitespace(&mut chars);
+	chars.peek().is_none().then_some(value)
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+	while chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+}
+
+fn parse_value(chars: &mut Peekable<Chars>) -> Option<Value> {
+	skip_whitespace(chars);
+
+	Some(match *chars.peek()? {
+		'{' => {
+			chars.next();
+			let mut members = vec![];
+
+			loop {
+				skip_whitespace(chars);
+
+				match chars.next()? {
+					'}' if members.is_empty() => break,
+					'"' => (),
+					_ => return None,
+				}
+
+				let key = parse_string(chars)?;
+				skip_whitespace(chars);
+				chars.next_if_eq(&':')?;
+				members.push((key, parse_value(chars)?));
+				skip_whitespace(chars);
+
+				match chars.next()? {
+					',' => (),
+					'}' => break,
+					_ => return None,
+				}
+			}
+
+			Value::Object(members)
+		}
+		'[' => {
+			chars.next();
+			let mut elements = vec![];
+
+			skip_whitespace(chars);
+			if chars.next_if_eq(&']').is_none() {
+				loop {
+					elements.push(parse_value(chars)?);
+					skip_whitespace(chars);
+
+					match chars.next()? {
+						',' => (),
+						']' => break,
+						_ => return None,
+					}
+				}
+			}
+
+			Value::Array(elements)
+		}
+		'"' => {
+			chars.next();
+			Value::String(parse_string(chars)?)
+		}
+		c if c == '-' || c.is_ascii_digit() => {
+			let mut number = String::new();
+			while let Some(c) = chars.next_if(|c| matches!(c, '0'..='9' | '-' | '+' | '.' | 'e' | 'E')) {
+				number.push(c);
+			}
+
+			Value::Number(number.parse().ok()?)
+		}
+		_ => {
+			let mut word = String::new();
+			while let Some(c) = chars.next_if(char::is_ascii_alphabetic) {
+				word.push(c);
+			}
+
+			match word.as_str() {
+				"null" => Value::Null,
+				"true" => Value::Bool(true),
+				"false" => Value::Bool(false),
+				_ => return None,
+			}
+		}
+	})
+}
+
+/// Rest of a string after its opening quote
+fn parse_string(chars: &mut Peekable<Chars>) -> Option<String> {
+	let mut out = String::new();
+
+	loop {
+		match chars.next()? {
+			'"' => return Some(out),
+			'\\' => match chars.next()? {
+				'n' => out.push('\n'),
+				'r' => out.push('\r'),
+				't' => out.push('\t'),
+				'b' => out.push('\u{8}'),
+				'f' => out.push('\u{c}'),
+				'u' => {
+					let high = hex_unit(chars)?;
+
+					// Characters outside the BMP come as a surrogate pair, only take the next escape if it completes one
+					let mut low = None;
+					if (0xd800..0xdc00).contains(&high) {
+						let mut ahead = chars.clone();
+						if ahead.next() == Some('\\') && ahead.next() == Some('u') {
+							low = hex_unit(&mut ahead).filter(|low| (0xdc00..0xe000).contains(low));
+							if low.is_some() {
+								*chars = ahead;
+							}
+						}
+					}
+
+					let code = low.map_or(high, |low| 0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00));
+					out.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+				}
+				c => out.push(c),
+			},
+			c => out.push(c),
+		}
+	}
+}
+
+/// The four hex digits after "\u"
+fn hex_unit(chars: &mut Peekable<Chars>) -> Option<u32> {
+	u32::from_str_radix(&chars.take(4).collect::<String>(), 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn escapes() {
+		assert_eq!(string(""), r#""""#);
+		assert_eq!(string("plain ñ 🦀"), r#""plain ñ 🦀""#);
+		assert_eq!(string(r#"say "hi" \o/"#), r#""say \"hi\" \\o/""#);
+		assert_eq!(string("a\nb\rc\td"), r#""a\nb\rc\td""#);
+		assert_eq!(string("\0\u{8}\u{1f}\u{7f}"), "\"\\u0000\\u0008\\u001f\u{7f}\"");
+	}
+
+	#[test]
+	fn documents() {
+		let value = parse(r#" {"a": [1, -2.5e3, true, false, null], "b": {}, "c": [], "d": "x"} "#).unwrap();
+
+		assert_eq!(
+			value.get("a").unwrap().elements(),
+			[Value::Number(1.0), Value::Number(-2500.0), Value::Bool(true), Value::Bool(false), Value::Null]
+		);
+		assert_eq!(value.get("b"), Some(&Value::Object(vec![])));
+		assert_eq!(value.get("c").unwrap().elements(), []);
+		assert_eq!(value.get("d").and_then(Value::as_str), Some("x"));
+		assert_eq!(value.get("e"), None);
+	}
+
+	#[test]
+	fn string_escapes() {
+		let unescape = |s: &str| parse(s).and_then(|v| v.as_str().map(str::to_owned));
+
+		assert_eq!(unescape(r#""\"\\\/\b\f\n\r\t""#).unwrap(), "\"\\/\u{8}\u{c}\n\r\t");
+		assert_eq!(unescape(r#""\u00e9\u20AC""#).unwrap(), "é€");
+		assert_eq!(unescape(r#""\ud83e\udd80""#).unwrap(), "🦀");
+
+		// Unpaired surrogates don't swallow what follows them
+		assert_eq!(unescape(r#""\ud83eab""#).unwrap(), "\u{fffd}ab");
+		assert_eq!(unescape(r#""\ud83e\u0041""#).unwrap(), "\u{fffd}A");
+		assert_eq!(unescape(r#""\udd80""#).unwrap(), "\u{fffd}");
+	}
+
+	#[test]
+	fn round_trip() {
+		let s = "a \"quoted\" \\ path\n\u{1}\u{7f} 🦀";
+		assert_eq!(parse(&string(s)), Some(Value::String(s.to_owned())));
+	}
+
+	#[test]
+	fn not_documents() {
+		for s in ["", "{", "[1,]", "{\"a\" 1}", "{\"a\": 1,}", "[1] 2", "nul", "\"open", "\"\\u12\"", "-"] {
+			assert_eq!(parse(s), None, "{s:?}");
+		}
+	}
+}
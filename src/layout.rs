@@ -0,0 +1,412 @@
+//! Folder layout templates like "{year}/{month}/{day}"
+
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+
+use crate::{
+	date::{Date, Time},
+	locale::Locale,
+	source::Source,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Token {
+	Year,
+	Month,
+	MonthName,
+	Day,
+	Week,
+	Hour,
+	App,
+	Monitor,
+	Resolution,
+}
+
+impl Token {
+	/// How fine grained the token is, years being the coarsest. Weeks are as fine as days since both come right
+	/// after the year
+	fn precision(&self) -> u8 {
+		match self {
+			Token::Year => 0,
+			Token::Month | Token::MonthName => 1,
+			Token::Day | Token::Week => 2,
+			Token::Hour | Token::App | Token::Monitor | Token::Resolution => 3,
+		}
+	}
+
+	fn parse(name: &str) -> Option<Token> {
+		Some(match name {
+			"year" => Token::Year,
+			"month" => Token::Month,
+			"month_name" => Token::MonthName,
+			"day" => Token::Day,
+			"week" => Token::Week,
+			"hour" => Token::Hour,
+			"app" => Token::App,
+			"monitor" => Token::Monitor,
+			"resolution" => Token::Resolution,
+			_ => return None,
+		})
+	}
+
+	/// Whether files can lack a value for it, those without stay in the folder above
+	fn optional(&self) -> bool {
+		matches!(self, Token::Hour | Token::App | Token::Monitor | Token::Resolution)
+	}
+
+	fn pattern(&self) -> &'static str {
+		match self {
+			Token::Year => r"(\d{4,})",
+			Token::MonthName => r"(\w+)",
+			Token::App | Token::Monitor => r"(.+)",
+			Token::Resolution => r"(\d+x\d+)",
+			_ => r"(\d\d)",
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Piece {
+	Text(String),
+	Token(Token),
+}
+
+/// One folder level of a layout
+#[derive(Clone, Debug)]
+struct Segment {
+	pieces: Vec<Piece>,
+	/// Matches folder names this segment renders to
+	pattern: Regex,
+}
+
+impl Segment {
+	fn optional(&self) -> bool {
+		self.pieces.iter().any(|p| matches!(p, Piece::Token(t) if t.optional()))
+	}
+
+	fn uses(&self, token: Token) -> bool {
+		self.pieces.contains(&Piece::Token(token))
+	}
+
+	/// `week_based` makes `{year}` the ISO week-numbering year, so the last days of December can be in week 1
+	fn render(
+		&self,
+		date: &Date,
+		time: Option<&Time>,
+		source: &Source,
+		week_based: bool,
+		locale: &Locale,
+	) -> Option<String> {
+		let mut s = String::new();
+		let (week_year, week) = date.iso_week();
+
+		for piece in &self.pieces {
+			match piece {
+				Piece::Text(text) => s.push_str(text),
+				Piece::Token(Token::Year) => {
+					s.push_str(&format!("{:04}", if week_based { week_year } else { date.year }))
+				}
+				Piece::Token(Token::Month) => s.push_str(&format!("{:02}", date.month)),
+				Piece::Token(Token::MonthName) => s.push_str(locale.months[date.month as usize - 1]),
+				Piece::Token(Token::Day) => s.push_str(&format!("{:02}", date.day)),
+				Piece::Token(Token::Week) => s.push_str(&format!("{:02}", week)),
+				Piece::Token(Token::Hour) => s.push_str(&format!("{:02}", time?.hour)),
+				Piece::Token(Token::App) => s.push_str(source.app?),
+				Piece::Token(Token::Monitor) => s.push_str(source.monitor?),
+				Piece::Token(Token::Resolution) => {
+					let (width, height) = source.resolution?;
+					s.push_str(&format!("{width}x{height}"))
+				}
+			}
+		}
+
+		Some(s)
+	}
+
+	fn tokens(&self) -> impl Iterator<Item = Token> + '_ {
+		self.pieces.iter().filter_map(|p| match p {
+			Piece::Token(t) => Some(*t),
+			Piece::Text(_) => None,
+		})
+	}
+
+	/// Values of the tokens in a folder name, in order, or `None` if this segment can't have produced it
+	fn key(&self, name: &str, locale: &Locale) -> Option<Vec<u32>> {
+		let captures = self.pattern.captures(name)?;
+
+		self.tokens()
+			.zip(captures.iter().skip(1))
+			.map(|(token, value)| match token {
+				Token::MonthName => locale.month(value?.as_str()),
+				Token::App | Token::Monitor | Token::Resolution => value.map(|_| 0), // Any name, all the same for sorting
+				_ => value?.as_str().parse().ok(),
+			})
+			.collect()
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct Layout {
+	segments: Vec<Segment>,
+	week_based: bool,
+	locale: Locale,
+}
+
+impl Layout {
+	/// Parses a template of `/` separated folders made of text and `{year}`, `{month}`, `{month_name}`, `{day}`,
+	/// `{week}`, `{hour}`, `{app}`, `{monitor}` and `{resolution}`
+	pub fn parse(s: &str) -> Result<Layout, String> {
+		let segments = s.split('/').filter(|s| !s.is_empty()).map(parse_segment).collect::<Result<Vec<_>, _>>()?;
+
+		if segments.is_empty() {
+			return Err("layout has no folders".to_owned());
+		}
+
+		if let Some(i) = segments.iter().position(Segment::optional) {
+			if segments[i..].iter().any(|s| !s.optional()) {
+				return Err(
+					"folders using the time of day, the app or the monitor must come after all others".to_owned()
+				);
+			}
+		}
+
+		let week_based = segments.iter().any(|s| s.uses(Token::Week));
+		let month_based = segments.iter().any(|s| s.uses(Token::Month) || s.uses(Token::MonthName));
+
+		if week_based && (month_based || segments.iter().any(|s| s.uses(Token::Day))) {
+			return Err("{week} can't be combined with {month}, {month_name} or {day}".to_owned());
+		}
+
+		Ok(Layout { segments, week_based, locale: Locale::parse("en").unwrap() })
+	}
+
+	/// Language used for `{month_name}`
+	pub fn set_locale(&mut self, locale: Locale) {
+		self.locale = locale;
+	}
+
+	/// Folder for a file from `date` and `source`, relative to the screenshot directory. Without a time of day, an
+	/// app or a monitor the file stays above any folders that would need one
+	pub fn dir(&self, date: &Date, time: Option<&Time>, source: &Source) -> PathBuf {
+		self.segments.iter().map_while(|s| s.render(date, time, source, self.week_based, &self.locale)).collect()
+	}
+
+	/// Number of folder levels down to a whole day, or week. This is where `latest` points
+	pub fn day_depth(&self) -> usize {
+		self.segments.iter().take_while(|s| !s.optional()).count()
+	}
+
+	/// Number of folder levels down to a whole year
+	pub fn year_depth(&self) -> Option<usize> {
+		self.depth_with_precision(0)
+	}
+
+	/// Number of folder levels down to a whole month, if the layout has months
+	pub fn month_depth(&self) -> Option<usize> {
+		self.depth_with_precision(1)
+	}
+
+	fn depth_with_precision(&self, precision: u8) -> Option<usize> {
+		let finest = |segments: &[Segment]| {
+			segments
+				.iter()
+				.flat_map(|s| &s.pieces)
+				.filter_map(|p| match p {
+					Piece::Token(t) => Some(t.precision()),
+					Piece::Text(_) => None,
+				})
+				.max()
+		};
+
+		(1..=self.segments.len()).find(|&depth| finest(&self.segments[..depth]) == Some(precision))
+	}
+
+	/// Date of the folder at `dir`, relative to the screenshot directory. Days of folders without one are the first
+	/// of their month or week
+	pub fn folder_date(&self, dir: &Path) -> Option<(Date, Option<Time>)> {
+		let names: Vec<_> = dir.iter().map(|n| n.to_string_lossy()).collect();
+
+		if names.len() < self.day_depth() || names.len() > self.segments.len() {
+			return None;
+		}
+
+		let (mut year, mut month, mut day, mut week, mut hour) = (None, 1, 1, None, None);
+
+		for (segment, name) in self.segments.iter().zip(&names) {
+			for (token, value) in segment.tokens().zip(segment.key(name, &self.locale)?) {
+				match token {
+					Token::Year => year = Some(value as i32),
+					Token::Month | Token::MonthName => month = value,
+					Token::Day => day = value,
+					Token::Week => week = Some(value),
+					Token::Hour => hour = Some(value),
+					Token::App | Token::Monitor | Token::Resolution => (),
+				}
+			}
+		}
+
+		let year = year?;
+		let date = match week {
+			Some(week) => {
+				// January 4th is always in week 1
+				let jan4 = Date { year, month: 1, day: 4 }.days();
+				let monday = jan4 - (jan4 + 3).rem_euclid(7);
+				Date::from_days(monday + i64::from(week.max(1) - 1) * 7)
+			}
+			None => Date { year, month, day },
+		};
+		let time = hour.map(|hour| Time { hour, minute: 0, second: 0 });
+
+		// Folders like "2024/13" look dated but aren't
+		if !date.exists() || time.is_some_and(|t| !t.exists()) {
+			return None;
+		}
+
+		Some((date, time))
+	}
+
+	/// Whether folders are named after the size of images, reading it takes opening them
+	pub fn uses_resolution(&self) -> bool {
+		self.segments.iter().any(|s| s.uses(Token::Resolution))
+	}
+
+	/// Sort key of a folder `depth` levels below the screenshot directory, `None` if it's not a dated folder
+	pub fn folder_key(&self, depth: usize, name: &str) -> Option<Vec<u32>> {
+		self.segments.get(depth)?.key(name, &self.locale)
+	}
+}
+
+/// Layouts files below `root` may already be organized with
+#[derive(Clone, Debug)]
+pub struct FolderDates {
+	pub root: PathBuf,
+	pub layouts: Vec<Layout>,
+}
+
+impl FolderDates {
+	/// Date of the folder `file` is in according to the first layout that understands it
+	pub fn date(&self, file: &Path) -> Option<(Date, Option<Time>)> {
+		let dir = file.parent()?.strip_prefix(&self.root).ok()?;
+		self.layouts.iter().find_map(|l| l.folder_date(dir))
+	}
+
+	pub fn folder_key(&self, name: &str) -> Option<Vec<u32>> {
+		self.layouts.iter().find_map(|l| l.folder_key(0, name))
+	}
+}
+
+fn parse_segment(s: &str) -> Result<Segment, String> {
+	let mut pieces = vec![];
+	let mut rest = s;
+
+	while let Some(start) = rest.find('{') {
+		if start > 0 {
+			pieces.push(Piece::Text(rest[..start].to_owned()));
+		}
+
+		let end = rest[start..].find('}').ok_or_else(|| format!("unclosed \"{{\" in \"{s}\""))? + start;
+		let name = &rest[start + 1..end];
+		pieces.push(Piece::Token(Token::parse(name).ok_or_else(|| format!("unknown layout token \"{{{name}}}\""))?));
+
+		rest = &rest[end + 1..];
+	}
+
+	if !rest.is_empty() {
+		pieces.push(Piece::Text(rest.to_owned()));
+	}
+
+	if !pieces.iter().any(|p| matches!(p, Piece::Token(_))) {
+		return Err(format!("folder \"{s}\" doesn't use any token"));
+	}
+
+	let pattern: String = pieces
+		.iter()
+		.map(|p| match p {
+			Piece::Text(text) => regex::escape(text),
+			Piece::Token(token) => token.pattern().to_owned(),
+		})
+		.collect();
+	let pattern = Regex::new(&format!("^{pattern}$")).map_err(|e| e.to_string())?;
+
+	Ok(Segment { pieces, pattern })
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const NO_SOURCE: Source = Source { app: None, monitor: None, resolution: None };
+
+	fn date(year: i32, month: u32, day: u32) -> Date {
+		Date { year, month, day }
+	}
+
+	fn dir(layout: &str, date: Date, time: Option<Time>) -> String {
+		let layout = Layout::parse(layout).unwrap();
+		layout.dir(&date, time.as_ref(), &NO_SOURCE).to_string_lossy().into_owned()
+	}
+
+	#[test]
+	fn renders_numbers() {
+		assert_eq!(dir("{year}/{month}/{day}", date(2024, 5, 1), None), "2024/05/01");
+		assert_eq!(dir("{year}-{month}", date(2024, 12, 31), None), "2024-12");
+	}
+
+	#[test]
+	fn renders_month_names() {
+		assert_eq!(dir("{year}/{month_name}", date(2024, 1, 9), None), "2024/January");
+
+		let mut layout = Layout::parse("{year}/{month_name}/{day}").unwrap();
+		layout.set_locale(Locale::parse("de_DE.UTF-8").unwrap());
+		assert_eq!(layout.dir(&date(2024, 3, 2), None, &NO_SOURCE), Path::new("2024/März/02"));
+		assert_eq!(layout.folder_date(Path::new("2024/März/02")), Some((date(2024, 3, 2), None)));
+	}
+
+	#[test]
+	fn renders_weeks_of_their_year() {
+		// The last days of 2024 are in week 1 of 2025, the first of 2021 in week 53 of 2020
+		assert_eq!(dir("{year}/W{week}", date(2024, 12, 30), None), "2025/W01");
+		assert_eq!(dir("{year}/W{week}", date(2021, 1, 3), None), "2020/W53");
+		assert_eq!(dir("{year}/W{week}", date(2024, 5, 1), None), "2024/W18");
+	}
+
+	#[test]
+	fn leaves_out_what_files_lack() {
+		let time = Time { hour: 9, minute: 30, second: 0 };
+		assert_eq!(dir("{year}/{month}/{day}/{hour}", date(2024, 5, 1), Some(time)), "2024/05/01/09");
+		assert_eq!(dir("{year}/{month}/{day}/{hour}", date(2024, 5, 1), None), "2024/05/01");
+		assert_eq!(dir("{year}/{month}/{day}/{app}", date(2024, 5, 1), None), "2024/05/01");
+	}
+
+	#[test]
+	fn reads_folder_dates() {
+		let layout = Layout::parse("{year}/{month}/{day}").unwrap();
+		assert_eq!(layout.folder_date(Path::new("2024/05/01")), Some((date(2024, 5, 1), None)));
+		assert_eq!(layout.folder_date(Path::new("2024/05")), None);
+
+		let layout = Layout::parse("{year}/W{week}").unwrap();
+		assert_eq!(layout.folder_date(Path::new("2025/W01")), Some((date(2024, 12, 30), None)));
+	}
+
+	#[test]
+	fn invalid_folder_dates_are_none() {
+		let layout = Layout::parse("{year}/{month}/{day}").unwrap();
+		assert_eq!(layout.folder_date(Path::new("2024/13/01")), None);
+		assert_eq!(layout.folder_date(Path::new("2024/00/01")), None);
+		assert_eq!(layout.folder_date(Path::new("2023/02/29")), None);
+		assert_eq!(layout.folder_date(Path::new("2024/02/29")), Some((date(2024, 2, 29), None)));
+
+		let layout = Layout::parse("{year}/{month}/{day}/{hour}").unwrap();
+		assert_eq!(layout.folder_date(Path::new("2024/05/01/24")), None);
+	}
+
+	#[test]
+	fn rejects_bad_templates() {
+		assert!(Layout::parse("").is_err());
+		assert!(Layout::parse("{year}/{nope}").is_err());
+		assert!(Layout::parse("{year}/{week}/{day}").is_err());
+		assert!(Layout::parse("{year}/{hour}/{month}").is_err());
+		assert!(Layout::parse("{year}/plain").is_err());
+	}
+}
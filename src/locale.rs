@@ -0,0 +1,248 @@
+//! Month names for folder layouts
+
+#[derive(Clone, Copy, Debug)]
+pub struct Locale {
+	pub months: &'static [&'static str; 12],
+}
+
+const LOCALES: &[(&str, [&str; 12])] = &[
+	(
+		"en",
+		[
+			"January",
+			"February",
+			"March",
+			"April",
+			"May",
+			"June",
+			"July",
+			"August",
+			"September",
+			"October",
+			"November",
+			"December",
+		],
+	),
+	(
+		"es",
+		[
+			"enero",
+			"febrero",
+			"marzo",
+			"abril",
+			"mayo",
+			"junio",
+			"julio",
+			"agosto",
+			"septiembre",
+			"octubre",
+			"noviembre",
+			"diciembre",
+		],
+	),
+	(
+		"ca",
+		[
+			"gener", "febrer", "març", "abril", "maig", "juny", "juliol", "agost", "setembre", "octubre", "novembre",
+			"desembre",
+		],
+	),
+	(
+		"de",
+		[
+			"Januar",
+			"Februar",
+			"März",
+			"April",
+			"Mai",
+			"Juni",
+			"Juli",
+			"August",
+			"September",
+			"Oktober",
+			"November",
+			"Dezember",
+		],
+	),
+	(
+		"fr",
+		[
+			"janvier",
+			"février",
+			"mars",
+			"avril",
+			"mai",
+			"juin",
+			"juillet",
+			"août",
+			"septembre",
+			"octobre",
+			"novembre",
+			"décembre",
+		],
+	),
+	(
+		"it",
+		[
+			"gennaio",
+			"febbraio",
+			"marzo",
+			"aprile",
+			"maggio",
+			"giugno",
+			"luglio",
+			"agosto",
+			"settembre",
+			"ottobre",
+			"novembre",
+			"dicembre",
+		],
+	),
+	(
+		"pt",
+		[
+			"janeiro",
+			"fevereiro",
+			"março",
+			"abril",
+			"maio",
+			"junho",
+			"julho",
+			"agosto",
+			"setembro",
+			"outubro",
+			"novembro",
+			"dezembro",
+		],
+	),
+	(
+		"nl",
+		[
+			"januari",
+			"februari",
+			"maart",
+			"april",
+			"mei",
+			"juni",
+			"juli",
+			"augustus",
+			"september",
+			"oktober",
+			"november",
+			"december",
+		],
+	),
+	(
+		"sv",
+		[
+			"januari",
+			"februari",
+			"mars",
+			"april",
+			"maj",
+			"juni",
+			"juli",
+			"augusti",
+			"september",
+			"oktober",
+			"november",
+			"december",
+		],
+	),
+	(
+		"da",
+		[
+			"januar",
+			"februar",
+			"marts",
+			"april",
+			"maj",
+			"juni",
+			"juli",
+			"august",
+			"september",
+			"oktober",
+			"november",
+			"december",
+		],
+	),
+	(
+		"nb",
+		[
+			"januar",
+			"februar",
+			"mars",
+			"april",
+			"mai",
+			"juni",
+			"juli",
+			"august",
+			"september",
+			"oktober",
+			"november",
+			"desember",
+		],
+	),
+	(
+		"fi",
+		[
+			"tammikuu",
+			"helmikuu",
+			"maaliskuu",
+			"huhtikuu",
+			"toukokuu",
+			"kesäkuu",
+			"heinäkuu",
+			"elokuu",
+			"syyskuu",
+			"lokakuu",
+			"marraskuu",
+			"joulukuu",
+		],
+	),
+	(
+		"pl",
+		[
+			"styczeń",
+			"luty",
+			"marzec",
+			"kwiecień",
+			"maj",
+			"czerwiec",
+			"lipiec",
+			"sierpień",
+			"wrzesień",
+			"październik",
+			"listopad",
+			"grudzień",
+		],
+	),
+];
+
+impl Locale {
+	/// Parses a language code like "de" or a POSIX locale like "de_DE.UTF-8"
+	pub fn parse(s: &str) -> Result<Locale, String> {
+		let language = s.split(['_', '-', '.', '@']).next().unwrap_or_default().to_lowercase();
+		let language = if language == "no" { "nb".to_owned() } else { language };
+
+		LOCALES.iter().find(|(code, _)| *code == language).map(|(_, months)| Locale { months }).ok_or_else(|| {
+			let known: Vec<_> = LOCALES.iter().map(|(code, _)| *code).collect();
+			format!("no month names for \"{s}\", known languages are {}", known.join(", "))
+		})
+	}
+
+	/// Locale from LC_ALL, LC_TIME or LANG, English if none of them is known
+	pub fn from_env() -> Locale {
+		["LC_ALL", "LC_TIME", "LANG"]
+			.iter()
+			.filter_map(|var| std::env::var(var).ok().filter(|v| !v.is_empty()))
+			.find_map(|v| Locale::parse(&v).ok())
+			.unwrap_or(Locale { months: &LOCALES[0].1 })
+	}
+
+	/// 1 based month number of a month name
+	pub fn month(&self, name: &str) -> Option<u32> {
+		let name = name.to_lowercase();
+		self.months.iter().position(|m| m.to_lowercase() == name).map(|i| i as u32 + 1)
+	}
+}
@@ -0,0 +1,25 @@
+//! Running ImageMagick, for comparing and shrinking images
+
+use std::{ffi::OsStr, io, path::Path, process::Command};
+
+/// Runs ImageMagick on the first frame of `file` with `options`, writing to `output`, and returns what it printed.
+/// Animations would give one image per frame otherwise
+pub fn convert(file: &Path, options: &[&str], output: &OsStr) -> anyhow::Result<Vec<u8>> {
+	let mut input = file.as_os_str().to_owned();
+	input.push("[0]");
+
+	let run = |program: &str| Command::new(program).arg(&input).args(options).arg(output).output();
+
+	// ImageMagick 7 is "magick", 6 only had "convert"
+	let output = match run("magick") {
+		Err(e) if e.kind() == io::ErrorKind::NotFound => run("convert"),
+		res => res,
+	}
+	.map_err(|e| anyhow::anyhow!("running ImageMagick: {e}"))?;
+
+	if !output.status.success() {
+		anyhow::bail!("ImageMagick failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+	}
+
+	Ok(output.stdout)
+}
@@ -1,45 +1,980 @@
 use std::{
+	collections::{BTreeMap, HashMap, HashSet},
+	ffi::OsString,
 	fs,
+	io::{BufRead, BufReader, ErrorKind, Read, Seek, SeekFrom, Write},
+	num::NonZeroUsize,
 	path::{Path, PathBuf},
 	sync::{
-		atomic::{AtomicBool, Ordering},
-		Arc,
+		atomic::{AtomicBool, AtomicUsize, Ordering},
+		mpsc::{Receiver, RecvTimeoutError, Sender},
+		Arc, Mutex,
 	},
 	thread,
+	time::{Duration, Instant, SystemTime},
 };
 
-use signal_hook::{
-	consts::{SIGINT, SIGTERM},
-	iterator::Signals,
+use notify::{
+	event::{AccessKind, AccessMode, ModifyKind, RenameMode},
+	EventKind, Watcher,
 };
 
-use notify::{event::AccessKind, Watcher};
-
-use clap::Parser;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
 use const_format::formatcp;
 use lazy_static::lazy_static;
 use regex::Regex;
 
+mod api;
+mod archive;
+mod blake3;
+mod browse;
+mod bursts;
+mod capture;
+mod changes;
+mod checksums;
+mod commands;
+mod completions;
+mod compositor;
+mod config;
+mod date;
+mod events;
+mod glob;
+mod hooks;
+mod index;
+mod journal;
+mod json;
+mod layout;
+mod locale;
+mod magick;
+mod metadata;
+mod metrics;
+mod naming;
+mod platform;
+mod quota;
+mod sidecar;
+mod source;
+mod space;
+mod tags;
+mod thumbs;
+mod trash;
+mod tz;
+mod upload;
+mod webdav;
+mod websocket;
+mod windows;
+
+use date::{Date, Time};
+use events::{info, Event};
+use glob::Glob;
+use layout::{FolderDates, Layout};
+use locale::Locale;
+use metadata::{JPEG_END, JPEG_START, PNG_END, PNG_SIGNATURE};
+use naming::NameTemplate;
+use platform::{SIGINT, SIGTERM};
+use source::Source;
+use tz::Zone;
+
 const YEAR: &str = "YEAR";
 const MONTH: &str = "MONTH";
 const DAY: &str = "DAY";
+const HOUR: &str = "HOUR";
+const MINUTE: &str = "MINUTE";
+const SECOND: &str = "SECOND";
 
 const LATEST: &str = "latest";
+const TODAY: &str = "today";
+const LATEST_MONTH: &str = "latest-month";
+const LATEST_YEAR: &str = "latest-year";
 const OTHER: &str = "other";
-
-const NAME_REGEX_STR: &str = formatcp!(r"(?<{}>\d\d\d\d)-(?<{}>\d\d)-(?<{}>\d\d).*\.png$", YEAR, MONTH, DAY);
+const QUARANTINE: &str = "quarantine";
+/// Tree of camera photos with --photos separate, laid out like the screenshots
+const PHOTOS: &str = "photos";
+/// Tree of animations with --animations separate
+const RECORDINGS: &str = "recordings";
+/// Folder of links to every file with the "starred" tag
+const STARRED: &str = "starred";
+/// Where the journal and other state of the screenshot directory is kept
+const STATE_DIR: &str = ".screenshot-manager";
+
+const NAME_REGEX_STR: &str = formatcp!(
+	r"(?<{}>\d\d\d\d)-(?<{}>\d\d)-(?<{}>\d\d)(?:[\s_T-]*(?:at\s+)?(?<{}>\d\d)[-:._]?(?<{}>\d\d)(?:[-:._]?(?<{}>\d\d))?)?",
+	YEAR,
+	MONTH,
+	DAY,
+	HOUR,
+	MINUTE,
+	SECOND
+);
+
+/// A command failed, or found problems
+const EXIT_FAILED: i32 = 1;
+/// The options, config files or environment variables are wrong, clap exits with this too
+const EXIT_USAGE: i32 = 2;
+/// The screenshot directory or the tree doesn't exist, or isn't a directory
+const EXIT_NO_DIR: i32 = 3;
+/// Another instance watches the tree already
+const EXIT_LOCKED: i32 = 4;
+/// Watching couldn't start: the watcher, the signal handler, the events socket or the metrics server
+const EXIT_SETUP: i32 = 5;
+/// Reading or writing files failed while sorting, or the watcher failed while running
+const EXIT_IO: i32 = 6;
+
+const EXIT_CODES: &str = formatcp!(
+	"Exit codes:\n  {EXIT_FAILED}  A command failed or found problems\n  {EXIT_USAGE}  Wrong options, config files or \
+	environment variables\n  {EXIT_NO_DIR}  The screenshot directory or the tree is missing\n  {EXIT_LOCKED}  Another \
+	instance watches the tree\n  {EXIT_SETUP}  Watching couldn't start\n  {EXIT_IO}  Sorting or watching failed \
+	while running"
+);
+
+/// How often a long running clean reports how far along it is
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Failures in a row after which sorting a file or updating the links is given up on, until something changes
+const MAX_RETRIES: u32 = 10;
+
+/// How often the tree is polled when inotify has no watches left for it
+const LIMIT_POLL: Duration = Duration::from_secs(2);
+
+/// How long a file created without a write so far waits for one to start. Links have none, and neither do files
+/// in folders moved in
+const CREATE_GRACE: Duration = Duration::from_secs(1);
+
+/// How long the links wait for more files after one is sorted, a burst of them updates the links once
+const LINK_BATCH: Duration = Duration::from_millis(500);
+
+/// How often the links are checked for being removed or replaced while watching
+const LINK_CHECK: Duration = Duration::from_secs(60);
+
+/// How long a path renamed away waits for the event with its new name before it counts as removed
+const RENAME_GRACE: Duration = Duration::from_millis(500);
 
 lazy_static! {
+	/// Destinations files are being put at during a clean
+	static ref CLAIMED: Mutex<HashSet<PathBuf>> = Mutex::new(HashSet::new());
+	/// Where files were moved away from by sorting, the watcher reports it like any other rename
+	static ref MOVED_AWAY: Mutex<HashSet<PathBuf>> = Mutex::new(HashSet::new());
 	static ref NAME_REGEX: Regex = Regex::new(NAME_REGEX_STR).unwrap();
+	// Unix time in seconds or milliseconds, like "1714569300.png" or "Screenshot_1714569300123.png"
+	static ref EPOCH_REGEX: Regex = Regex::new(r"(?:^|\D)(\d{10}|\d{13})(?:\D|$)").unwrap();
 }
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Clone, Debug)]
 #[command(author, version, about, long_about = None)]
-#[command(propagate_version = true)]
+#[command(propagate_version = true, after_help = EXIT_CODES)]
+#[command(args_override_self = true, subcommand_negates_reqs = true)]
 pub struct Args {
 	/// Path to screenshot directory
-	#[arg(value_name = "PATH")]
-	screenshot_dir: String,
+	#[arg(value_name = "PATH", required_unless_present = "config")]
+	screenshot_dir: Option<String>,
+
+	/// Watch the screenshot directories of the `[[source]]`s in FILE instead, each with options of its own like
+	/// `layout = "{year}/{month}"` or `into = "/mnt/work"`. Those before the first source apply to all of them, and
+	/// so do those given here, over any other. A ".screenshot-manager.toml" in a screenshot directory, with or
+	/// without --config, has options for it alone. Environment variables like SCREENSHOT_MANAGER_LAYOUT for
+	/// --layout or SCREENSHOT_MANAGER_DIR for PATH override those files, and the command line overrides them
+	#[arg(long, value_name = "FILE")]
+	config: Option<PathBuf>,
+
+	/// Watch the `[[profile.NAME.source]]`s of --config too, and give all sources the options after its
+	/// `[profile.NAME]`, like the directories and hooks of work or home
+	#[arg(long, value_name = "NAME", requires = "config")]
+	profile: Option<String>,
+
+	/// Sort into the tree at DIR instead of the screenshot directory itself, like an encrypted or synced folder.
+	/// Commands work on that tree
+	#[arg(long, value_name = "DIR")]
+	into: Option<PathBuf>,
+
+	/// Sort screen recordings into the tree at DIR instead, with its own links. Recordings are animations and
+	/// videos like MP4, WebM and Matroska, told by their contents and sorted by the date in their name
+	#[arg(long, value_name = "DIR", conflicts_with = "animations")]
+	recordings_into: Option<PathBuf>,
+
+	/// Run once and exit instead of watching the directory
+	#[command(subcommand)]
+	command: Option<Command>,
+
+	/// Poll the directory every INTERVAL instead of relying on filesystem events (e.g. "2s", "500ms")
+	#[arg(long, value_name = "INTERVAL", value_parser = parse_duration)]
+	poll: Option<Duration>,
+
+	/// While watching, clean the whole directory every INTERVAL like on startup, for files whose events were missed
+	/// (e.g. "15m")
+	#[arg(long, value_name = "INTERVAL", value_parser = parse_duration)]
+	rescan_interval: Option<Duration>,
+
+	/// Only sort files changed within AGE when cleaning, like "30d" for a directory with years of screenshots in it.
+	/// The others are left for `clean --all`
+	#[arg(long, value_name = "AGE", value_parser = parse_duration)]
+	newer_than: Option<Duration>,
+
+	/// Re-sort files dropped into the wrong date folder anywhere in the tree, not only those dropped into the
+	/// screenshot directory. The whole tree is watched with it, and with --sidecars, --checksums or --window-info, so
+	/// what is kept of files follows them as other programs rename or remove them. That takes an inotify watch per
+	/// folder, otherwise it is caught up with at the next start
+	#[arg(short, long)]
+	recursive: bool,
+
+	/// What to do with folders dropped into the screenshot directory, like a folder of screenshots copied there
+	#[arg(long, value_name = "POLICY", value_enum, default_value_t = DroppedFolders::Leave)]
+	dropped_folders: DroppedFolders,
+
+	/// Wait until a file has had no events for DELAY before sorting it
+	#[arg(long, value_name = "DELAY", value_parser = parse_duration, default_value = platform::DEFAULT_SETTLE)]
+	settle: Duration,
+
+	/// Only sort files whose size stays the same for WINDOW and which don't look truncated
+	#[arg(long, value_name = "WINDOW", value_parser = parse_duration)]
+	stable: Option<Duration>,
+
+	/// What to do when a file with the same name already exists at the destination
+	#[arg(long, value_name = "STRATEGY", value_enum, default_value_t = Conflict::Rename)]
+	on_conflict: Conflict,
+
+	/// What to do with a new file that is the same as one already in the folder it goes into, whatever its name.
+	/// Without --mode move it's only left unsorted
+	#[arg(long, value_name = "ACTION", value_enum)]
+	dedupe: Option<Dedupe>,
+
+	/// How files are put into the organized tree
+	#[arg(long, value_enum, default_value_t = Mode::Move)]
+	mode: Mode,
+
+	/// Only touch files of these types, like "png,jpg,webp", and sort them by the date in their name like PNGs.
+	/// PNG, JPEG, WebP and GIF files are recognized by their contents, anything else by its extension [default:
+	/// everything, only PNGs and animations by name]
+	#[arg(long, value_name = "EXT", value_delimiter = ',', value_parser = parse_extension)]
+	extensions: Vec<String>,
+
+	/// What to do with files smaller than --min-size
+	#[arg(long, value_name = "ACTION", value_enum, default_value_t = Small::Ignore, requires = "min_size")]
+	small_files: Small,
+
+	/// Files smaller than SIZE are not sorted, like "1KiB" or "512"
+	#[arg(long, value_name = "SIZE", value_parser = parse_size)]
+	min_size: Option<u64>,
+
+	/// Keep the files of the tree under SIZE in total while watching, like "20GiB". What happens once they aren't
+	/// follows --over-quota
+	#[arg(long, value_name = "SIZE", value_parser = parse_size)]
+	max_total: Option<u64>,
+
+	/// --max-total for the tree of --recordings-into, recordings are big and can get one of their own
+	#[arg(long, value_name = "SIZE", value_parser = parse_size, requires = "recordings_into")]
+	recordings_max_total: Option<u64>,
+
+	/// Report the filesystem of the tree getting below SIZE free while watching, like "2GiB". In an urgent
+	/// notification too with --notify
+	#[arg(long, value_name = "SIZE", value_parser = parse_size)]
+	min_free: Option<u64>,
+
+	/// What to do once the tree is over --max-total
+	#[arg(long, value_name = "ACTION", value_enum, default_value_t = OverQuota::Prune, requires = "max_total")]
+	over_quota: OverQuota,
+
+	/// Files sorted at the same time by the first clean [default: number of CPUs]
+	#[arg(short, long, value_name = "N", default_value_t = default_jobs(), hide_default_value = true)]
+	jobs: NonZeroUsize,
+
+	/// Leave files whose name matches GLOB alone, like "*.kra" or "wallpaper*". Can be given more than once
+	#[arg(long, value_name = "GLOB", value_parser = Glob::parse)]
+	exclude: Vec<Glob>,
+
+	/// Sort files without a date in their name by the capture date stored in JPEG and PNG metadata
+	#[arg(long)]
+	metadata_dates: bool,
+
+	/// Sort files without a date in their name or metadata by their modification time instead of into "other"
+	#[arg(long)]
+	mtime_fallback: bool,
+
+	/// Folders files are sorted into, from "{year}", "{month}", "{month_name}", "{day}", "{week}", "{hour}",
+	/// "{app}", the tool that took the screenshot, "{monitor}" and "{resolution}", like "3840x2160"
+	#[arg(long, value_name = "TEMPLATE", value_parser = Layout::parse, default_value = "{year}/{month}/{day}")]
+	layout: Layout,
+
+	/// Rename files as they are sorted, from "{date}", "{time}", "{app}", "{monitor}", "{name}", the old name, and
+	/// "{seq}", a number starting at 1 that keeps names apart. Like "{date}_{time}_{app}_{seq}", the extension is
+	/// kept. Tokens without a value are left out, keep "{date}" in it for the tree to be repairable
+	#[arg(long, value_name = "TEMPLATE", value_parser = NameTemplate::parse)]
+	rename: Option<NameTemplate>,
+
+	/// Finds the monitor for "{monitor}" in file names, its first group if it has one [default: names like "DP-1",
+	/// "HDMI-A-1" or "eDP-1"]
+	#[arg(long, value_name = "REGEX", value_parser = Regex::new)]
+	monitor_pattern: Option<Regex>,
+
+	/// Language of "{month_name}" folders, like "de" or "es_ES.UTF-8" [default: from LC_TIME or LANG]
+	#[arg(long, value_name = "LANG", value_parser = Locale::parse)]
+	locale: Option<Locale>,
+
+	/// Time zone dates are sorted in: "local", "utc" or a name like "Europe/Madrid"
+	#[arg(long, value_name = "ZONE", value_parser = Zone::parse, default_value = "local")]
+	timezone: Zone,
+
+	/// Time zone the dates and times in file names are in, if different from --timezone
+	#[arg(long, value_name = "ZONE", value_parser = Zone::parse)]
+	name_timezone: Option<Zone>,
+
+	/// Also keep a "today" link to the current day's folder, once it has something in it
+	#[arg(long)]
+	today_link: bool,
+
+	/// Also keep "latest-month" and "latest-year" links to the newest month and year folders
+	#[arg(long)]
+	period_links: bool,
+
+	/// Point links at folders relative to the screenshot directory, so they survive it being moved or mounted
+	/// elsewhere
+	#[arg(long)]
+	relative_links: bool,
+
+	/// Remove dated folders left empty, so they are never picked as the newest one
+	#[arg(long)]
+	prune_empty: bool,
+
+	/// Print one JSON object per line for every file moved, skipped or failed and every link updated, instead of
+	/// prose
+	#[arg(long)]
+	json_events: bool,
+
+	/// Only print warnings and errors, not a line for every file
+	#[arg(short, long)]
+	quiet: bool,
+
+	/// Print without colors. They are only used on terminals, and not with NO_COLOR set either
+	#[arg(long)]
+	no_color: bool,
+
+	/// Listen on a Unix socket at PATH and send every subscriber a JSON line for each file sorted. The serve command
+	/// subscribes to it instead
+	#[arg(long, value_name = "PATH")]
+	events_socket: Option<PathBuf>,
+
+	/// Run COMMAND through the shell for every new screenshot sorted, with its path as $1
+	#[arg(long, value_name = "COMMAND")]
+	exec: Option<String>,
+
+	/// Show a desktop notification for every new screenshot sorted, with notify-send
+	#[arg(long)]
+	notify: bool,
+
+	/// Upload every new screenshot sorted with curl and put its URL on the clipboard, and in a notification with
+	/// --notify. To "0x0.st", to a URL taking it as the "file" field of a form and answering with its URL, or with
+	/// "put:" and a URL with "{name}" in it to PUT it there, like a public-write bucket or a WebDAV share
+	#[arg(long, value_name = "TARGET", value_parser = upload::Target::parse)]
+	upload: Option<upload::Target>,
+
+	/// Write a "<name>.json" next to every file sorted with its original name, when it was sorted, the app that took
+	/// it, the window and its tags. Sidecars move along with their files
+	#[arg(long)]
+	sidecars: bool,
+
+	/// Keep BLAKE3 checksums of the files sorted in a hidden manifest in each folder, for scrub to check them
+	#[arg(long)]
+	checksums: bool,
+
+	/// Record the title and workspace of the focused window for every new screenshot, asking Sway or Hyprland. They
+	/// are listed by the export command
+	#[arg(long)]
+	window_info: bool,
+
+	/// Serve Prometheus metrics over HTTP at ADDR/metrics, like 127.0.0.1:9184, and a health check at ADDR/healthz
+	#[arg(long, value_name = "ADDR")]
+	metrics: Option<std::net::SocketAddr>,
+
+	/// Only answer requests to the serve command that have "Authorization: Bearer TOKEN", or "?token=TOKEN" for
+	/// WebSockets of browsers. Best given in the config or $SCREENSHOT_MANAGER_SERVE_TOKEN, other users can see
+	/// command lines
+	#[arg(long, value_name = "TOKEN")]
+	serve_token: Option<String>,
+
+	/// Only answer requests to the serve command that log in as USER with PASSWORD, browsers ask for them. Best given
+	/// like --serve-token
+	#[arg(long, value_name = "USER:PASSWORD", value_parser = parse_login)]
+	serve_login: Option<String>,
+
+	/// What to keep instead of links on filesystems without symlinks, like FAT32 and exFAT
+	#[arg(long, value_name = "KIND", value_enum, default_value_t = LinkFallback::Pointer)]
+	link_fallback: LinkFallback,
+
+	/// Name of the link to the newest folder
+	#[arg(long, value_name = "NAME", value_parser = parse_name, default_value = LATEST)]
+	latest_name: String,
+
+	/// Name of the folder for files without a date
+	#[arg(long, value_name = "NAME", value_parser = parse_name, default_value = OTHER)]
+	other_name: String,
+
+	/// Put files without a date into a folder for their extension inside the other folder, like "other/pdf"
+	#[arg(long)]
+	other_by_extension: bool,
+
+	/// What to do with files without a date, like reference images kept in the screenshot directory
+	#[arg(long, value_name = "ACTION", value_enum, default_value_t = Undated::Other)]
+	undated: Undated,
+
+	/// What to do with camera photos, told by the camera make or model in their EXIF, like those a phone sync drops
+	/// into the screenshot directory
+	#[arg(long, value_name = "ACTION", value_enum, default_value_t = Apart::Sort)]
+	photos: Apart,
+
+	/// What to do with animations, GIFs, APNGs and animated WebPs like Peek and gifski make. They are sorted by the
+	/// date in their name like PNGs, into "recordings" with separate
+	#[arg(long, value_name = "ACTION", value_enum, default_value_t = Apart::Sort)]
+	animations: Apart,
+
+	/// How the files being sorted were organized before, for dates of files that have no other
+	#[arg(skip)]
+	folder_dates: Option<FolderDates>,
+
+	/// Whether each move is asked for first, by the commands with --interactive
+	#[arg(skip)]
+	confirm: bool,
+}
+
+impl Args {
+	fn link_names(&self) -> [&str; 4] {
+		[&self.latest_name, TODAY, LATEST_MONTH, LATEST_YEAR]
+	}
+
+	/// Everything in the screenshot directory this program puts there besides dated folders
+	fn is_reserved(&self, name: &str) -> bool {
+		self.link_names().iter().any(|l| name == *l || Some(name) == pointer_file(Path::new(l)).to_str())
+			|| name == self.other_name
+			|| name == QUARANTINE
+			|| name == PHOTOS
+			|| name == RECORDINGS
+			|| name == STARRED
+	}
+}
+
+fn default_jobs() -> NonZeroUsize {
+	thread::available_parallelism().unwrap_or(NonZeroUsize::MIN)
+}
+
+fn parse_extension(s: &str) -> Result<String, String> {
+	let ext = s.trim().trim_start_matches('.');
+
+	if ext.is_empty() || ext.contains(['.', '/', '\\']) {
+		return Err(format!("\"{s}\" is not a file extension"));
+	}
+
+	Ok(file_type(ext))
+}
+
+/// Lowercase extension with aliases like "jpeg" folded into one name
+fn file_type(ext: &str) -> String {
+	match ext.to_lowercase().as_str() {
+		"jpeg" | "jpe" => "jpg".to_owned(),
+		ext => ext.to_owned(),
+	}
+}
+
+fn parse_name(s: &str) -> Result<String, String> {
+	match Path::new(s).components().collect::<Vec<_>>()[..] {
+		[std::path::Component::Normal(_)] => Ok(s.to_owned()),
+		_ => Err(format!("\"{s}\" is not a plain file name")),
+	}
+}
+
+fn parse_login(s: &str) -> Result<String, String> {
+	match s.split_once(':') {
+		Some((user, password)) if !user.is_empty() && !password.is_empty() => Ok(s.to_owned()),
+		_ => Err("logins are USER:PASSWORD".to_owned()),
+	}
+}
+
+fn parse_tag(s: &str) -> Result<String, String> {
+	if s.is_empty() || s.contains(char::is_whitespace) {
+		return Err(format!("\"{s}\" is not a tag, tags are single words"));
+	}
+
+	Ok(s.to_owned())
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum Command {
+	/// Sort the files in the screenshot directory once, like watching does when it starts
+	Clean {
+		/// Sort the files older than --newer-than too
+		#[arg(long)]
+		all: bool,
+
+		/// Ask before each file is sorted
+		#[arg(short, long)]
+		interactive: bool,
+	},
+	/// Move every file in the organized tree that is not in the folder its date implies to where it belongs
+	Repair {
+		/// Ask before each file is moved
+		#[arg(short, long)]
+		interactive: bool,
+	},
+	/// Report misplaced files, folders that are not dated and broken links without changing anything. Exits with an
+	/// error if there are any
+	Verify,
+	/// Move every file in the organized tree to where --layout puts it and remove the folders left empty. Running it
+	/// again after an interruption picks up where it stopped
+	Migrate {
+		/// Layout the tree was organized with, files with no other date keep the one of their folder
+		#[arg(long, value_name = "TEMPLATE", value_parser = Layout::parse)]
+		from: Option<Layout>,
+
+		/// Only print what would be moved
+		#[arg(long)]
+		dry_run: bool,
+
+		/// Ask before each file is moved
+		#[arg(short, long, conflicts_with = "dry_run")]
+		interactive: bool,
+	},
+	/// Move every file in the organized tree back into the screenshot directory, then remove the links and the
+	/// folders left empty. Name clashes follow --on-conflict
+	Flatten,
+	/// Sort the files of another folder into the tree. Dates come from file names, metadata, folders named like
+	/// "2024/05/01", "2024-05" or "2024", and modification times, in that order
+	Import {
+		/// Folder to take files from, with --mode copy it is left as it is
+		#[arg(value_name = "SOURCE")]
+		source: PathBuf,
+	},
+	/// Put back the files of the last operations recorded in the journal. A running watcher would sort them again,
+	/// stop it first
+	Undo {
+		/// Number of operations to undo
+		#[arg(long, value_name = "N", default_value_t = 1)]
+		last: usize,
+
+		/// Undo everything the last run did instead
+		#[arg(long, conflicts_with = "last")]
+		session: bool,
+	},
+	/// Take a screenshot and sort it into the tree right away, then run --exec, --notify and --upload
+	Capture {
+		/// How to take it [default: grim with --region or --output, the portal otherwise]
+		#[arg(long, value_enum)]
+		backend: Option<Backend>,
+
+		/// Let the user choose what to take, in the dialog of the portal
+		#[arg(long, conflicts_with_all = ["region", "output"])]
+		interactive: bool,
+
+		/// Select a region of the screen with slurp
+		#[arg(long, conflicts_with = "output")]
+		region: bool,
+
+		/// Take only the output named NAME, like "DP-1"
+		#[arg(long, value_name = "NAME")]
+		output: Option<String>,
+	},
+	/// Save the image on the clipboard as a screenshot taken now, with wl-paste on Wayland or xclip on X11
+	Paste,
+	/// Add tags to files of the tree or remove them
+	Tag {
+		#[command(subcommand)]
+		action: TagAction,
+	},
+	/// Print the files of the tree that have all of the tags given, and the size and shape asked for
+	Search {
+		#[arg(long = "tag", value_name = "TAG", value_parser = parse_tag)]
+		tags: Vec<String>,
+
+		/// Only starred files
+		#[arg(long)]
+		starred: bool,
+
+		/// Only images at least PIXELS wide, like 3840 for full screen captures of a 4K monitor
+		#[arg(long, value_name = "PIXELS")]
+		min_width: Option<u32>,
+
+		/// Only images at least PIXELS high
+		#[arg(long, value_name = "PIXELS")]
+		min_height: Option<u32>,
+
+		/// Only images of at least this many million pixels
+		#[arg(long, value_name = "N")]
+		min_megapixels: Option<f64>,
+
+		/// Only images of this shape
+		#[arg(long)]
+		orientation: Option<Orientation>,
+	},
+	/// Serve a JSON API over HTTP for dashboards: GET /api/latest for the newest screenshot, /api/days/2024-05-01 for
+	/// those of a day, /api/file/ID for the image of one and /api/thumb/ID for its thumbnail. With the
+	/// --events-socket of a watcher, /ws is a WebSocket telling about each screenshot it sorts. Runs until stopped
+	Serve {
+		/// Address to listen on, or "unix:" and the path of a Unix socket only this user can connect to. Addresses
+		/// other than those of this machine need --serve-token or --serve-login, screenshots show what is on screen
+		#[arg(long, value_name = "ADDR", default_value = "127.0.0.1:9185", value_parser = api::Listen::parse)]
+		listen: api::Listen,
+	},
+	/// Print the paths of the newest screenshots, newest first. For fzf, an image viewer or an upload script
+	Latest {
+		/// How many
+		#[arg(long, value_name = "N", default_value_t = 1)]
+		list: usize,
+
+		/// Print them as a JSON array
+		#[arg(long, conflicts_with = "null")]
+		json: bool,
+
+		/// End each path with a NUL instead of a newline, for xargs -0
+		#[arg(long)]
+		null: bool,
+	},
+	/// Print the path of the newest screenshot, like `latest` does. Exits with an error if there is none
+	Last,
+	/// Star FILE, it gets a link in the starred folder. Same as tagging it "starred"
+	Star { file: PathBuf },
+	/// Take the star from FILE
+	Unstar { file: PathBuf },
+	/// Open a screenshot or the folder of a day with the program the desktop uses for it
+	#[command(group(clap::ArgGroup::new("what").required(true).args(["latest", "date", "file"])))]
+	Open {
+		/// The newest screenshot
+		#[arg(long)]
+		latest: bool,
+
+		/// The folder of a day, like 2024-05-01
+		#[arg(long, value_name = "DATE", value_parser = Date::parse)]
+		date: Option<Date>,
+
+		/// A file or folder, relative to the screenshot directory if it's not found from here
+		file: Option<PathBuf>,
+	},
+	/// Move screenshots to the trash, with their tags and sidecars
+	#[command(group(clap::ArgGroup::new("what").required(true).args(["file", "date", "older_than"])))]
+	Delete {
+		/// A screenshot in the tree
+		file: Option<PathBuf>,
+
+		/// Every screenshot of a day, like 2024-05-01
+		#[arg(long, value_name = "DATE", value_parser = Date::parse)]
+		date: Option<Date>,
+
+		/// Every screenshot not modified for this long, like "30d". Starred ones are kept, delete them by name
+		#[arg(long, value_name = "AGE", value_parser = parse_duration)]
+		older_than: Option<Duration>,
+	},
+	/// Report bursts of screenshots that look alike and were taken minutes apart, like retries while reporting a bug.
+	/// Compares them with ImageMagick
+	Bursts {
+		/// Longest time between two screenshots of a burst
+		#[arg(long, value_name = "DURATION", value_parser = parse_duration, default_value = "5m")]
+		within: Duration,
+
+		/// Move all but this one of each burst to the trash
+		#[arg(long, value_name = "WHICH", value_enum)]
+		keep: Option<Keep>,
+
+		/// Ask for each burst whether to keep only the last of it
+		#[arg(short, long, conflicts_with = "keep")]
+		interactive: bool,
+	},
+	/// Manage the thumbnails of the screenshots, kept in ".screenshot-manager/thumbs" for galleries and image viewers
+	Thumbs {
+		#[command(subcommand)]
+		action: ThumbsAction,
+	},
+	/// Check the files of the tree against the checksums kept with --checksums, reporting the ones that changed,
+	/// shrank or went missing. Exits with an error if there are any
+	Scrub {
+		/// Also checksum the files that have none yet
+		#[arg(long)]
+		add_missing: bool,
+	},
+	/// Mirror the tree to a WebDAV folder, like one of Nextcloud, with curl. Only files new or changed since the
+	/// last sync are uploaded and those removed are deleted, unless they were changed on the server meanwhile
+	Sync {
+		/// Folder on the server, like https://cloud.example.com/remote.php/dav/files/me/Screenshots
+		url: String,
+
+		/// User to log in as, with the password in $WEBDAV_PASSWORD. Without it ~/.netrc is used if it has the server
+		#[arg(long)]
+		user: Option<String>,
+	},
+	/// Browse the tree in the terminal, starring, tagging and deleting files from the keyboard
+	Browse,
+	/// Check that an instance is watching the directory, it can be read and its links work. Exits with 1 if not
+	Health,
+	/// Print every file in the tree with its date, size, BLAKE3 hash if --checksums kept it and the window it was
+	/// taken in if --window-info knew it
+	Export {
+		#[arg(long, value_enum, default_value_t = Format::Json)]
+		format: Format,
+	},
+	/// Pack the folder of a month into "<month>.tar" in OUTPUT, encrypted with age into "<month>.tar.age" when
+	/// given recipients
+	Archive {
+		/// Month to archive, like 2024-05
+		#[arg(value_parser = Date::parse_month)]
+		month: Date,
+
+		/// Folder the archive is written to
+		#[arg(long, short)]
+		output: PathBuf,
+
+		/// Public key to encrypt the archive to, like "age1..." or "ssh-ed25519 ...". Can be given more than once
+		#[arg(long = "recipient", short, value_name = "RECIPIENT")]
+		recipients: Vec<String>,
+
+		/// File of recipients to encrypt the archive to, one per line
+		#[arg(long = "recipients-file", short = 'R', value_name = "FILE")]
+		recipients_files: Vec<PathBuf>,
+	},
+	/// Look for what would keep watching the screenshot directory from working, like watch limits, permissions and
+	/// names without a date, and tell how to fix it. Exits with an error if anything would
+	Doctor,
+	/// Print the completion script for SHELL, like `screenshot-manager completions bash >
+	/// ~/.local/share/bash-completion/completions/screenshot-manager`. Needs no PATH
+	Completions {
+		shell: Shell,
+
+		/// Print what could follow WORDS instead, for the script
+		#[arg(long, hide = true)]
+		complete: bool,
+
+		#[arg(last = true, hide = true)]
+		words: Vec<String>,
+	},
+	/// Create the screenshot directory and the tree with its folder for files without a date, and write a starter
+	/// ".screenshot-manager.toml". What exists already is left as it is
+	Init {
+		/// Also write a systemd user unit watching the directory, to enable with systemctl
+		#[arg(long)]
+		systemd: bool,
+	},
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum TagAction {
+	/// Give FILE the tags given
+	Add {
+		file: PathBuf,
+		#[arg(required = true, value_parser = parse_tag)]
+		tags: Vec<String>,
+	},
+	/// Take the tags given from FILE
+	Rm {
+		file: PathBuf,
+		#[arg(required = true, value_parser = parse_tag)]
+		tags: Vec<String>,
+	},
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum ThumbsAction {
+	/// Make the thumbnails that are missing or older than their screenshot, like after re-encoding it
+	Rebuild {
+		/// Make all of them again
+		#[arg(long)]
+		all: bool,
+	},
+	/// Remove the thumbnails of screenshots that are gone
+	Prune {
+		/// Then remove the oldest until the rest take at most SIZE, like "500MiB"
+		#[arg(long, value_name = "SIZE", value_parser = parse_size)]
+		max_size: Option<u64>,
+	},
+	/// Report missing, outdated and broken thumbnails and those of screenshots that are gone. Exits with an error if
+	/// there are any
+	Verify,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+	/// The XDG Desktop Portal, works on sandboxed and most Wayland desktops
+	Portal,
+	/// grim and slurp, for wlroots compositors like Sway
+	Grim,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shell {
+	Bash,
+	Zsh,
+	Fish,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+	/// An array of objects
+	Json,
+	/// A header and one row per file
+	Csv,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+	/// Move files out of the screenshot directory
+	Move,
+	/// Leave the originals in place and organize copies
+	Copy,
+	/// Leave the originals in place and organize hardlinks to them
+	Hardlink,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinkFallback {
+	/// Write the folder a link would point to into "<link>.txt"
+	Pointer,
+	/// Write "<link>.txt" and keep a copy of the folder in place of the link
+	Copy,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Small {
+	/// Leave them where they are
+	Ignore,
+	/// Put them into a "quarantine" folder
+	Quarantine,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Undated {
+	/// Put them into the other folder
+	Other,
+	/// Leave them where they are
+	Ignore,
+	/// Put them into the "quarantine" folder to be looked at
+	Quarantine,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Apart {
+	/// Sort them with the screenshots
+	Sort,
+	/// Sort them into a tree of their own, in the "photos" or "recordings" folder
+	Separate,
+	/// Leave them where they are
+	Ignore,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverQuota {
+	/// Remove the oldest files that aren't starred until the tree fits again
+	Prune,
+	/// Leave new screenshots where they are and report it, in a notification too with --notify
+	Refuse,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DroppedFolders {
+	/// Leave them where they are
+	Leave,
+	/// Sort the files in them like those dropped in alone, then remove what's left empty
+	Sort,
+	/// Put them into the other folder as they are
+	Other,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dedupe {
+	/// Delete it
+	Remove,
+	/// Move it to the trash
+	Trash,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Keep {
+	/// The one taken first
+	First,
+	/// The one taken last, likely the one that came out right
+	Last,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Orientation {
+	/// Wider than high, like most screens
+	Landscape,
+	/// Higher than wide, like phone screens and windows snapped to a side
+	Portrait,
+	Square,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Conflict {
+	/// Leave the new file where it is
+	Skip,
+	/// Replace the existing file
+	Overwrite,
+	/// Add a numeric suffix to the new file's name
+	Rename,
+	/// Drop the new file if it's the same as the existing one, add a hash of its contents to its name if not
+	Hash,
+	/// Report an error and leave the new file where it is
+	Error,
+}
+
+fn parse_duration(s: &str) -> Result<Duration, String> {
+	let s = s.trim();
+	let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+	let (value, unit) = s.split_at(split);
+
+	let value = value.parse::<u64>().map_err(|e| format!("invalid duration \"{s}\": {e}"))?;
+
+	match unit {
+		"ms" => Ok(Duration::from_millis(value)),
+		"" | "s" => Ok(Duration::from_secs(value)),
+		"m" => Ok(Duration::from_secs(value * 60)),
+		"h" => Ok(Duration::from_secs(value * 60 * 60)),
+		"d" => Ok(Duration::from_secs(value * 60 * 60 * 24)),
+		_ => Err(format!("invalid duration unit \"{unit}\", expected one of ms, s, m, h, d")),
+	}
+}
+
+fn parse_size(s: &str) -> Result<u64, String> {
+	let s = s.trim();
+	let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+	let (value, unit) = s.split_at(split);
+
+	let value = value.parse::<u64>().map_err(|e| format!("invalid size \"{s}\": {e}"))?;
+
+	let scale = match unit.trim().to_lowercase().as_str() {
+		"" | "b" => 1,
+		"k" | "kb" | "kib" => 1 << 10,
+		"m" | "mb" | "mib" => 1 << 20,
+		"g" | "gb" | "gib" => 1 << 30,
+		_ => return Err(format!("invalid size unit \"{unit}\", expected one of B, KiB, MiB, GiB")),
+	};
+
+	value.checked_mul(scale).ok_or_else(|| format!("size \"{s}\" is too big"))
+}
+
+/// `bytes` in the largest binary unit it is at least one of, like "20 GiB"
+fn format_size(bytes: u64) -> String {
+	const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+	let unit = (0..UNITS.len()).rev().find(|&i| bytes >> (10 * i) > 0).unwrap_or(0);
+	let value = bytes as f64 / (1u64 << (10 * unit)) as f64;
+
+	match value.fract() == 0.0 {
+		true => format!("{value} {}", UNITS[unit]),
+		false => format!("{value:.1} {}", UNITS[unit]),
+	}
+}
+
+/// How long to wait before retrying after the `attempt`th failure in a row: 2s, 4s, 8s... up to about 17 minutes
+fn backoff(attempt: u32) -> Duration {
+	Duration::from_secs(1 << attempt.clamp(1, MAX_RETRIES))
+}
+
+/// Schedules sorting `path` again after it failed, unless it failed too often in a row already
+fn retry(retries: &mut HashMap<PathBuf, u32>, pending: &mut HashMap<PathBuf, Instant>, path: PathBuf) {
+	let attempt = retries.entry(path.clone()).or_default();
+	*attempt += 1;
+
+	if *attempt > MAX_RETRIES {
+		info!("Giving up on \"{}\" after {MAX_RETRIES} retries, until it is written again", path.display());
+		retries.remove(&path);
+		return;
+	}
+
+	let delay = backoff(*attempt);
+	info!("Retry \"{}\" in {}s", path.display(), delay.as_secs());
+	pending.insert(path, Instant::now() + delay);
 }
 
 fn check_exists(path: &Path) -> bool {
@@ -60,248 +995,1898 @@ fn check_exists(path: &Path) -> bool {
 	}
 }
 
-fn update_file(path: &Path, file: &Path) -> anyhow::Result<()> {
-	if !file.is_file() {
-		return Ok(());
+/// Files something is still writing, sorting them would move them out from under it
+fn is_temporary(filename: &str) -> bool {
+	const SUFFIXES: &[&str] = &[".part", ".partial", ".tmp", ".temp", ".crdownload", ".download", ".swp", ".swo", "~"];
+
+	let lower = filename.to_lowercase();
+
+	// Hidden files include macOS screencapture's, which writes "Screen Shot 2024-05-01 at 10.23.45.png" to a
+	// dotfile first and renames it when done. Emacs keeps "#name#" while editing
+	filename.starts_with('.')
+		|| (filename.starts_with('#') && filename.ends_with('#'))
+		|| SUFFIXES.iter().any(|s| lower.ends_with(s))
+}
+
+/// Sorts `file` into the tree, returns whether it had to be put somewhere
+fn update_file(args: &Args, path: &Path, file: &Path) -> anyhow::Result<Option<PathBuf>> {
+	let Some(to) = destination(args, path, file)? else {
+		return Ok(None);
+	};
+
+	if file.parent() == Some(path.join(&to).as_path()) {
+		note_sorted(args, path, &path.join(&to)); // Put there by someone else maybe, like a sync
+		return Ok(None); // Already where it belongs
 	}
 
-	let filename = file.file_name().unwrap(); // Already checked
-	let filename_lossy = filename.to_string_lossy();
-	let matches = NAME_REGEX.captures(&filename_lossy);
+	if !quota::admit(args, path, file) {
+		return Ok(None);
+	}
+
+	if to.starts_with(PHOTOS) {
+		info!("\"{}\" is a camera photo", file.display());
+	} else if to.starts_with(RECORDINGS) {
+		info!("\"{}\" is an animation", file.display());
+	} else if to == Path::new(QUARANTINE) {
+		match args.min_size.filter(|&m| file.metadata().is_ok_and(|f| f.len() < m)) {
+			Some(min) => info!("\"{}\" is smaller than {min} bytes", file.display()),
+			None => info!("\"{}\" has no date", file.display()),
+		}
+	}
+
+	move_files(args, path, file, &to)
+}
+
+/// Sorts `file`, new in the screenshot directory, into the tree in `path` unless --dedupe finds it there already.
+/// Screen recordings go into that of --recordings-into
+fn sort_new(args: &Args, path: &Path, file: &Path) -> anyhow::Result<Option<PathBuf>> {
+	let path = match args.recordings_into.as_deref() {
+		Some(recordings) if file.is_file() && metadata::is_recording(file)? => {
+			info!("\"{}\" is a screen recording", file.display());
+			recordings
+		}
+		_ => path,
+	};
+
+	let Some(action) = args.dedupe else {
+		return update_file(args, path, file);
+	};
+
+	let Some(original) = duplicate_of(args, path, file)? else {
+		return update_file(args, path, file);
+	};
+
+	Event::Skipped { path: file, reason: format!("is the same as \"{}\"", original.display()) }.emit();
 
-	match matches {
-		Some(matches) => {
-			let year = matches.name(YEAR).unwrap();
-			let month = matches.name(MONTH).unwrap();
-			let day = matches.name(DAY).unwrap();
+	if args.mode != Mode::Move {
+		return Ok(None); // The original is the user's
+	}
 
-			move_files(
-				path,
-				&PathBuf::from(filename),
-				&PathBuf::new().join(year.as_str()).join(month.as_str()).join(day.as_str()),
-			)
-			.map_err(anyhow::Error::msg)?;
+	match action {
+		Dedupe::Remove => {
+			info!("Remove duplicate \"{}\"", file.display());
+			fs::remove_file(file)?;
 		}
-		None => {
-			move_files(path, &PathBuf::from(filename), &PathBuf::from(OTHER)).map_err(anyhow::Error::msg)?;
+		Dedupe::Trash => {
+			info!("Trash duplicate \"{}\"", file.display());
+			trash::put(file).map_err(|e| anyhow::anyhow!("trashing it: {e}"))?;
 		}
 	}
 
-	Ok(())
+	MOVED_AWAY.lock().unwrap().insert(file.to_path_buf());
+
+	Ok(None)
 }
 
-fn update_latest(path: &Path) -> anyhow::Result<()> {
-	let dir_filter = |f: Result<fs::DirEntry, _>| f.ok().filter(|f| f.file_type().ok().is_some_and(|f| f.is_dir()));
-	let max_name_fold = |acc: u32, e: fs::DirEntry| {
-		e.file_name().into_string().map(|s| s.parse::<u32>().unwrap_or(0)).unwrap_or(0).max(acc)
+/// A file with the same contents as `file` in the folder of the tree in `path` it would be sorted into
+fn duplicate_of(args: &Args, path: &Path, file: &Path) -> anyhow::Result<Option<PathBuf>> {
+	let Some(to) = destination(args, path, file)? else {
+		return Ok(None);
 	};
 
-	let paths = fs::read_dir(path)?;
-	let year = paths.into_iter().filter_map(dir_filter).fold(0, max_name_fold);
-	let year_path = path.join(year.to_string());
+	let entries = match fs::read_dir(path.join(to)) {
+		Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None), // Nothing was sorted there yet
+		res => res?,
+	};
 
-	let paths = fs::read_dir(&year_path)?;
-	let month = paths.into_iter().filter_map(dir_filter).fold(0, max_name_fold);
-	let month_path = year_path.join(format!("{:0>2}", &month));
+	let size = file.metadata()?.len();
 
-	let paths = fs::read_dir(&month_path)?;
-	let day = paths.into_iter().filter_map(dir_filter).fold(0, max_name_fold);
-	let day_path = month_path.join(format!("{:0>2}", &day));
+	for entry in entries {
+		let other = entry?.path();
 
-	let latest = path.join(LATEST);
-	if latest.exists() {
-		if !latest.is_symlink() {
-			eprintln!("{} is not a symlink", latest.display());
-			return Ok(()); // Do not touch
+		// Compared by size first, reading both is only worth it if they can be the same
+		if other != file
+			&& other.metadata().is_ok_and(|m| m.is_file() && m.len() == size)
+			&& same_contents(file, &other)?
+		{
+			return Ok(Some(other));
 		}
+	}
+
+	Ok(None)
+}
 
-		fs::remove_file(&latest)?;
+/// Folder relative to the screenshot directory `file` belongs in, `None` if it is to be left alone
+fn destination(args: &Args, path: &Path, file: &Path) -> anyhow::Result<Option<PathBuf>> {
+	// Links are never sorted, those of the starred folder would be sorted again and again
+	if !fs::symlink_metadata(file).is_ok_and(|m| m.is_file()) || file.starts_with(path.join(STATE_DIR)) {
+		return Ok(None);
 	}
 
-	if !day_path.exists() {
-		eprintln!("Path found \"{}\" for {}-{}-{} does not exist", day_path.display(), year, month, day);
-	} else {
-		println!("Symlink: \"{}\" -> \"{}\"", day_path.display(), latest.display());
-		std::os::unix::fs::symlink(day_path, latest)?;
+	let filename = file.file_name().unwrap(); // Already checked
+	let filename_lossy = filename.to_string_lossy();
+
+	if is_temporary(&filename_lossy)
+		|| args.exclude.iter().any(|g| g.matches(&filename_lossy))
+		|| sidecar::is_sidecar(file)
+	{
+		return Ok(None);
 	}
 
-	Ok(())
-}
+	if file.parent() == Some(path) && args.is_reserved(&filename_lossy) {
+		return Ok(None); // Pointer files standing in for links
+	}
 
-fn clean_directory(path: &Path) -> anyhow::Result<()> {
-	println!("Started cleaning \"{}\"", path.display());
+	let kind = file_kind(file)?;
 
-	// Move all screenshots
-	let paths = fs::read_dir(path)?;
-	paths
-		.into_iter()
-		.filter_map(|f| -> Option<(PathBuf, anyhow::Error)> {
-			let file = match f {
-				Ok(f) => f,
-				Err(e) => {
-					eprintln!("Error while iterating files: {e}");
-					return None;
-				}
-			};
+	if !args.extensions.is_empty() && !args.extensions.contains(&kind) {
+		return Ok(None);
+	}
+
+	if args.min_size.is_some_and(|m| file.metadata().is_ok_and(|f| f.len() < m)) {
+		return Ok((args.small_files == Small::Quarantine).then(|| PathBuf::from(QUARANTINE)));
+	}
+
+	// Photos and animations can have a tree of their own
+	let apart = if args.photos != Apart::Sort && metadata::is_photo(file)? {
+		Some((args.photos, PHOTOS))
+	} else if args.animations != Apart::Sort && metadata::is_animated(file)? {
+		Some((args.animations, RECORDINGS))
+	} else {
+		None
+	};
+
+	let tree = match apart {
+		Some((Apart::Ignore, _)) => return Ok(None),
+		apart => apart.map(|(_, tree)| tree),
+	};
+
+	Ok(match (file_date(args, file, &kind)?, args.undated) {
+		(Some((date, time)), _) => {
+			let mut source = Source::of(&filename_lossy, args.monitor_pattern.as_ref());
 
-			if let Err(e) = update_file(path, &file.path()) {
-				Some((file.path(), e))
-			} else {
-				None
+			if args.layout.uses_resolution() {
+				source.resolution = metadata::dimensions(file)?;
 			}
-		})
-		.for_each(|e| eprintln!("Error while processing \"{}\": {}", e.0.display(), e.1));
 
-	// Update latest directory
-	update_latest(path)?;
+			let dir = args.layout.dir(&date, time.as_ref(), &source);
+			Some(match tree {
+				Some(tree) => Path::new(tree).join(dir),
+				None => dir,
+			})
+		}
+		(None, _) if tree.is_some() => tree.map(PathBuf::from),
+		(None, Undated::Ignore) => None,
+		(None, Undated::Quarantine) => Some(PathBuf::from(QUARANTINE)),
+		(None, Undated::Other) if args.other_by_extension && !kind.is_empty() => {
+			Some(Path::new(&args.other_name).join(&kind))
+		}
+		(None, Undated::Other) => Some(PathBuf::from(&args.other_name)),
+	})
+}
 
-	println!("Cleaning done");
+/// Type of `file` by its contents, or its extension if they are of no known image type
+fn file_kind(file: &Path) -> anyhow::Result<String> {
+	// Screenshot tools don't always get the extension right, or give one at all
+	Ok(match metadata::image_type(file)? {
+		Some(kind) => kind.to_owned(),
+		None => file_type(&file.extension().unwrap_or_default().to_string_lossy()),
+	})
+}
 
-	Ok(())
+/// Date `file` of type `kind` was taken, from its name, metadata, folder or modification time as allowed
+fn file_date(args: &Args, file: &Path, kind: &str) -> anyhow::Result<Option<(Date, Option<Time>)>> {
+	let filename_lossy = file.file_name().unwrap_or_default().to_string_lossy();
+	let animated = || matches!(kind, "gif" | "webp") && metadata::is_animated(file).unwrap_or_default();
+	let video = || args.recordings_into.is_some() && metadata::is_video(file).unwrap_or_default();
+	let by_name = kind == "png" || args.extensions.iter().any(|e| e == kind) || animated() || video();
+	let matches = NAME_REGEX.captures(&filename_lossy).filter(|_| by_name);
+
+	let date = match matches {
+		Some(matches) => name_date(args, &matches)?,
+		None if by_name => EPOCH_REGEX.captures(&filename_lossy).map(|c| epoch_date(args, &c[1])),
+		None => None,
+	};
+
+	let date = match date {
+		None if args.metadata_dates => metadata::capture_date(file, &args.timezone)?.map(|d| (d, None)),
+		date => date,
+	};
+
+	let date = match (date, &args.folder_dates) {
+		(None, Some(folders)) => folders.date(file),
+		(date, _) => date,
+	};
+
+	Ok(match date {
+		None if args.mtime_fallback => {
+			Some(args.timezone.local(file.metadata()?.modified()?)).map(|(d, t)| (d, Some(t)))
+		}
+		date => date,
+	})
 }
 
-fn move_files(dir: &Path, file: &Path, to: &Path) -> anyhow::Result<()> {
-	let from = dir.join(file);
-	let to = dir.join(to);
-	let end_file = to.join(file);
+/// Name `from` gets in `to` with --rename, its own without it or a date for it. `taken` tells names in use
+fn new_name(args: &Args, from: &Path, to: &Path, taken: impl Fn(&Path) -> bool) -> anyhow::Result<PathBuf> {
+	let name = from.file_name().unwrap(); // Only called with files
 
-	println!("Move \"{}\" -> \"{}\"", from.display(), end_file.display());
+	let Some(template) = &args.rename else {
+		return Ok(to.join(name));
+	};
 
-	if !to.exists() {
-		println!("Create \"{}\"", to.display());
-		fs::create_dir_all(&to)?;
+	let Some((date, time)) = file_date(args, from, &file_kind(from)?)? else {
+		return Ok(to.join(name));
+	};
+
+	let (name, stem) = (name.to_string_lossy(), from.file_stem().unwrap_or_default().to_string_lossy());
+	let values = naming::Values { date, time, source: Source::of(&name, args.monitor_pattern.as_ref()), name: &stem };
+	let ext = from.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+	let file = |seq| to.join(template.render(&values, seq) + &ext);
+
+	Ok(match template.numbered() {
+		true => (1..).map(file).find(|f| !taken(f)).unwrap(), // Infinite iterator
+		false => file(1),
+	})
+}
+
+/// Date and time in a file name `matches` of [`NAME_REGEX`] is of, `None` if there is no such day. Names like
+/// "2024-13-01" look like dates but aren't, digits after a date that aren't a time leave it without one
+fn name_date(args: &Args, matches: &regex::Captures) -> anyhow::Result<Option<(Date, Option<Time>)>> {
+	let date = Date { year: matches[YEAR].parse()?, month: matches[MONTH].parse()?, day: matches[DAY].parse()? };
+
+	if !date.exists() {
+		return Ok(None);
 	}
 
-	fs::rename(&from, &end_file)?;
+	let (Some(hour), Some(minute)) = (matches.name(HOUR), matches.name(MINUTE)) else {
+		return Ok(Some((date, None)));
+	};
 
-	Ok(())
+	let second = matches.name(SECOND).map_or(Ok(0), |s| s.as_str().parse())?;
+	let time = Time { hour: hour.as_str().parse()?, minute: minute.as_str().parse()?, second };
+
+	if !time.exists() {
+		return Ok(Some((date, None)));
+	}
+
+	Ok(Some(match &args.name_timezone {
+		Some(zone) => {
+			let (date, time) = args.timezone.wall_clock(zone.instant(date, time));
+			(date, Some(time))
+		}
+		None => (date, Some(time)),
+	}))
 }
 
-fn main() {
-	// Parse arguments
-	let args = Args::parse();
+/// Date and time of a Unix timestamp of 10 digits in seconds or 13 in milliseconds
+fn epoch_date(args: &Args, digits: &str) -> (Date, Option<Time>) {
+	let value: i64 = digits.parse().unwrap(); // Only digits, too few to overflow
+	let secs = if digits.len() == 13 { value / 1000 } else { value };
+	let (date, time) = args.timezone.wall_clock(secs);
+
+	(date, Some(time))
+}
 
-	let screenshot_dir = PathBuf::from(&args.screenshot_dir).canonicalize();
+enum FileState {
+	Ready,
+	Growing,
+	Truncated,
+}
 
-	if let Err(e) = screenshot_dir {
-		eprintln!("Could not canonicalize \"{}\": {}", args.screenshot_dir, e);
-		std::process::exit(1);
+/// Whether `file`, which was `size` bytes long a moment ago, is done being written
+fn file_state(file: &Path, size: u64) -> anyhow::Result<FileState> {
+	if fs::metadata(file)?.len() != size {
+		return Ok(FileState::Growing);
 	}
 
-	let screenshot_dir = screenshot_dir.unwrap();
+	let mut f = fs::File::open(file)?;
+	let mut start = [0; 8];
+	let mut end = [0; 12];
 
-	if !check_exists(&screenshot_dir) {
-		eprintln!("Directory \"{}\" does not exist", screenshot_dir.display());
-		std::process::exit(1);
+	if size < (start.len() + end.len()) as u64 {
+		return Ok(if size == 0 { FileState::Truncated } else { FileState::Ready });
 	}
 
-	if !screenshot_dir.is_dir() {
-		eprintln!("\"{}\" is not a directory", screenshot_dir.display());
-		std::process::exit(1);
+	f.read_exact(&mut start)?;
+	f.seek(SeekFrom::End(-(end.len() as i64)))?;
+	f.read_exact(&mut end)?;
+
+	let complete = if start.starts_with(PNG_SIGNATURE) {
+		end.ends_with(PNG_END)
+	} else if start.starts_with(JPEG_START) {
+		end.ends_with(JPEG_END)
+	} else {
+		true // Nothing else we know how to check
+	};
+
+	Ok(if complete { FileState::Ready } else { FileState::Truncated })
+}
+
+fn update_latest(args: &Args, path: &Path) -> anyhow::Result<()> {
+	if args.prune_empty {
+		prune_empty(args, path, 0)?;
 	}
 
-	// First run cleaning
+	update_starred(args, path)?;
 
-	if let Err(e) = clean_directory(&screenshot_dir) {
-		eprintln!("Error while cleaning directory \"{}\": {e}", screenshot_dir.display());
-		std::process::exit(1);
+	if args.today_link {
+		// Only once something was sorted into it, so the link never points nowhere
+		let today = path.join(args.layout.dir(&args.timezone.local(SystemTime::now()).0, None, &Source::default()));
+		point_link(args, &path.join(TODAY), Some(today.as_path()).filter(|t| t.is_dir()))?;
 	}
 
-	// Setup watcher
+	let cached = NEWEST_DAY.lock().unwrap().get(path).map(|(_, day)| day.clone()).filter(|day| day.is_dir());
 
-	let signals = Signals::new([SIGINT, SIGTERM]);
+	let day_path = match cached {
+		Some(day) => day,
+		None => match newest_day(args, path)? {
+			Some(newest) => {
+				let day = newest.1.clone();
+				NEWEST_DAY.lock().unwrap().insert(path.to_path_buf(), newest);
+				day
+			}
+			None => return Ok(()),
+		},
+	};
 
-	if let Err(e) = signals {
-		eprintln!("Error while creating signal handler: {e}");
-		std::process::exit(1);
-	}
+	if args.period_links {
+		let relative = day_path.strip_prefix(path).unwrap_or(&day_path);
+		let up_to = |depth: usize| path.join(relative.iter().take(depth).collect::<PathBuf>());
+
+		if let Some(depth) = args.layout.year_depth().filter(|&d| d <= args.layout.day_depth()) {
+			point_link(args, &path.join(LATEST_YEAR), Some(&up_to(depth)))?;
+		}
 
-	let mut signals = signals.unwrap();
+		if let Some(depth) = args.layout.month_depth().filter(|&d| d <= args.layout.day_depth()) {
+			point_link(args, &path.join(LATEST_MONTH), Some(&up_to(depth)))?;
+		}
+	}
 
-	let (tx, rx) = std::sync::mpsc::channel();
-	let t = tx.clone();
+	point_link(args, &path.join(&args.latest_name), Some(&day_path))
+}
 
-	let running = Arc::new(AtomicBool::new(true));
-	let r = running.clone();
+/// Newest day folder of each tree with its sort keys, kept up to date as files are sorted instead of finding it
+/// again every time the links are updated
+static NEWEST_DAY: Mutex<BTreeMap<PathBuf, DayFolder>> = Mutex::new(BTreeMap::new());
+
+/// Sort keys of the folders down to a day, and the folder of the day
+type DayFolder = (Vec<Vec<u32>>, PathBuf);
+
+/// The newest day folder in the tree in `path`, going down the newest folder of each level
+fn newest_day(args: &Args, path: &Path) -> anyhow::Result<Option<DayFolder>> {
+	// Follows symlinks, date folders may link to other filesystems
+	let dir_filter = |f: Result<fs::DirEntry, _>| f.ok().filter(|f| f.path().is_dir());
+	let newest = |dir: &Path, depth: usize| -> anyhow::Result<Option<(Vec<u32>, PathBuf)>> {
+		let dirs = fs::read_dir(dir)?.filter_map(dir_filter);
+		let key = |e: &fs::DirEntry| {
+			let name = e.file_name().to_string_lossy().into_owned();
+			args.layout.folder_key(depth, &name).filter(|_| depth > 0 || !args.is_reserved(&name))
+		};
+		Ok(dirs.filter_map(|e| Some((key(&e)?, e.path()))).max())
+	};
 
-	let watcher = notify::RecommendedWatcher::new(tx.clone(), notify::Config::default());
+	let (mut keys, mut day_path) = (vec![], path.to_path_buf());
 
-	thread::spawn(move || {
-		for sig in signals.forever() {
-			match sig {
-				SIGINT => {
-					println!("CTRL-C received, terminating...");
-					r.store(false, Ordering::SeqCst);
-					_ = t.send(Err(notify::Error::generic("SIGINT")));
-					break;
-				}
-				SIGTERM => {
-					println!("Terminate received, finishing...");
-					r.store(false, Ordering::SeqCst);
-					_ = t.send(Err(notify::Error::generic("SIGTERM")));
-					break;
-				}
-				_ => (),
+	for depth in 0..args.layout.day_depth() {
+		match newest(&day_path, depth)? {
+			Some((key, dir)) => {
+				keys.push(key);
+				day_path = dir;
+			}
+			None => {
+				eprintln!("No dated folder found in \"{}\"", day_path.display());
+				return Ok(None);
 			}
 		}
-	});
+	}
+
+	Ok(Some((keys, day_path)))
+}
+
+/// Takes the day folder of `folder`, a file was just sorted into it, as the newest of the tree in `path` if it is
+fn note_sorted(args: &Args, path: &Path, folder: &Path) {
+	let Ok(relative) = folder.strip_prefix(path) else {
+		return;
+	};
+
+	let names: Vec<_> = relative.iter().take(args.layout.day_depth()).map(|n| n.to_string_lossy()).collect();
 
-	if let Err(e) = watcher {
-		eprintln!("Error creating watcher for \"{}\":{e}", screenshot_dir.display());
-		std::process::exit(1);
+	if names.len() < args.layout.day_depth() || names.first().is_some_and(|n| args.is_reserved(n)) {
+		return; // Not dated, like the other folder
 	}
 
-	println!("Watcher starting for \"{}\"", screenshot_dir.display());
-	let mut watcher = watcher.unwrap();
-	let res = watcher.watch(&screenshot_dir, notify::RecursiveMode::NonRecursive);
+	let Some(keys) = names.iter().enumerate().map(|(depth, name)| args.layout.folder_key(depth, name)).collect() else {
+		return;
+	};
 
-	if let Err(e) = res {
-		eprintln!("Error watching \"{}\": {e}", screenshot_dir.display());
-		std::process::exit(1);
+	// Without one yet the tree wasn't looked at, the first update finds it
+	if let Some(newest) = NEWEST_DAY.lock().unwrap().get_mut(path).filter(|(newest, _)| keys > *newest) {
+		*newest = (keys, path.join(names.iter().map(|n| n.as_ref()).collect::<PathBuf>()));
 	}
+}
 
-	loop {
-		let res = rx.recv();
+/// Whether a link update_latest keeps in the tree `path` was removed, points nowhere or was replaced by something
+/// else
+fn links_broken(args: &Args, path: &Path) -> bool {
+	let depths =
+		[args.layout.month_depth(), args.layout.year_depth()].map(|d| d.is_some_and(|d| d <= args.layout.day_depth()));
+	let period = [LATEST_MONTH, LATEST_YEAR].into_iter().zip(depths).filter(|&(_, has)| has && args.period_links);
+	let known = NEWEST_DAY.lock().unwrap().contains_key(path);
+
+	std::iter::once(args.latest_name.as_str()).chain(period.map(|(name, _)| name)).any(|name| {
+		let link = path.join(name);
+		// Stands in for links where there are none
+		let pointer = pointer_file(&link).is_file();
+
+		match link.symlink_metadata() {
+			Ok(metadata) if metadata.is_symlink() => !link.is_dir(),
+			Ok(metadata) => !(metadata.is_dir() && pointer),
+			// Never made yet if there's no dated folder
+			Err(_) => !pointer && known,
+		}
+	})
+}
 
-		if let Err(e) = res {
-			eprintln!("Error receiving MPSC message: {e}");
-			std::process::exit(1);
+/// Forgets the newest day folder of the tree in `path`, folders changed by others may have changed it
+fn forget_newest_day(path: &Path) {
+	NEWEST_DAY.lock().unwrap().remove(path);
+}
+
+/// Removes empty dated folders `depth` levels below the screenshot directory inside `dir`, and returns whether `dir`
+/// is empty afterwards
+fn prune_empty(args: &Args, dir: &Path, depth: usize) -> anyhow::Result<bool> {
+	let mut empty = true;
+
+	for entry in fs::read_dir(dir)? {
+		let entry = entry?;
+		let name = entry.file_name().to_string_lossy().into_owned();
+		let dated = args.layout.folder_key(depth, &name).is_some() && !(depth == 0 && args.is_reserved(&name));
+
+		// Symlinked folders may be mount points for other filesystems, leave them
+		if dated && entry.file_type()?.is_dir() && prune_empty(args, &entry.path(), depth + 1)? {
+			info!("Remove empty \"{}\"", entry.path().display());
+			fs::remove_dir(entry.path())?;
+		} else {
+			empty = false;
 		}
+	}
 
-		let res = res.unwrap();
+	Ok(empty)
+}
 
-		if let Err(e) = res {
-			if !running.load(Ordering::SeqCst) {
-				// Graceful shutdown
-				std::process::exit(0);
-			}
+/// Links every starred file from the starred folder, and only those. It's left alone until something is starred
+fn update_starred(args: &Args, path: &Path) -> anyhow::Result<()> {
+	let dir = path.join(STARRED);
+	let starred: Vec<PathBuf> =
+		tags::read(path)?.into_iter().filter(|(f, t)| t.contains(tags::STAR) && f.is_file()).map(|(f, _)| f).collect();
+
+	if starred.is_empty() && !dir.exists() {
+		return Ok(());
+	}
+
+	fs::create_dir_all(&dir)?;
 
-			eprintln!("Error with watcher event: {e}");
-			std::process::exit(1);
+	let mut wanted = HashMap::new();
+
+	for file in &starred {
+		let name = dir.join(file.file_name().unwrap_or_default()); // Only files are tagged
+		let name = if wanted.contains_key(&name) { free_name(&name, |f| wanted.contains_key(f)) } else { name };
+		let target = match args.relative_links {
+			// One level up, out of the starred folder, gets to the screenshot directory
+			true => Path::new("..").join(file.strip_prefix(path).unwrap_or(file)),
+			false => file.clone(),
+		};
+
+		wanted.insert(name, target);
+	}
+
+	for entry in fs::read_dir(&dir)? {
+		let link = entry?.path();
+
+		if link.is_symlink() && wanted.get(&link) != fs::read_link(&link).ok().as_ref() {
+			fs::remove_file(&link)?;
+		}
+	}
+
+	for (link, target) in wanted {
+		if !link.is_symlink() {
+			platform::link_file(&target, &link)?;
 		}
+	}
 
-		let event = res.unwrap();
+	Ok(())
+}
 
-		use notify::event::AccessMode;
-		use notify::EventKind;
+/// Makes `link` point at `target`, or removes it if there's no target. Anything that isn't a link is left alone
+fn point_link(args: &Args, link: &Path, target: Option<&Path>) -> anyhow::Result<()> {
+	// Links always live next to the folders they point into
+	let target = target.map(|t| match link.parent().filter(|_| args.relative_links) {
+		Some(parent) => t.strip_prefix(parent).unwrap_or(t),
+		None => t,
+	});
 
-		if let EventKind::Access(AccessKind::Close(AccessMode::Write)) = event.kind {
-			let mut work_done = false;
+	let pointer = pointer_file(link);
+	let pointed = fs::read_to_string(&pointer).ok().map(PathBuf::from);
+	let existed = link.symlink_metadata().is_ok() || pointed.is_some();
 
-			for path in event.paths {
-				if path.is_file() {
-					if let Err(e) = update_file(&screenshot_dir, path.as_path()) {
-						eprintln!("Error while handling \"{}\": {e}", path.display());
-					}
-					work_done = true;
-				}
+	// Broken links don't "exist"
+	if link.symlink_metadata().is_ok() {
+		if link.is_symlink() {
+			if fs::read_link(link).ok().as_deref() == target {
+				return Ok(());
 			}
 
-			if work_done {
-				if let Err(e) = update_latest(&screenshot_dir) {
-					eprintln!("Error while updating \"latest\" link: {e}");
-				}
+			platform::remove_link(link)?;
+		} else if link.is_dir() && pointed.is_some() {
+			// A copy made in place of a link
+			if let Some(target) = target.filter(|&t| pointed.as_deref() == Some(t)) {
+				return mirror(&resolve_link(link, target), link);
 			}
-		}
+
+			info!("Remove copy \"{}\"", link.display());
+			fs::remove_dir_all(link)?;
+		} else if fs::read_dir(link).is_ok_and(|mut d| d.next().is_none()) {
+			info!("Remove empty folder \"{}\" in place of the link", link.display());
+			fs::remove_dir(link)?;
+		} else if link.metadata().is_ok_and(|m| m.is_file() && m.len() == 0) {
+			info!("Remove empty file \"{}\" in place of the link", link.display());
+			fs::remove_file(link)?;
+		} else {
+			eprintln!("{} is not a symlink", link.display());
+			return Ok(()); // Do not touch
+		}
+	} else if args.link_fallback == LinkFallback::Pointer && pointed.is_some() && pointed.as_deref() == target {
+		return Ok(()); // Links didn't work last time either
+	}
+
+	if pointed.is_some() {
+		fs::remove_file(&pointer)?;
+	}
+
+	let Some(target) = target else {
+		if existed {
+			Event::LatestUpdated { link, target: None }.emit();
+		}
+
+		return Ok(());
+	};
+
+	match platform::link_dir(target, link) {
+		Err(e) if platform::links_unsupported(&e) => {
+			info!("Symlinks not supported ({e}), writing \"{}\" instead", pointer.display());
+			fs::write(&pointer, target.as_os_str().as_encoded_bytes())?;
+
+			if args.link_fallback == LinkFallback::Copy {
+				mirror(&resolve_link(link, target), link)?;
+			}
+		}
+		res => res?,
+	}
+
+	Event::LatestUpdated { link, target: Some(target) }.emit();
+	Ok(())
+}
+
+/// File standing in for `link` where there are no links
+fn pointer_file(link: &Path) -> PathBuf {
+	let mut name = link.file_name().unwrap_or_default().to_owned();
+	name.push(".txt");
+	link.with_file_name(name)
+}
+
+/// Where `target` is when the link at `link` points to it, relative targets start next to the link
+fn resolve_link(link: &Path, target: &Path) -> PathBuf {
+	link.parent().map(|p| p.join(target)).unwrap_or_else(|| target.to_path_buf())
+}
+
+/// Makes `to` a copy of the directory `from`, leaving files that look unchanged alone
+fn mirror(from: &Path, to: &Path) -> anyhow::Result<()> {
+	fs::create_dir_all(to)?;
+
+	let names = fs::read_dir(from)?.map(|e| Ok(e?.file_name())).collect::<std::io::Result<Vec<_>>>()?;
+
+	for entry in fs::read_dir(to)? {
+		let entry = entry?;
+
+		if !names.contains(&entry.file_name()) {
+			match entry.file_type()?.is_dir() {
+				true => fs::remove_dir_all(entry.path())?,
+				false => fs::remove_file(entry.path())?,
+			}
+		}
+	}
+
+	for name in names {
+		let (from, to) = (from.join(&name), to.join(&name));
+
+		if from.is_dir() {
+			mirror(&from, &to)?;
+		} else if to.metadata().map(|m| m.len()).ok() != Some(from.metadata()?.len()) {
+			info!("Copy \"{}\" -> \"{}\"", from.display(), to.display());
+			fs::copy(&from, &to)?;
+		}
+	}
+
+	Ok(())
+}
+
+/// Sorts the files in the screenshot directory `dir` into `tree`, returns those that failed to be tried again
+fn clean_directory(args: &Args, dir: &Path, tree: &Path) -> anyhow::Result<Vec<PathBuf>> {
+	info!("Started cleaning \"{}\"", dir.display());
+
+	// Move all screenshots
+	let mut files = vec![];
+	for f in fs::read_dir(dir)? {
+		match f {
+			Ok(f) => files.push(f.path()),
+			Err(e) => eprintln!("Error while iterating files: {e}"),
+		}
+	}
+
+	let dropped =
+		|f: &mut PathBuf| args.dropped_folders != DroppedFolders::Leave && is_dropped_folder(args, dir, tree, f);
+	let folders: Vec<_> = files.extract_if(.., dropped).collect();
+
+	if let Some(age) = args.newer_than {
+		let cutoff = SystemTime::now() - age;
+		let old = files.extract_if(.., |f| f.metadata().and_then(|m| m.modified()).is_ok_and(|t| t < cutoff)).count();
+
+		if old > 0 {
+			info!("Leaving {old} files older than --newer-than, `clean --all` sorts them");
+		}
+	}
+
+	let total = files.len();
+	let (next, moved, errors) = (AtomicUsize::new(0), AtomicUsize::new(0), AtomicUsize::new(0));
+	let last_report = Mutex::new(Instant::now());
+	let failed = Mutex::new(vec![]);
+
+	// Moving is mostly waiting on the filesystem, and --stable waits on every file
+	let sort = || loop {
+		let i = next.fetch_add(1, Ordering::Relaxed);
+		let Some(file) = files.get(i) else {
+			break;
+		};
+
+		// Backlogs of thousands of files take a while
+		if let Ok(mut last) = last_report.try_lock() {
+			if last.elapsed() >= PROGRESS_INTERVAL {
+				let (moved, errors) = (moved.load(Ordering::Relaxed), errors.load(Ordering::Relaxed));
+				info!("Processed {i} of {total} files, {moved} sorted, {errors} errors, {} left", total - i);
+				*last = Instant::now();
+			}
+		}
+
+		if let Some(window) = args.stable.filter(|_| file.is_file()) {
+			let state = fs::metadata(file).map_err(Into::into).and_then(|m| {
+				thread::sleep(window);
+				file_state(file, m.len())
+			});
+
+			match state {
+				Ok(FileState::Ready) => (),
+				Ok(_) => {
+					let reason = "still being written or truncated".to_owned();
+					Event::Skipped { path: file, reason }.emit();
+					continue;
+				}
+				Err(e) => {
+					Event::Error { path: Some(file), message: e.to_string() }.emit();
+					errors.fetch_add(1, Ordering::Relaxed);
+					failed.lock().unwrap().push(file.clone());
+					continue;
+				}
+			}
+		}
+
+		match sort_new(args, tree, file) {
+			Ok(Some(_)) => _ = moved.fetch_add(1, Ordering::Relaxed),
+			Ok(None) => (),
+			Err(e) => {
+				Event::Error { path: Some(file), message: e.to_string() }.emit();
+				errors.fetch_add(1, Ordering::Relaxed);
+				failed.lock().unwrap().push(file.clone());
+			}
+		}
+	};
+
+	thread::scope(|scope| {
+		// Questions come one at a time anyway
+		for _ in 0..if args.confirm { 1 } else { args.jobs.get() } {
+			scope.spawn(sort);
+		}
+	});
+
+	for folder in folders {
+		update_folder(args, dir, tree, &folder);
+	}
+
+	for tree in trees(args, tree) {
+		if let Err(e) = quota::enforce(args, tree) {
+			Event::Error { path: None, message: format!("keeping under --max-total: {e}") }.emit();
+		}
+
+		space::check(args, tree);
+	}
+
+	// The watcher isn't started yet, there are no events to expect. Those of other sources may still come
+	CLAIMED.lock().unwrap().retain(|f| !f.starts_with(tree));
+	MOVED_AWAY.lock().unwrap().retain(|f| !f.starts_with(dir) && !f.starts_with(tree));
+
+	let (moved, errors) = (moved.into_inner(), errors.into_inner());
+	if moved + errors > 0 {
+		info!("Sorted {moved} of {total} files, {errors} errors");
+	}
+
+	// Update latest directory
+	for tree in trees(args, tree) {
+		update_latest(args, tree)?;
+	}
+
+	info!("Cleaning done");
+
+	Ok(failed.into_inner().unwrap())
+}
+
+/// The tree in `tree` and that of --recordings-into, if given
+fn trees<'a>(args: &'a Args, tree: &'a Path) -> impl Iterator<Item = &'a Path> {
+	std::iter::once(tree).chain(args.recordings_into.as_deref())
+}
+
+/// Whether `path` is a folder dropped into the screenshot directory `dir`, not one of the tree in `tree` or of this
+/// program
+fn is_dropped_folder(args: &Args, dir: &Path, tree: &Path, path: &Path) -> bool {
+	let name = path.file_name().unwrap_or_default().to_string_lossy();
+
+	path.parent() == Some(dir)
+		&& path.is_dir()
+		&& !path.is_symlink()
+		&& name != STATE_DIR
+		&& !tree.starts_with(path)
+		&& (dir != tree || !commands::is_managed(args, &name))
+}
+
+/// Sorts the files in `folder`, dropped into the screenshot directory `dir`, into `tree` as --dropped-folders says.
+/// Those that fail are reported and left in it
+fn update_folder(args: &Args, dir: &Path, tree: &Path, folder: &Path) {
+	info!("Sorting folder \"{}\"", folder.display());
+
+	let mut files = vec![];
+
+	if let Err(e) = commands::tree_files(folder, &mut files) {
+		Event::Error { path: Some(folder), message: e.to_string() }.emit();
+		return;
+	}
+
+	for file in files {
+		let res = match args.dropped_folders {
+			DroppedFolders::Leave => return,
+			DroppedFolders::Sort => update_file(args, tree, &file),
+			DroppedFolders::Other if is_temporary(&file.file_name().unwrap_or_default().to_string_lossy()) => continue,
+			DroppedFolders::Other => {
+				// Its path below the screenshot directory, with the name of the folder
+				let within = file.parent().and_then(|p| p.strip_prefix(dir).ok()).unwrap_or(Path::new(""));
+				move_files(args, tree, &file, &Path::new(&args.other_name).join(within))
+			}
+		};
+
+		if let Err(e) = res {
+			Event::Error { path: Some(&file), message: e.to_string() }.emit();
+		}
+	}
+
+	if args.mode == Mode::Move {
+		match commands::remove_empty(folder) {
+			Ok(true) => _ = MOVED_AWAY.lock().unwrap().insert(folder.to_path_buf()),
+			Ok(false) => (),
+			Err(e) => Event::Error { path: Some(folder), message: format!("removing it: {e}") }.emit(),
+		}
+	}
+}
+
+/// Puts `from` into the folder `to` inside `dir`, returns where if it was put there
+fn move_files(args: &Args, dir: &Path, from: &Path, to: &Path) -> anyhow::Result<Option<PathBuf>> {
+	let to = dir.join(to);
+
+	if args.mode != Mode::Move {
+		// Numbered names may have given it any number then
+		let copy = new_name(args, from, &to, |f| f.exists() && !same_contents(from, f).unwrap_or_default())?;
+
+		if copy.exists() && same_contents(from, &copy)? {
+			return Ok(None); // Organized on a previous run
+		}
+	}
+
+	// Files sorted at the same time may be renamed to the same free name
+	let mut claimed = CLAIMED.lock().unwrap();
+	let taken = |f: &Path| f.exists() || claimed.contains(f);
+	let mut end_file = new_name(args, from, &to, taken)?;
+
+	if taken(&end_file) {
+		match args.on_conflict {
+			Conflict::Skip => {
+				let reason = format!("\"{}\" already exists", end_file.display());
+				Event::Skipped { path: from, reason }.emit();
+				return Ok(None);
+			}
+			Conflict::Overwrite => info!("Overwrite \"{}\"", end_file.display()),
+			Conflict::Rename => end_file = free_name(&end_file, taken),
+			Conflict::Hash => {
+				// Names with the same hash may still be of different files, their bytes tell
+				let hashed = hashed_name(from, &end_file)?;
+				let same = |f: &&PathBuf| f.is_file() && same_contents(from, f).unwrap_or_default();
+
+				if let Some(existing) = [&end_file, &hashed].into_iter().find(same) {
+					let reason = format!("is the same as \"{}\"", existing.display());
+					Event::Skipped { path: from, reason }.emit();
+
+					if args.mode == Mode::Move {
+						info!("Remove duplicate \"{}\"", from.display());
+						fs::remove_file(from)?;
+						MOVED_AWAY.lock().unwrap().insert(from.to_path_buf());
+					}
+
+					return Ok(None);
+				}
+
+				end_file = if taken(&hashed) { free_name(&hashed, taken) } else { hashed };
+			}
+			Conflict::Error => anyhow::bail!("\"{}\" already exists", end_file.display()),
+		}
+	}
+
+	claimed.insert(end_file.clone());
+	drop(claimed);
+
+	let action = match args.mode {
+		Mode::Move => "Move",
+		Mode::Copy => "Copy",
+		Mode::Hardlink => "Link",
+	};
+
+	if args.confirm && !confirm(action, from, &end_file)? {
+		CLAIMED.lock().unwrap().remove(&end_file);
+		return Ok(None);
+	}
+
+	// Free for the retry if it failed
+	if let Err(e) = place(args, from, &to, &end_file) {
+		CLAIMED.lock().unwrap().remove(&end_file);
+		return Err(e);
+	}
+
+	Event::Moved { action, from, to: &end_file }.emit();
+
+	if args.mode == Mode::Move {
+		MOVED_AWAY.lock().unwrap().insert(from.to_path_buf());
+	}
+
+	if let Err(e) = journal::record(dir, &action.to_lowercase(), from, &end_file) {
+		eprintln!("Error while recording \"{}\" in the journal: {e}", end_file.display());
+	}
+
+	if args.mode == Mode::Move {
+		if let Err(e) = tags::moved(dir, from, &end_file) {
+			eprintln!("Error while moving the tags of \"{}\": {e}", from.display());
+		}
+
+		if let Err(e) = sidecar::moved(from, &end_file) {
+			eprintln!("Error while moving the sidecar of \"{}\": {e}", from.display());
+		}
+	}
+
+	let checksum_moved = match args.mode {
+		Mode::Move => checksums::moved(from, &end_file).unwrap_or_else(|e| {
+			eprintln!("Error while moving the checksum of \"{}\": {e}", from.display());
+			false
+		}),
+		_ => false,
+	};
+
+	if args.checksums && !checksum_moved {
+		if let Err(e) = checksums::add(&end_file) {
+			eprintln!("Error while checksumming \"{}\": {e}", end_file.display());
+		}
+	}
+
+	if args.sidecars && !sidecar::path(&end_file).exists() {
+		if let Err(e) = sidecar::write(dir, &end_file, from) {
+			eprintln!("Error while writing the sidecar of \"{}\": {e}", end_file.display());
+		}
+	}
+
+	metrics::sorted(fs::metadata(&end_file).map(|m| m.len()).unwrap_or_default());
+	note_sorted(args, dir, &to);
+
+	Ok(Some(end_file))
+}
+
+/// What was answered for every move left with "a" or "q", all are asked for until then
+static ANSWER_ALL: Mutex<Option<bool>> = Mutex::new(None);
+
+/// Asks whether to `action` `from` to `to`: yes, no, all the rest or none of them
+fn confirm(action: &str, from: &Path, to: &Path) -> anyhow::Result<bool> {
+	let mut all = ANSWER_ALL.lock().unwrap(); // Held while asking, one question at a time
+
+	if let Some(answer) = *all {
+		return Ok(answer);
+	}
+
+	loop {
+		print!("{action} \"{}\" -> \"{}\"? [y,n,a,q] ", from.display(), to.display());
+		std::io::stdout().flush()?;
+
+		let mut answer = String::new();
+
+		// Nobody left to answer
+		if std::io::stdin().read_line(&mut answer)? == 0 {
+			println!();
+			*all = Some(false);
+			return Ok(false);
+		}
+
+		match answer.trim() {
+			"y" => return Ok(true),
+			"n" => return Ok(false),
+			"a" => *all = Some(true),
+			"q" => *all = Some(false),
+			_ => {
+				println!("y: yes, n: no, a: this and all the rest, q: none of the rest");
+				continue;
+			}
+		}
+
+		return Ok(all.unwrap());
+	}
+}
+
+/// Puts `from` at `end_file` in the folder `to` as --mode says
+fn place(args: &Args, from: &Path, to: &Path, end_file: &Path) -> anyhow::Result<()> {
+	if !to.exists() {
+		info!("Create \"{}\"", to.display());
+		fs::create_dir_all(to)?;
+	}
+
+	match args.mode {
+		Mode::Move => match fs::rename(from, end_file) {
+			Err(e) if e.kind() == ErrorKind::CrossesDevices => move_across_devices(from, end_file)?,
+			res => {
+				res?;
+				// Both directory entries changed, neither may be lost on power loss
+				sync_parent(end_file)?;
+				sync_parent(from)?;
+			}
+		},
+		Mode::Copy => {
+			copy_durably(from, end_file)?;
+		}
+		Mode::Hardlink => {
+			if end_file.exists() {
+				fs::remove_file(end_file)?; // Overwriting, links can't replace files
+			}
+
+			match fs::hard_link(from, end_file) {
+				Err(e) if e.kind() == ErrorKind::CrossesDevices => {
+					anyhow::bail!("{e}, hardlinks can't cross filesystems (try --mode copy)")
+				}
+				res => res?,
+			}
+
+			sync_parent(end_file)?;
+		}
+	}
+
+	Ok(())
+}
+
+fn move_across_devices(from: &Path, to: &Path) -> anyhow::Result<()> {
+	info!("Copy \"{}\" -> \"{}\" across filesystems", from.display(), to.display());
+
+	copy_durably(from, to)?;
+
+	if !same_contents(from, to)? {
+		fs::remove_file(to)?;
+		anyhow::bail!("copy of \"{}\" does not match the original", from.display());
+	}
+
+	fs::remove_file(from)?;
+	sync_parent(from)?;
+
+	Ok(())
+}
+
+/// Copies `from` to `to` so that `to` is either complete and on disk or not there at all, even if the system goes
+/// down in the middle of it
+fn copy_durably(from: &Path, to: &Path) -> std::io::Result<()> {
+	// Hidden and partial, so nothing sorts it while it's being written
+	let mut name = std::ffi::OsString::from(".");
+	name.push(to.file_name().unwrap_or_default());
+	name.push(".partial");
+	let temp = to.with_file_name(name);
+
+	// Failing now is clearer than running out halfway
+	space::ensure(to.parent().unwrap_or(to), fs::metadata(from)?.len())?;
+
+	let res = fs::copy(from, &temp).and_then(|_| fs::File::open(&temp)?.sync_all()).and_then(|_| fs::rename(&temp, to));
+
+	if res.is_err() {
+		_ = fs::remove_file(&temp);
+	}
+
+	res?;
+	sync_parent(to)
+}
+
+fn sync_parent(file: &Path) -> std::io::Result<()> {
+	file.parent().map_or(Ok(()), platform::sync_dir)
+}
+
+fn same_contents(a: &Path, b: &Path) -> std::io::Result<bool> {
+	let (mut a, mut b) = (BufReader::new(fs::File::open(a)?), BufReader::new(fs::File::open(b)?));
+
+	loop {
+		let (chunk_a, chunk_b) = (a.fill_buf()?, b.fill_buf()?);
+		let len = chunk_a.len().min(chunk_b.len());
+
+		if chunk_a[..len] != chunk_b[..len] {
+			return Ok(false);
+		}
+
+		if len == 0 {
+			return Ok(chunk_a.is_empty() && chunk_b.is_empty());
+		}
+
+		a.consume(len);
+		b.consume(len);
+	}
+}
+
+/// First `name_N.ext` next to `file` that isn't taken yet
+fn free_name(file: &Path, taken: impl Fn(&Path) -> bool) -> PathBuf {
+	let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+	let ext = file.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+
+	(1..).map(|n| file.with_file_name(format!("{stem}_{n}{ext}"))).find(|f| !taken(f)).unwrap()
+	// Infinite iterator
+}
+
+/// `file` next to itself with the start of the BLAKE3 hash of `from` added to its name, like
+/// "name_1a2b3c4d5e6f7a8b.png". Only a name, whether files are the same goes by comparing them
+fn hashed_name(from: &Path, file: &Path) -> std::io::Result<PathBuf> {
+	let hash = blake3::hash(fs::File::open(from)?)?;
+	let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+	let ext = file.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+
+	Ok(file.with_file_name(format!("{stem}_{}{ext}", &hash[..16])))
+}
+
+/// Keeps other instances away from `path` for as long as the returned file is open
+fn lock_instance(path: &Path) -> anyhow::Result<Result<fs::File, String>> {
+	let dir = path.join(STATE_DIR);
+	fs::create_dir_all(&dir)?;
+
+	let mut file = fs::OpenOptions::new().read(true).write(true).create(true).truncate(false).open(dir.join("lock"))?;
+
+	match file.try_lock() {
+		Ok(()) => {}
+		Err(fs::TryLockError::WouldBlock) => {
+			let mut pid = String::new();
+			_ = file.read_to_string(&mut pid);
+			return Ok(Err(pid.trim().to_owned()));
+		}
+		Err(fs::TryLockError::Error(e)) => return Err(e.into()),
+	}
+
+	// Only for telling who holds it, the lock itself is what counts
+	file.set_len(0)?;
+	write!(file, "{}", std::process::id())?;
+
+	Ok(Ok(file))
+}
+
+/// Pid of the instance watching `path`, if one is
+fn watching_instance(path: &Path) -> std::io::Result<Option<String>> {
+	let mut file = match fs::File::open(path.join(STATE_DIR).join("lock")) {
+		Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+		res => res?,
+	};
+
+	match file.try_lock_shared() {
+		Ok(()) => Ok(None),
+		Err(fs::TryLockError::WouldBlock) => {
+			let mut pid = String::new();
+			file.read_to_string(&mut pid)?;
+			Ok(Some(pid.trim().to_owned()))
+		}
+		Err(fs::TryLockError::Error(e)) => Err(e),
+	}
+}
+
+fn is_write_done(kind: &EventKind, polling: bool) -> bool {
+	match kind {
+		EventKind::Access(AccessKind::Close(AccessMode::Write)) => true,
+		// The poll watcher and some platforms can only tell a file appeared or changed
+		EventKind::Create(_) | EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Any) => {
+			polling || !platform::REPORTS_CLOSE_WRITE
+		}
+		// Downloads and macOS screencapture write a temporary file and rename it once done
+		EventKind::Modify(ModifyKind::Name(RenameMode::To | RenameMode::Both)) => true,
+		// FSEvents reports both names without telling which is which
+		EventKind::Modify(ModifyKind::Name(RenameMode::Any)) => !platform::REPORTS_CLOSE_WRITE,
+		_ => false,
+	}
+}
+
+fn main() {
+	// Parse arguments
+	let given: Vec<_> = std::env::args_os().collect();
+	let args = parse_args(&given, &[], None).unwrap_or_else(|e| e.exit());
+	events::set_json(args.json_events);
+	events::set_style(args.quiet, args.no_color);
+
+	if let Some(Command::Completions { shell, complete, words }) = &args.command {
+		if *complete {
+			completions::complete(words);
+		} else {
+			print!("{}", completions::script(*shell));
+		}
+
+		return;
+	}
+
+	let sources = match &args.config {
+		Some(_) if args.command.is_some() => {
+			eprintln!("Commands work on one screenshot directory, give its PATH instead of --config");
+			std::process::exit(EXIT_USAGE);
+		}
+		Some(_) if args.screenshot_dir.is_some() => {
+			eprintln!("The screenshot directories are those of the sources of --config, give no PATH with it");
+			std::process::exit(EXIT_USAGE);
+		}
+		Some(config) => match config::read(config, args.profile.as_deref()) {
+			Ok(sources) => sources.iter().map(|s| source_args(&given, Some(config), &s.args, Some(&s.path))).collect(),
+			Err(e) => {
+				eprintln!("Error: {e}");
+				std::process::exit(EXIT_USAGE);
+			}
+		},
+		None => vec![source_args(&given, None, &[], None)],
+	};
+
+	// The directories init creates can't be opened before
+	if let (Some(Command::Init { .. }), [args]) = (&args.command, &sources[..]) {
+		if let Err(e) = commands::create_dirs(args) {
+			eprintln!("Error: {e}");
+			std::process::exit(EXIT_IO);
+		}
+	}
+
+	let sources: Vec<_> = sources.into_iter().map(open_source).collect();
+
+	if let Some(command) = &args.command {
+		let (args, _, tree) = &sources[0]; // Only one without --config
+
+		if let Err(e) = commands::run(args, command, tree) {
+			eprintln!("Error: {e}");
+			std::process::exit(EXIT_FAILED);
+		}
+
+		return;
+	}
+
+	let mut locks = vec![];
+
+	for (_, _, tree) in &sources {
+		match lock_instance(tree) {
+			Ok(Ok(lock)) => locks.push(lock),
+			Ok(Err(pid)) => {
+				eprintln!(
+					"Error locking \"{}\": already being watched by another instance (pid {pid})",
+					tree.display()
+				);
+				std::process::exit(EXIT_LOCKED);
+			}
+			Err(e) => {
+				eprintln!("Error locking \"{}\": {e}", tree.display());
+				std::process::exit(EXIT_SETUP);
+			}
+		}
+	}
+
+	if let Some(socket) = &args.events_socket {
+		if let Err(e) = events::serve_subscribers(socket) {
+			eprintln!("Error listening on \"{}\": {e}", socket.display());
+			std::process::exit(EXIT_SETUP);
+		}
+	}
+
+	if let Some(addr) = args.metrics {
+		let trees: Vec<_> = sources.iter().map(|(args, _, tree)| (args.clone(), tree.clone())).collect();
+		let problems = move || trees.iter().flat_map(|(args, tree)| commands::health_problems(args, tree)).collect();
+
+		if let Err(e) = metrics::serve(addr, problems) {
+			eprintln!("Error serving metrics on {addr}: {e}");
+			std::process::exit(EXIT_SETUP);
+		}
+	}
+
+	let running = Arc::new(AtomicBool::new(true));
+	let r = running.clone();
+	let channels: Vec<_> = sources.iter().map(|_| std::sync::mpsc::channel()).collect();
+	let senders: Vec<_> = channels.iter().map(|(tx, _)| tx.clone()).collect();
+
+	let signals = platform::on_termination(move |sig| {
+		let name = match sig {
+			SIGINT => {
+				info!("CTRL-C received, terminating...");
+				"SIGINT"
+			}
+			SIGTERM => {
+				info!("Terminate received, finishing...");
+				"SIGTERM"
+			}
+			_ => return,
+		};
+
+		r.store(false, Ordering::SeqCst);
+
+		for t in &senders {
+			_ = t.send(Err(notify::Error::generic(name)));
+		}
+	});
+
+	if let Err(e) = signals {
+		eprintln!("Error while creating signal handler: {e}");
+		std::process::exit(EXIT_SETUP);
+	}
+
+	thread::scope(|scope| {
+		for ((args, dir, tree), (tx, rx)) in sources.iter().zip(channels) {
+			let running = &running;
+			scope.spawn(move || watch(args, dir, tree, tx, rx, running));
+		}
+	});
+}
+
+/// Parses `options`, those of the environment and those of the command line `given`, the later overriding the
+/// earlier. `path` is the screenshot directory of a source, given last
+fn parse_args(given: &[OsString], options: &[String], path: Option<&str>) -> Result<Args, clap::Error> {
+	use clap::error::ErrorKind;
+
+	let env = config::env_options(&Args::command()).map_err(|e| Args::command().error(ErrorKind::InvalidValue, e))?;
+
+	let parse = |dir: Option<OsString>| {
+		let (program, given) = given.split_first().map_or((None, given), |(p, given)| (Some(p.clone()), given));
+		let path = path.into_iter().flat_map(|p| ["--".into(), p.into()]);
+		let options = options.iter().chain(&env).map(Into::into);
+		Args::try_parse_from(program.into_iter().chain(options).chain(dir).chain(given.iter().cloned()).chain(path))
+	};
+
+	// Watching requires it from clap already, commands but completions here
+	let missing = |res: &Result<Args, clap::Error>| match res {
+		Err(e) => e.kind() == ErrorKind::MissingRequiredArgument,
+		Ok(args) => {
+			args.screenshot_dir.is_none()
+				&& args.config.is_none()
+				&& !matches!(args.command, None | Some(Command::Completions { .. }))
+		}
+	};
+
+	let res = parse(None);
+
+	if !missing(&res) {
+		return res;
+	}
+
+	// Before what was given, where a command would follow it
+	if let Some(Ok(args)) = std::env::var_os(config::ENV_DIR).map(|dir| parse(Some(dir))) {
+		return Ok(args);
+	}
+
+	res.and_then(|_| {
+		let message = "the following required arguments were not provided:\n  <PATH>";
+		Err(Args::command().error(ErrorKind::MissingRequiredArgument, message))
+	})
+}
+
+/// Options of a screenshot directory: `options`, those of its `DIR_CONFIG`, those of the environment and those of
+/// the command line `given`, the later overriding the earlier. `path` is that of a source of the config `config`, the
+/// one on the command line without
+fn source_args(given: &[OsString], config: Option<&Path>, options: &[String], path: Option<&str>) -> Args {
+	let parse = |options: &[String]| parse_args(given, options, path);
+
+	// Without the usage, which would be that of the command line
+	let fail = |what: String, e: &dyn std::fmt::Display| -> ! {
+		let e = e.to_string();
+		let e = e.lines().next().unwrap_or_default().trim_start_matches("error: ").to_owned();
+		eprintln!("Error in {what}: {e}");
+		std::process::exit(EXIT_USAGE);
+	};
+
+	let args = match (parse(options), path.zip(config)) {
+		(Ok(args), _) => args,
+		(Err(e), Some((path, config))) => fail(format!("source \"{path}\" of \"{}\"", config.display()), &e),
+		(Err(e), None) => e.exit(),
+	};
+
+	let dir = PathBuf::from(args.screenshot_dir.as_deref().unwrap_or_default()); // Required without --config
+
+	match config::dir_options(&dir) {
+		Ok(None) => args,
+		Ok(Some(own)) => {
+			let options: Vec<_> = options.iter().cloned().chain(own).collect();
+			parse(&options).unwrap_or_else(|e| fail(format!("\"{}\"", dir.join(config::DIR_CONFIG).display()), &e))
+		}
+		Err(e) => {
+			eprintln!("Error: {e}");
+			std::process::exit(EXIT_USAGE);
+		}
+	}
+}
+
+/// `args` ready for use, with the screenshot directory and the tree they name. Exits if either is unusable
+fn open_source(mut args: Args) -> (Args, PathBuf, PathBuf) {
+	args.layout.set_locale(args.locale.unwrap_or_else(Locale::from_env));
+
+	let dir = PathBuf::from(args.screenshot_dir.as_deref().unwrap_or_default()); // Required without --config
+	let dir = existing_dir(&dir);
+	let tree = args.into.as_deref().map_or_else(|| dir.clone(), existing_dir);
+	args.recordings_into = args.recordings_into.as_deref().map(existing_dir);
+
+	(args, dir, tree)
+}
+
+/// `dir` made absolute, exits if it's not a folder
+fn existing_dir(dir: &Path) -> PathBuf {
+	let canonical = dir.canonicalize();
+
+	if let Err(e) = canonical {
+		eprintln!("Could not canonicalize \"{}\": {}", dir.display(), e);
+		std::process::exit(EXIT_NO_DIR);
+	}
+
+	let dir = canonical.unwrap();
+
+	if !check_exists(&dir) {
+		eprintln!("Directory \"{}\" does not exist", dir.display());
+		std::process::exit(EXIT_NO_DIR);
+	}
+
+	if !dir.is_dir() {
+		eprintln!("\"{}\" is not a directory", dir.display());
+		std::process::exit(EXIT_NO_DIR);
+	}
+
+	dir
+}
+
+/// A watcher for `paths` sending to `tx`, polling every `poll` if given. Errors tell what was being done
+fn start_watcher(
+	poll: Option<Duration>,
+	tx: &Sender<notify::Result<notify::Event>>,
+	paths: &[(&Path, notify::RecursiveMode)],
+) -> Result<Box<dyn Watcher>, (String, notify::Error)> {
+	let watcher: notify::Result<Box<dyn Watcher>> = match poll {
+		Some(interval) => notify::PollWatcher::new(tx.clone(), notify::Config::default().with_poll_interval(interval))
+			.map(|w| Box::new(w) as Box<dyn Watcher>),
+		None => notify::RecommendedWatcher::new(tx.clone(), notify::Config::default())
+			.map(|w| Box::new(w) as Box<dyn Watcher>),
+	};
+
+	let mut watcher = watcher.map_err(|e| ("creating the watcher".to_owned(), e))?;
+
+	for &(path, mode) in paths {
+		watcher.watch(path, mode).map_err(|e| (format!("watching \"{}\"", path.display()), e))?;
+	}
+
+	Ok(watcher)
+}
+
+/// Whether the watcher follows what other programs do in the whole tree, a watch for each of its folders. Only
+/// needed to re-sort files or when sidecars, checksums or windows are kept of them
+fn follows_tree(args: &Args) -> bool {
+	args.recursive || args.sidecars || args.checksums || args.window_info
+}
+
+/// Sorts the files in the screenshot directory `dir` into `tree`, then every new one it gets until terminated
+fn watch(
+	args: &Args,
+	dir: &Path,
+	tree: &Path,
+	tx: Sender<notify::Result<notify::Event>>,
+	rx: Receiver<notify::Result<notify::Event>>,
+	running: &AtomicBool,
+) {
+	// The tree of --recordings-into has a quota of its own
+	let recordings = args.recordings_into.as_deref().map(|r| (r, args.recordings_max_total));
+
+	for (tree, max_total) in [(tree, args.max_total)].into_iter().chain(recordings) {
+		if let Err(e) = quota::init(&Args { max_total, ..args.clone() }, tree) {
+			eprintln!("Error measuring \"{}\": {e}", tree.display());
+			std::process::exit(EXIT_IO);
+		}
+	}
+
+	// What changed in the tree while nothing watched it, before the clean changes it more
+	let appeared = changes::catch_up(args, tree).unwrap_or_else(|e| {
+		Event::Error { path: Some(tree), message: format!("catching up with the tree: {e}") }.emit();
+		vec![]
+	});
+
+	// First run cleaning
+
+	let failed = match clean_directory(args, dir, tree) {
+		Ok(failed) => failed,
+		Err(e) => {
+			eprintln!("Error while cleaning directory \"{}\": {e}", dir.display());
+			std::process::exit(EXIT_IO);
+		}
+	};
+
+	// Setup watcher
+
+	// The whole tree when files removed or renamed in it must be noticed right away. A tree elsewhere is watched too
+	// then, unless one holds the other
+	let follow_tree = follows_tree(args);
+	let watched = match (tree.starts_with(dir), dir.starts_with(tree)) {
+		_ if !follow_tree => vec![(dir, notify::RecursiveMode::NonRecursive)],
+		(false, false) => vec![(dir, notify::RecursiveMode::Recursive), (tree, notify::RecursiveMode::Recursive)],
+		(true, _) => vec![(dir, notify::RecursiveMode::Recursive)],
+		(_, true) => vec![(tree, notify::RecursiveMode::Recursive)],
+	};
+
+	let mut poll = args.poll;
+
+	// Kept until watching ends, dropping it stops the events
+	let _watcher = loop {
+		match start_watcher(poll, &tx, &watched) {
+			Ok(watcher) => break watcher,
+			Err((what, e)) => match platform::watch_limit_hit(&e).filter(|_| poll.is_none()) {
+				Some(sysctl) => {
+					eprintln!(
+						"Inotify reached its limit {what}, polling every {LIMIT_POLL:?} instead. Raise {sysctl} with sysctl to \
+						watch for events again"
+					);
+					poll = Some(LIMIT_POLL);
+				}
+				None => {
+					eprintln!("Error {what}: {e}");
+					std::process::exit(EXIT_SETUP);
+				}
+			},
+		}
+	};
+
+	match poll {
+		Some(interval) => info!("Poll watcher starting for \"{}\" every {:?}", dir.display(), interval),
+		None => info!("Watcher starting for \"{}\"", dir.display()),
+	}
+
+	// Links to files and folders of the tree, the starred folder is full of them
+	let links: Vec<_> = args.link_names().iter().chain([&STARRED]).map(|l| tree.join(l)).collect();
+	let mut today = args.timezone.local(SystemTime::now()).0;
+
+	// Paths waiting for their settle delay to pass, with the time they become ready
+	let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+	// Paths gone from the tree, with the time they count as removed unless a rename turns up for them
+	let mut vanished: HashMap<PathBuf, Instant> = HashMap::new();
+	// Failures in a row of paths that are waiting to be retried
+	let mut retries: HashMap<PathBuf, u32> = HashMap::new();
+	// Pending paths that were created but not written yet, a write makes them wait for its close instead
+	let mut created: HashSet<PathBuf> = HashSet::new();
+	// Sizes of pending paths waiting out --stable, they count as ready if they still have them
+	let mut sizes: HashMap<PathBuf, u64> = HashMap::new();
+	// Failures in a row of updating the links, and when to try again
+	let mut link_retry: Option<(u32, Instant)> = None;
+	// When the links are updated for the files sorted since they last were
+	let mut link_batch: Option<Instant> = None;
+	// When the links are checked next, and the trees whose links were broken then
+	let (mut next_link_check, mut links_broke) = (Instant::now() + LINK_CHECK, HashSet::new());
+
+	// When to clean the directory again with --rescan-interval
+	let mut next_rescan = args.rescan_interval.map(|interval| Instant::now() + interval);
+
+	for path in failed {
+		retry(&mut retries, &mut pending, path);
+	}
+
+	// Files sorted into wrong folders while nothing watched are sorted again like those dropped in now
+	if args.recursive {
+		pending.extend(appeared.into_iter().map(|path| (path, Instant::now())));
+	}
+
+	loop {
+		// Files removed or renamed in the tree by something else
+		let mut tree_changed = false;
+
+		// Wake up now and then to notice the day changing
+		let day_check = args.today_link.then(|| Instant::now() + Duration::from_secs(60));
+
+		let deadlines = pending.values().chain(vanished.values()).copied().chain(link_retry.map(|(_, t)| t));
+		let deadlines = deadlines.chain(link_batch).chain([next_link_check]);
+
+		let res = match deadlines.chain(day_check).chain(next_rescan).min() {
+			Some(deadline) => rx.recv_timeout(deadline.saturating_duration_since(Instant::now())),
+			None => rx.recv().map_err(RecvTimeoutError::from),
+		};
+
+		match res {
+			Err(RecvTimeoutError::Timeout) => (),
+			Err(e) => {
+				eprintln!("Error receiving MPSC message: {e}");
+				std::process::exit(EXIT_IO);
+			}
+			Ok(Err(e)) => {
+				if !running.load(Ordering::SeqCst) {
+					// Without events from the tree, what others changed in it meanwhile is left for the next start
+					if follow_tree {
+						if let Err(e) = changes::save(args, tree) {
+							Event::Error { path: Some(tree), message: format!("saving the snapshot: {e}") }.emit();
+						}
+					}
+
+					// Graceful shutdown, once every source has returned
+					return;
+				}
+
+				eprintln!("Error with watcher event: {e}");
+				std::process::exit(EXIT_IO);
+			}
+			Ok(Ok(mut event)) => {
+				metrics::event_received();
+
+				let ours = |path: &Path| MOVED_AWAY.lock().unwrap().remove(path);
+				let in_tree = |path: &Path| changes::in_tree(args, tree, path);
+
+				match &event.kind {
+					EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if event.paths.len() == 2 => {
+						let (from, to) = (&event.paths[0], &event.paths[1]);
+						vanished.remove(from);
+
+						if !ours(from) && in_tree(from) && in_tree(to) {
+							changes::renamed(tree, from, to);
+							tree_changed = true;
+						}
+					}
+					EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(RenameMode::From | RenameMode::Any)) => {
+						// Renames come in halves, the one with both names may still be on its way
+						for path in event.paths.iter().filter(|p| in_tree(p) && !p.exists()) {
+							vanished.insert(path.clone(), Instant::now() + RENAME_GRACE);
+						}
+					}
+					_ => (),
+				}
+
+				// Dropped folders wait until nothing changed in them for a moment, they may still be copied in
+				let change = match event.kind {
+					EventKind::Access(kind) => kind == AccessKind::Close(AccessMode::Write),
+					_ => true,
+				};
+
+				if args.dropped_folders != DroppedFolders::Leave && change {
+					event.paths.retain(|path| {
+						let folder = path.ancestors().find(|a| a.parent() == Some(dir));
+
+						match folder.filter(|f| is_dropped_folder(args, dir, tree, f)) {
+							Some(folder) => {
+								pending.insert(folder.to_path_buf(), Instant::now() + args.settle.max(CREATE_GRACE));
+								false
+							}
+							None => true,
+						}
+					});
+				}
+
+				let polling = poll.is_some();
+				let waits_for_close = platform::REPORTS_CLOSE_WRITE && !polling;
+
+				if waits_for_close && matches!(event.kind, EventKind::Modify(ModifyKind::Data(_))) {
+					for path in &event.paths {
+						if created.remove(path) {
+							pending.remove(path);
+						}
+					}
+				}
+
+				let write_done = is_write_done(&event.kind, polling);
+				let arrived =
+					matches!(event.kind, EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(RenameMode::To)));
+
+				if write_done || (arrived && waits_for_close) {
+					for path in event.paths {
+						if links.iter().any(|l| path.starts_with(l)) {
+							continue; // Seen through a symlink, the real path gets its own event
+						}
+
+						if !args.recursive && path.parent() != Some(dir) {
+							continue; // Already in the tree
+						}
+
+						// Files dragged in with a folder, or there before the watch of a new folder, have no events
+						if path.is_dir() {
+							if arrived && args.recursive {
+								let mut files = vec![];
+								_ = commands::tree_files(&path, &mut files);
+								files.retain(|f| !pending.contains_key(f));
+
+								for file in files {
+									pending.insert(file.clone(), Instant::now() + args.settle.max(CREATE_GRACE));
+									created.insert(file);
+								}
+							}
+
+							continue;
+						}
+
+						// Repeated events push the deadline back, written again it gets a fresh set of retries
+						retries.remove(&path);
+						sizes.remove(&path);
+
+						if write_done {
+							created.remove(&path);
+							pending.insert(path, Instant::now() + args.settle);
+						} else if !pending.contains_key(&path) {
+							pending.insert(path.clone(), Instant::now() + args.settle.max(CREATE_GRACE));
+							created.insert(path);
+						}
+					}
+				}
+			}
+		}
+
+		let now = Instant::now();
+
+		vanished.retain(|path, &mut t| {
+			if t > now {
+				return true;
+			}
+
+			if !MOVED_AWAY.lock().unwrap().remove(path) && !path.exists() {
+				changes::removed(tree, path);
+				tree_changed = true;
+			}
+
+			false
+		});
+
+		let ready: Vec<PathBuf> = pending.iter().filter(|(_, &t)| t <= now).map(|(p, _)| p.clone()).collect();
+		let mut work_done = tree_changed;
+
+		if tree_changed {
+			trees(args, tree).for_each(forget_newest_day);
+		}
+
+		// Editors saving as, moves into the directory and network filesystems don't always have events
+		if let Some(interval) = args.rescan_interval.filter(|_| next_rescan.is_some_and(|t| t <= now)) {
+			match clean_directory(args, dir, tree) {
+				Ok(failed) => failed.into_iter().for_each(|path| retry(&mut retries, &mut pending, path)),
+				Err(e) => Event::Error { path: Some(dir), message: format!("rescanning: {e}") }.emit(),
+			}
+
+			next_rescan = Some(Instant::now() + interval);
+			work_done = true;
+		}
+
+		for path in ready {
+			pending.remove(&path);
+			created.remove(&path);
+
+			if args.dropped_folders != DroppedFolders::Leave && is_dropped_folder(args, dir, tree, &path) {
+				update_folder(args, dir, tree, &path);
+				work_done = true;
+				continue;
+			}
+
+			if !path.is_file() {
+				retries.remove(&path); // Gone, or sorted by something else
+				sizes.remove(&path);
+				continue;
+			}
+
+			if let Some(window) = args.stable {
+				// Waited out as a deadline of its own, sleeping would hold up everything else
+				let Some(size) = sizes.remove(&path) else {
+					match fs::metadata(&path) {
+						Ok(metadata) => {
+							sizes.insert(path.clone(), metadata.len());
+							pending.insert(path, Instant::now() + window);
+						}
+						Err(e) => {
+							Event::Error { path: Some(&path), message: e.to_string() }.emit();
+							retry(&mut retries, &mut pending, path);
+						}
+					}
+
+					continue;
+				};
+
+				match file_state(&path, size) {
+					Ok(FileState::Ready) => (),
+					Ok(FileState::Growing) => {
+						pending.insert(path, Instant::now() + args.settle);
+						continue;
+					}
+					Ok(FileState::Truncated) => {
+						let reason = "looks truncated, leaving it until it is written again".to_owned();
+						Event::Skipped { path: &path, reason }.emit();
+						continue;
+					}
+					Err(e) => {
+						Event::Error { path: Some(&path), message: e.to_string() }.emit();
+						retry(&mut retries, &mut pending, path);
+						continue;
+					}
+				}
+			}
+
+			// Asked before sorting, the sooner the likelier it's still the window the screenshot is of. Retries are too
+			// late for that
+			let window = match (args.window_info && !retries.contains_key(&path)).then(compositor::focused) {
+				Some(Err(e)) => {
+					let message = format!("asking the compositor for the focused window: {e}");
+					Event::Error { path: None, message }.emit();
+					None
+				}
+				window => window.and_then(Result::ok).flatten(),
+			};
+
+			let res = sort_new(args, tree, path.as_path());
+
+			if res.is_ok() {
+				retries.remove(&path);
+			}
+
+			match res {
+				Ok(Some(file)) => {
+					hooks::run(args, &file);
+
+					if let Some(window) = window {
+						if let Err(e) = windows::record(tree, &file, &window) {
+							eprintln!("Error while recording the window of \"{}\": {e}", file.display());
+						}
+
+						if let Err(e) = sidecar::refresh(tree, &file) {
+							eprintln!("Error while writing the sidecar of \"{}\": {e}", file.display());
+						}
+					}
+				}
+				Ok(None) => (),
+				Err(e) => {
+					Event::Error { path: Some(&path), message: e.to_string() }.emit();
+					retry(&mut retries, &mut pending, path);
+				}
+			}
+			work_done = true;
+		}
+
+		if work_done {
+			for tree in trees(args, tree) {
+				if let Err(e) = quota::enforce(args, tree) {
+					Event::Error { path: None, message: format!("keeping under --max-total: {e}") }.emit();
+				}
+
+				space::check(args, tree);
+			}
+		}
+
+		let day = args.timezone.local(SystemTime::now()).0;
+
+		if work_done {
+			link_batch.get_or_insert(Instant::now() + LINK_BATCH);
+		}
+
+		// Links removed, pointing nowhere or replaced are made again. Once, what replaced them may be wanted there
+		if next_link_check <= Instant::now() {
+			next_link_check = Instant::now() + LINK_CHECK;
+
+			for tree in trees(args, tree) {
+				if !links_broken(args, tree) {
+					links_broke.remove(tree);
+				} else if links_broke.insert(tree.to_path_buf()) {
+					info!("The links of \"{}\" are broken, making them again", tree.display());
+					forget_newest_day(tree);
+					link_batch = Some(Instant::now());
+				}
+			}
+		}
+
+		let link_due = link_retry.is_some_and(|(_, t)| t <= Instant::now());
+		let batch_due = link_batch.is_some_and(|t| t <= Instant::now());
+
+		if batch_due || link_due || (args.today_link && day != today) {
+			today = day;
+			link_batch = None;
+
+			match trees(args, tree).try_for_each(|tree| update_latest(args, tree)) {
+				Ok(()) => link_retry = None,
+				Err(e) => {
+					Event::Error { path: None, message: format!("updating \"latest\" link: {e}") }.emit();
+
+					link_retry = match link_retry.map_or(1, |(attempt, _)| attempt + 1) {
+						attempt if attempt > MAX_RETRIES => {
+							info!("Giving up on the links after {MAX_RETRIES} retries, until a file is sorted");
+							None
+						}
+						attempt => {
+							let delay = backoff(attempt);
+							info!("Retry updating the links in {}s", delay.as_secs());
+							Some((attempt, Instant::now() + delay))
+						}
+					};
+				}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn date_of(name: &str) -> Option<(Date, Option<Time>)> {
+		let args = Args::parse_from(["screenshot-manager", "."]);
+		name_date(&args, &NAME_REGEX.captures(name).unwrap()).unwrap()
+	}
+
+	fn date(year: i32, month: u32, day: u32) -> Date {
+		Date { year, month, day }
+	}
+
+	fn time(hour: u32, minute: u32, second: u32) -> Option<Time> {
+		Some(Time { hour, minute, second })
+	}
+
+	#[test]
+	fn name_dates() {
+		assert_eq!(date_of("Screenshot_2024-05-01_10-20-30.png"), Some((date(2024, 5, 1), time(10, 20, 30))));
+		assert_eq!(date_of("Screenshot from 2024-05-01 10-20-30.png"), Some((date(2024, 5, 1), time(10, 20, 30))));
+		assert_eq!(date_of("Screen Shot 2024-05-01 at 10.20.30.png"), Some((date(2024, 5, 1), time(10, 20, 30))));
+		assert_eq!(date_of("2024-05-01T1020.png"), Some((date(2024, 5, 1), time(10, 20, 0))));
+		assert_eq!(date_of("2024-05-01_shot.png"), Some((date(2024, 5, 1), None)));
+	}
+
+	#[test]
+	fn hash_conflicts_compare_contents() {
+		let root = std::env::temp_dir().join(format!("screenshot-manager-hash-{}", std::process::id()));
+		let day = root.join("2024/05/01");
+		fs::create_dir_all(&day).unwrap();
+
+		let (from, existing) = (root.join("shot.png"), day.join("shot.png"));
+		fs::write(&from, "new").unwrap();
+		fs::write(&existing, "old").unwrap();
+
+		// Another file under the name the hash gives, as if they collided
+		let hashed = hashed_name(&from, &existing).unwrap();
+		fs::write(&hashed, "other").unwrap();
+
+		let args = Args::parse_from(["screenshot-manager".as_ref(), root.as_os_str(), "--on-conflict=hash".as_ref()]);
+		let moved = move_files(&args, &root, &from, Path::new("2024/05/01")).ok().flatten();
+		let contents = moved.as_ref().map(|f| fs::read_to_string(f).unwrap());
+		fs::remove_dir_all(&root).unwrap();
+
+		assert_eq!(moved, Some(free_name(&hashed, |_| false)));
+		assert_eq!(contents.as_deref(), Some("new"));
+	}
+
+	#[test]
+	fn invalid_name_dates_are_none() {
+		assert_eq!(date_of("2024-13-01_shot.png"), None);
+		assert_eq!(date_of("2024-00-01_shot.png"), None);
+		assert_eq!(date_of("2024-05-00_shot.png"), None);
+		assert_eq!(date_of("2023-02-29_shot.png"), None);
+		assert_eq!(date_of("2024-05-01_24-00-00.png"), Some((date(2024, 5, 1), None)));
+		assert_eq!(date_of("2024-05-01_10-60-00.png"), Some((date(2024, 5, 1), None)));
+		assert_eq!(date_of("2024-05-01_10-20-60.png"), Some((date(2024, 5, 1), None)));
+		assert_eq!(date_of("2024-05-01_8080.png"), Some((date(2024, 5, 1), None)));
 	}
 }
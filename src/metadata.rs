@@ -0,0 +1,505 @@
+//! Dates embedded in image files: EXIF in JPEG and PNG, PNG text and tIME chunks. And the size of the image, from
+//! their headers, the camera a photo was taken with and whether it's animated or a video
+
+use std::{
+	fs,
+	io::{self, BufReader, Read, Seek, SeekFrom},
+	path::Path,
+};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::{
+	date::{Date, Time},
+	tz::Zone,
+};
+
+pub const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+pub const PNG_END: &[u8] = b"\0\0\0\0IEND\xae\x42\x60\x82";
+pub const JPEG_START: &[u8] = b"\xff\xd8\xff";
+pub const JPEG_END: &[u8] = b"\xff\xd9";
+const GIF: &[&[u8]] = &[b"GIF87a", b"GIF89a"];
+const RIFF: &[u8] = b"RIFF";
+const WEBP: &[u8] = b"WEBP";
+
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+
+const TAG_MAKE: u16 = 0x010f;
+const TAG_MODEL: u16 = 0x0110;
+const TAG_DATE_TIME: u16 = 0x0132;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+const TAG_DATE_TIME_DIGITIZED: u16 = 0x9004;
+
+/// Anything bigger is not metadata a screenshot would carry
+const MAX_CHUNK: u32 = 1 << 20;
+
+lazy_static! {
+	// EXIF uses "2024:05:01 10:00:00", PNG "Creation Time" is free form but usually ISO 8601 or RFC 1123
+	static ref NUMERIC_DATE: Regex = Regex::new(r"(\d{4})[:-](\d\d)[:-](\d\d)").unwrap();
+	static ref RFC1123_DATE: Regex =
+		Regex::new(r"(\d{1,2}) (Jan|Feb|Mar|Apr|May|Jun|Jul|Aug|Sep|Oct|Nov|Dec)[a-z]* (\d{4})").unwrap();
+}
+
+/// Kind of image in `file` going by its first bytes, named like its usual extension
+pub fn image_type(file: &Path) -> io::Result<Option<&'static str>> {
+	let mut magic = Vec::with_capacity(12);
+	fs::File::open(file)?.take(12).read_to_end(&mut magic)?;
+
+	Ok(if magic.starts_with(PNG_SIGNATURE) {
+		Some("png")
+	} else if magic.starts_with(JPEG_START) {
+		Some("jpg")
+	} else if magic.starts_with(RIFF) && magic.get(8..12) == Some(WEBP) {
+		Some("webp")
+	} else if GIF.iter().any(|g| magic.starts_with(g)) {
+		Some("gif")
+	} else {
+		None
+	})
+}
+
+/// Width and height of the image in `file`, if it is a PNG, JPEG or WebP
+pub fn dimensions(file: &Path) -> io::Result<Option<(u32, u32)>> {
+	let mut f = BufReader::new(fs::File::open(file)?);
+	let mut header = Vec::with_capacity(30);
+	(&mut f).take(30).read_to_end(&mut header)?;
+
+	// PNG and WebP keep the size in the first 30 bytes, a small JPEG can be shorter
+	let whole = header.len() == 30;
+	header.resize(30, 0);
+
+	let be32 = |at: usize| u32::from_be_bytes(header[at..at + 4].try_into().unwrap());
+	let le24 = |at: usize| u32::from_le_bytes([header[at], header[at + 1], header[at + 2], 0]);
+
+	Ok(if whole && header.starts_with(PNG_SIGNATURE) && &header[12..16] == b"IHDR" {
+		Some((be32(16), be32(20)))
+	} else if header.starts_with(JPEG_START) {
+		f.seek(SeekFrom::Start(2))?;
+		jpeg_dimensions(&mut f)?
+	} else if whole && header.starts_with(RIFF) && &header[8..12] == WEBP {
+		// Lossy, lossless and extended WebP each keep the size their own way
+		match &header[12..16] {
+			b"VP8 " => {
+				let le14 = |at: usize| u32::from(u16::from_le_bytes([header[at], header[at + 1]]) & 0x3fff);
+				Some((le14(26), le14(28)))
+			}
+			b"VP8L" => {
+				let bits = u32::from_le_bytes(header[21..25].try_into().unwrap());
+				Some((1 + (bits & 0x3fff), 1 + (bits >> 14 & 0x3fff)))
+			}
+			b"VP8X" => Some((1 + le24(24), 1 + le24(27))),
+			_ => None,
+		}
+	} else {
+		None
+	})
+}
+
+/// Whether `file` is an animation, a GIF of more than one frame, an APNG or an animated WebP, like screen recorders
+/// such as Peek and gifski make
+pub fn is_animated(file: &Path) -> io::Result<bool> {
+	let mut f = BufReader::new(fs::File::open(file)?);
+	let mut header = [0; 21];
+
+	if f.read_exact(&mut header).is_err() {
+		return Ok(false);
+	}
+
+	if GIF.iter().any(|g| header.starts_with(g)) {
+		// The packed byte of the screen descriptor tells if a global color table follows
+		let colors = if header[10] & 0x80 != 0 { 3 << ((header[10] & 7) + 1) } else { 0 };
+		f.seek(SeekFrom::Start(13 + colors))?;
+		gif_frames(&mut f).map(|frames| frames > 1)
+	} else if header.starts_with(PNG_SIGNATURE) {
+		f.seek(SeekFrom::Start(8))?;
+		apng(&mut f)
+	} else if header.starts_with(RIFF) && &header[8..16] == b"WEBPVP8X" {
+		Ok(header[20] & 0x02 != 0) // The animation flag of the extended format
+	} else {
+		Ok(false)
+	}
+}
+
+/// Frames of the GIF after its header, counted up to the second
+fn gif_frames(f: &mut (impl Read + Seek)) -> io::Result<u32> {
+	let mut frames = 0;
+	let mut byte = [0; 1];
+
+	while frames < 2 && f.read_exact(&mut byte).is_ok() {
+		match byte[0] {
+			// An extension, its label then its data
+			0x21 => {
+				f.seek(SeekFrom::Current(1))?;
+				skip_sub_blocks(f)?;
+			}
+			// An image, its descriptor, local color table and the code size before the data
+			0x2c => {
+				let mut descriptor = [0; 9];
+				f.read_exact(&mut descriptor)?;
+				let colors = if descriptor[8] & 0x80 != 0 { 3 << ((descriptor[8] & 7) + 1) } else { 0 };
+				f.seek(SeekFrom::Current(colors + 1))?;
+				skip_sub_blocks(f)?;
+				frames += 1;
+			}
+			_ => break, // The trailer, or something broken
+		}
+	}
+
+	Ok(frames)
+}
+
+/// Whether `file` is a video, an MP4 or QuickTime file or a WebM or Matroska one
+pub fn is_video(file: &Path) -> io::Result<bool> {
+	let mut header = Vec::with_capacity(12);
+	fs::File::open(file)?.take(12).read_to_end(&mut header)?;
+
+	// HEIF and AVIF images are in the same container as MP4, their brand tells them apart
+	let image = [b"heic", b"heix", b"mif1", b"msf1", b"avif"].iter().any(|b| header.get(8..12) == Some(&b[..]));
+
+	Ok(header.get(4..8) == Some(b"ftyp") && !image || header.starts_with(b"\x1a\x45\xdf\xa3"))
+}
+
+/// Whether `file` is a screen recording, an animation or a video
+pub fn is_recording(file: &Path) -> io::Result<bool> {
+	Ok(is_animated(file)? || is_video(file)?)
+}
+
+/// Skips the data of a GIF block, sub-blocks each starting with their length that end at one of none
+fn skip_sub_blocks(f: &mut (impl Read + Seek)) -> io::Result<()> {
+	loop {
+		let mut len = [0; 1];
+		f.read_exact(&mut len)?;
+
+		if len[0] == 0 {
+			return Ok(());
+		}
+
+		f.seek(SeekFrom::Current(len[0].into()))?;
+	}
+}
+
+/// Whether the chunks of a PNG have animation control, which APNG puts before the image data
+fn apng(f: &mut (impl Read + Seek)) -> io::Result<bool> {
+	loop {
+		let mut header = [0; 8];
+		if f.read_exact(&mut header).is_err() {
+			return Ok(false);
+		}
+
+		match &header[4..] {
+			b"acTL" => return Ok(true),
+			b"IDAT" | b"IEND" => return Ok(false),
+			_ => f.seek(SeekFrom::Current(i64::from(u32::from_be_bytes(header[..4].try_into().unwrap())) + 4))?,
+		};
+	}
+}
+
+/// Size in the start of frame segment, which comes after the metadata
+fn jpeg_dimensions(f: &mut impl Read) -> io::Result<Option<(u32, u32)>> {
+	loop {
+		let mut marker = [0; 4];
+		if f.read_exact(&mut marker).is_err() || marker[0] != 0xff || marker[1] == 0xda {
+			return Ok(None);
+		}
+
+		let len = u16::from_be_bytes([marker[2], marker[3]]).saturating_sub(2);
+		let mut data = vec![0; len.into()];
+		f.read_exact(&mut data)?;
+
+		// 0xc0 to 0xcf, but those three are Huffman and arithmetic coding tables
+		if matches!(marker[1], 0xc0..=0xcf) && !matches!(marker[1], 0xc4 | 0xc8 | 0xcc) && data.len() >= 5 {
+			let height = u16::from_be_bytes([data[1], data[2]]);
+			let width = u16::from_be_bytes([data[3], data[4]]);
+
+			return Ok(Some((width.into(), height.into())));
+		}
+	}
+}
+
+/// Whether `file` is a photo, a JPEG or PNG whose EXIF names the make or model of the camera. Screenshots don't
+pub fn is_photo(file: &Path) -> io::Result<bool> {
+	let mut f = BufReader::new(fs::File::open(file)?);
+	let mut magic = [0; 8];
+
+	if f.read_exact(&mut magic).is_err() {
+		return Ok(false);
+	}
+
+	let exif = if magic.starts_with(PNG_SIGNATURE) {
+		png_exif(&mut f)?
+	} else if magic.starts_with(JPEG_START) {
+		f.seek(SeekFrom::Start(2))?;
+		jpeg_exif(&mut f)?
+	} else {
+		None
+	};
+
+	let Some(data) = exif else {
+		return Ok(false);
+	};
+
+	let little_endian = match data.get(..2) {
+		Some(b"II") => true,
+		Some(b"MM") => false,
+		_ => return Ok(false),
+	};
+
+	let tiff = Tiff { data: &data, little_endian };
+
+	Ok(tiff.u32(4).is_some_and(|ifd0| [TAG_MAKE, TAG_MODEL].iter().any(|&t| tiff.find(ifd0 as usize, t).is_some())))
+}
+
+fn png_exif(f: &mut (impl Read + Seek)) -> io::Result<Option<Vec<u8>>> {
+	loop {
+		let mut header = [0; 8];
+		if f.read_exact(&mut header).is_err() {
+			return Ok(None);
+		}
+
+		let len = u32::from_be_bytes(header[..4].try_into().unwrap());
+
+		match &header[4..] {
+			b"IEND" => return Ok(None),
+			b"eXIf" if len <= MAX_CHUNK => {
+				let mut data = vec![0; len as usize];
+				f.read_exact(&mut data)?;
+				return Ok(Some(data));
+			}
+			_ => f.seek(SeekFrom::Current(i64::from(len) + 4))?, // Skip data and CRC
+		};
+	}
+}
+
+fn jpeg_exif(f: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+	loop {
+		let mut marker = [0; 4];
+		if f.read_exact(&mut marker).is_err() || marker[0] != 0xff || marker[1] == 0xda {
+			return Ok(None);
+		}
+
+		let len = u16::from_be_bytes([marker[2], marker[3]]).saturating_sub(2);
+		let mut data = vec![0; len.into()];
+		f.read_exact(&mut data)?;
+
+		if marker[1] == 0xe1 {
+			if let Some(exif) = data.strip_prefix(EXIF_HEADER) {
+				return Ok(Some(exif.to_vec()));
+			}
+		}
+	}
+}
+
+/// Capture date stored inside `file`, if it is a JPEG or PNG that has one. Dates stored in UTC are converted to
+/// `zone`, others are in whatever zone the capturing device was in
+pub fn capture_date(file: &Path, zone: &Zone) -> io::Result<Option<Date>> {
+	let mut f = BufReader::new(fs::File::open(file)?);
+	let mut magic = [0; 8];
+
+	if f.read_exact(&mut magic).is_err() {
+		return Ok(None); // Too small to be an image
+	}
+
+	if magic.starts_with(PNG_SIGNATURE) {
+		png_date(&mut f, zone)
+	} else if magic.starts_with(JPEG_START) {
+		f.seek(SeekFrom::Start(2))?;
+		jpeg_date(&mut f)
+	} else {
+		Ok(None)
+	}
+}
+
+fn png_date(f: &mut (impl Read + Seek), zone: &Zone) -> io::Result<Option<Date>> {
+	let (mut exif, mut text, mut time) = (None, None, None);
+
+	loop {
+		let mut header = [0; 8];
+		if f.read_exact(&mut header).is_err() {
+			break; // Truncated, go with what was found
+		}
+
+		let len = u32::from_be_bytes(header[..4].try_into().unwrap());
+		let kind = &header[4..];
+
+		if !matches!(kind, b"eXIf" | b"tEXt" | b"iTXt" | b"tIME") || len > MAX_CHUNK {
+			if kind == b"IEND" {
+				break;
+			}
+
+			f.seek(SeekFrom::Current(i64::from(len) + 4))?; // Skip data and CRC
+			continue;
+		}
+
+		let mut data = vec![0; len as usize];
+		f.read_exact(&mut data)?;
+		f.seek(SeekFrom::Current(4))?;
+
+		match kind {
+			b"eXIf" => exif = exif.or_else(|| exif_date(&data)),
+			b"tEXt" | b"iTXt" => {
+				if let Some(value) = data.strip_prefix(b"Creation Time\0") {
+					// iTXt has compression and language fields before the text, none of which look like a date
+					text = text.or_else(|| parse_date(&String::from_utf8_lossy(value)));
+				}
+			}
+			b"tIME" if data.len() >= 7 => {
+				// Last modification in UTC, better than nothing
+				let year = u16::from_be_bytes([data[0], data[1]]);
+				let date = Date { year: year.into(), month: data[2].into(), day: data[3].into() };
+				let time_of_day = Time { hour: data[4].into(), minute: data[5].into(), second: data[6].into() };
+
+				// A month 0 or a day 40 would roll over into some other real day
+				if date.exists() && time_of_day.exists() {
+					time = Some(zone.wall_clock(Zone::Utc.instant(date, time_of_day)).0);
+				}
+			}
+			_ => (),
+		}
+	}
+
+	Ok(exif.or(text).or(time))
+}
+
+fn jpeg_date(f: &mut impl Read) -> io::Result<Option<Date>> {
+	loop {
+		let mut marker = [0; 4];
+		if f.read_exact(&mut marker).is_err() || marker[0] != 0xff {
+			return Ok(None);
+		}
+
+		// Start of scan, image data follows and metadata always comes before it
+		if marker[1] == 0xda {
+			return Ok(None);
+		}
+
+		let len = u16::from_be_bytes([marker[2], marker[3]]).saturating_sub(2);
+		let mut data = vec![0; len.into()];
+		f.read_exact(&mut data)?;
+
+		if marker[1] == 0xe1 {
+			if let Some(date) = data.strip_prefix(EXIF_HEADER).and_then(exif_date) {
+				return Ok(Some(date));
+			}
+		}
+	}
+}
+
+/// Reads a TIFF structure as found in EXIF blocks
+struct Tiff<'a> {
+	data: &'a [u8],
+	little_endian: bool,
+}
+
+impl Tiff<'_> {
+	fn u16(&self, at: usize) -> Option<u16> {
+		let bytes = self.data.get(at..at + 2)?.try_into().ok()?;
+		Some(if self.little_endian { u16::from_le_bytes(bytes) } else { u16::from_be_bytes(bytes) })
+	}
+
+	fn u32(&self, at: usize) -> Option<u32> {
+		let bytes = self.data.get(at..at + 4)?.try_into().ok()?;
+		Some(if self.little_endian { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) })
+	}
+
+	/// Offset of the value of `tag` in the directory at `ifd`, and the value count
+	fn find(&self, ifd: usize, tag: u16) -> Option<(usize, usize)> {
+		let entries = self.u16(ifd)?;
+
+		(0..usize::from(entries)).map(|i| ifd + 2 + i * 12).find(|&e| self.u16(e) == Some(tag)).and_then(|e| {
+			let count = self.u32(e + 4)? as usize;
+			// Values of up to 4 bytes are stored inline, the rest somewhere else
+			let at = if count > 4 { self.u32(e + 8)? as usize } else { e + 8 };
+			Some((at, count))
+		})
+	}
+
+	fn date(&self, ifd: usize, tag: u16) -> Option<Date> {
+		let (at, count) = self.find(ifd, tag)?;
+		parse_date(&String::from_utf8_lossy(self.data.get(at..at + count)?))
+	}
+}
+
+fn exif_date(data: &[u8]) -> Option<Date> {
+	let little_endian = match data.get(..2)? {
+		b"II" => true,
+		b"MM" => false,
+		_ => return None,
+	};
+
+	let tiff = Tiff { data, little_endian };
+	let ifd0 = tiff.u32(4)? as usize;
+	let exif_ifd = tiff.find(ifd0, TAG_EXIF_IFD).and_then(|(at, _)| tiff.u32(at)).map(|at| at as usize);
+
+	exif_ifd
+		.and_then(|ifd| tiff.date(ifd, TAG_DATE_TIME_ORIGINAL).or_else(|| tiff.date(ifd, TAG_DATE_TIME_DIGITIZED)))
+		.or_else(|| tiff.date(ifd0, TAG_DATE_TIME))
+}
+
+fn parse_date(s: &str) -> Option<Date> {
+	const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+	let date = if let Some(c) = NUMERIC_DATE.captures(s) {
+		Date { year: c[1].parse().ok()?, month: c[2].parse().ok()?, day: c[3].parse().ok()? }
+	} else {
+		let c = RFC1123_DATE.captures(s)?;
+		let month = MONTHS.iter().position(|&m| m == &c[2])? as u32 + 1;
+		Date { year: c[3].parse().ok()?, month, day: c[1].parse().ok()? }
+	};
+
+	// Unset EXIF dates are "0000:00:00 00:00:00"
+	(date.year > 0 && date.exists()).then_some(date)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::Cursor;
+
+	use super::*;
+
+	/// Chunks of a PNG after its signature, CRCs left zero as they aren't checked
+	fn chunks(chunks: &[(&[u8; 4], &[u8])]) -> Cursor<Vec<u8>> {
+		let mut data = vec![];
+
+		for (kind, body) in chunks {
+			data.extend((body.len() as u32).to_be_bytes());
+			data.extend(*kind);
+			data.extend(*body);
+			data.extend([0; 4]);
+		}
+
+		Cursor::new(data)
+	}
+
+	#[test]
+	fn text_dates() {
+		assert_eq!(parse_date("2024:05:01 10:20:30"), Some(Date { year: 2024, month: 5, day: 1 }));
+		assert_eq!(parse_date("Wed, 1 May 2024 10:20:30 +0000"), Some(Date { year: 2024, month: 5, day: 1 }));
+		assert_eq!(parse_date("0000:00:00 00:00:00"), None);
+		assert_eq!(parse_date("2024-02-31T10:20:30"), None);
+		assert_eq!(parse_date("31 Apr 2024"), None);
+	}
+
+	#[test]
+	fn png_time_chunks() {
+		let time = |data: &[u8]| png_date(&mut chunks(&[(b"tIME", data), (b"IEND", b"")]), &Zone::Utc).unwrap();
+
+		assert_eq!(time(&[0x07, 0xe8, 5, 1, 23, 59, 59]), Some(Date { year: 2024, month: 5, day: 1 }));
+		assert_eq!(time(&[0x07, 0xe8, 0, 1, 10, 0, 0]), None);
+		assert_eq!(time(&[0x07, 0xe8, 5, 40, 10, 0, 0]), None);
+		assert_eq!(time(&[0x07, 0xe8, 2, 30, 10, 0, 0]), None);
+		assert_eq!(time(&[0x07, 0xe8, 5, 1, 25, 0, 0]), None);
+	}
+
+	#[test]
+	fn png_text_before_time() {
+		let mut png = chunks(&[
+			(b"tIME", &[0x07, 0xe8, 5, 1, 10, 0, 0]),
+			(b"tEXt", b"Creation Time\x002024-04-30 08:00:00"),
+			(b"IEND", b""),
+		]);
+
+		assert_eq!(png_date(&mut png, &Zone::Utc).unwrap(), Some(Date { year: 2024, month: 4, day: 30 }));
+	}
+}
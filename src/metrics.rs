@@ -0,0 +1,129 @@
+//! Counters for monitoring, served in the Prometheus text format with --metrics next to a health check
+
+use std::{
+	fmt::Write as _,
+	io::{self, BufRead, BufReader, Write},
+	net::{SocketAddr, TcpListener, TcpStream},
+	sync::atomic::{AtomicU64, Ordering},
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+static SORTED: AtomicU64 = AtomicU64::new(0);
+static ERRORS: AtomicU64 = AtomicU64::new(0);
+static BYTES: AtomicU64 = AtomicU64::new(0);
+static REMOVED: AtomicU64 = AtomicU64::new(0);
+static PRUNED: AtomicU64 = AtomicU64::new(0);
+/// Seconds since the epoch of the last watcher event, or of starting before there is one
+static LAST_EVENT: AtomicU64 = AtomicU64::new(0);
+
+fn now() -> u64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+/// A file of `bytes` was put into the tree
+pub fn sorted(bytes: u64) {
+	SORTED.fetch_add(1, Ordering::Relaxed);
+	BYTES.fetch_add(bytes, Ordering::Relaxed);
+}
+
+/// A file or folder was removed from the tree by something else
+pub fn removed() {
+	REMOVED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// A file was removed from the tree to keep it under --max-total
+pub fn pruned() {
+	PRUNED.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn error() {
+	ERRORS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// The watcher reported something, whatever it was
+pub fn event_received() {
+	LAST_EVENT.store(now(), Ordering::Relaxed);
+}
+
+fn render() -> String {
+	let mut s = String::new();
+	let mut metric = |name: &str, kind: &str, help: &str, value: u64| {
+		_ = write!(s, "# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}\n");
+	};
+
+	metric(
+		"screenshot_manager_files_sorted_total",
+		"counter",
+		"Files put into the tree.",
+		SORTED.load(Ordering::Relaxed),
+	);
+	metric("screenshot_manager_errors_total", "counter", "Errors while sorting.", ERRORS.load(Ordering::Relaxed));
+	metric(
+		"screenshot_manager_bytes_sorted_total",
+		"counter",
+		"Bytes of the files put into the tree.",
+		BYTES.load(Ordering::Relaxed),
+	);
+	metric(
+		"screenshot_manager_files_removed_total",
+		"counter",
+		"Files and folders removed from the tree by other programs.",
+		REMOVED.load(Ordering::Relaxed),
+	);
+	metric(
+		"screenshot_manager_files_pruned_total",
+		"counter",
+		"Files removed from the tree to keep it under its quota.",
+		PRUNED.load(Ordering::Relaxed),
+	);
+	metric(
+		"screenshot_manager_seconds_since_last_event",
+		"gauge",
+		"Seconds since the watcher last reported anything.",
+		now().saturating_sub(LAST_EVENT.load(Ordering::Relaxed)),
+	);
+
+	s
+}
+
+/// Serves `/metrics` and `/healthz` over HTTP on `addr` from another thread. `health` tells what is wrong, if
+/// anything
+pub fn serve(addr: SocketAddr, health: impl Fn() -> Vec<String> + Send + 'static) -> io::Result<()> {
+	let listener = TcpListener::bind(addr)?;
+	LAST_EVENT.store(now(), Ordering::Relaxed);
+
+	std::thread::spawn(move || {
+		for stream in listener.incoming().flatten() {
+			// A scraper that stops talking must not hold up the next one
+			_ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+			_ = stream.set_write_timeout(Some(Duration::from_secs(5)));
+			_ = respond(stream, &health);
+		}
+	});
+
+	Ok(())
+}
+
+fn respond(mut stream: TcpStream, health: &dyn Fn() -> Vec<String>) -> io::Result<()> {
+	let mut request = String::new();
+	BufReader::new(&stream).read_line(&mut request)?;
+
+	// "GET /metrics HTTP/1.1", headers and body are of no interest
+	let mut parts = request.split_whitespace();
+	let (status, body) = match (parts.next(), parts.next()) {
+		(Some("GET"), Some("/metrics")) => ("200 OK", render()),
+		(Some("GET"), Some("/healthz")) => match health() {
+			problems if problems.is_empty() => ("200 OK", "ok\n".to_owned()),
+			problems => ("503 Service Unavailable", problems.join("\n") + "\n"),
+		},
+		(Some("GET"), _) => ("404 Not Found", "Not found\n".to_owned()),
+		_ => ("405 Method Not Allowed", "Method not allowed\n".to_owned()),
+	};
+
+	write!(
+		stream,
+		"HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\n\
+		 Connection: close\r\n\r\n{body}",
+		body.len()
+	)
+}
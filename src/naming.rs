@@ -0,0 +1,136 @@
+//! Name templates like "{date}_{time}_{app}_{seq}" files are renamed with as they are sorted
+
+use crate::{
+	date::{Date, Time},
+	source::Source,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Token {
+	Date,
+	Time,
+	App,
+	Monitor,
+	Name,
+	Seq,
+}
+
+impl Token {
+	fn parse(name: &str) -> Option<Token> {
+		Some(match name {
+			"date" => Token::Date,
+			"time" => Token::Time,
+			"app" => Token::App,
+			"monitor" => Token::Monitor,
+			"name" => Token::Name,
+			"seq" => Token::Seq,
+			_ => return None,
+		})
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Piece {
+	Text(String),
+	Token(Token),
+}
+
+/// What a file is renamed with
+pub struct Values<'a> {
+	pub date: Date,
+	pub time: Option<Time>,
+	pub source: Source<'a>,
+	/// Name the file had, without its extension
+	pub name: &'a str,
+}
+
+#[derive(Clone, Debug)]
+pub struct NameTemplate {
+	pieces: Vec<Piece>,
+}
+
+impl NameTemplate {
+	/// Parses a file name without extension made of text and `{date}`, `{time}`, `{app}`, `{monitor}`, `{name}`
+	/// and `{seq}`
+	pub fn parse(s: &str) -> Result<NameTemplate, String> {
+		if s.contains(['/', '\\']) {
+			return Err("a name template can't have folders, those come from --layout".to_owned());
+		}
+
+		let mut pieces = vec![];
+		let mut rest = s;
+
+		while let Some(start) = rest.find('{') {
+			if start > 0 {
+				pieces.push(Piece::Text(rest[..start].to_owned()));
+			}
+
+			let end = rest[start..].find('}').ok_or_else(|| format!("unclosed \"{{\" in \"{s}\""))? + start;
+			let name = &rest[start + 1..end];
+			pieces.push(Piece::Token(Token::parse(name).ok_or_else(|| format!("unknown name token \"{{{name}}}\""))?));
+
+			rest = &rest[end + 1..];
+		}
+
+		if !rest.is_empty() {
+			pieces.push(Piece::Text(rest.to_owned()));
+		}
+
+		if !pieces.iter().any(|p| matches!(p, Piece::Token(_))) {
+			return Err(format!("name \"{s}\" doesn't use any token, every file would get the same one"));
+		}
+
+		Ok(NameTemplate { pieces })
+	}
+
+	/// Whether names need a number to tell them apart
+	pub fn numbered(&self) -> bool {
+		self.pieces.contains(&Piece::Token(Token::Seq))
+	}
+
+	/// Name for a file with `values`, numbered `seq`. Tokens without a value are left out along with the text
+	/// between them and the previous one, "{date}_{app}" is only the date for files of unknown apps
+	pub fn render(&self, values: &Values, seq: usize) -> String {
+		// Text before the first token is always there
+		let (lead, pieces) = match self.pieces.split_first() {
+			Some((Piece::Text(lead), rest)) => (lead.as_str(), rest),
+			_ => ("", &self.pieces[..]),
+		};
+		let mut s = lead.to_owned();
+		let mut separator = None;
+
+		for piece in pieces {
+			let value = match piece {
+				Piece::Text(text) => {
+					separator = Some(text.as_str());
+					continue;
+				}
+				Piece::Token(Token::Date) => Some(values.date.to_string()),
+				Piece::Token(Token::Time) => {
+					values.time.map(|t| format!("{:02}-{:02}-{:02}", t.hour, t.minute, t.second))
+				}
+				Piece::Token(Token::App) => values.source.app.map(str::to_owned),
+				Piece::Token(Token::Monitor) => values.source.monitor.map(str::to_owned),
+				Piece::Token(Token::Name) => Some(values.name.to_owned()).filter(|n| !n.is_empty()),
+				Piece::Token(Token::Seq) => Some(seq.to_string()),
+			};
+
+			if let Some(value) = value {
+				// Nothing to separate from before the first value
+				if let Some(separator) = separator.filter(|_| s.len() > lead.len()) {
+					s.push_str(separator);
+				}
+
+				s.push_str(&value);
+			}
+
+			separator = None;
+		}
+
+		if let Some(separator) = separator {
+			s.push_str(separator);
+		}
+
+		s
+	}
+}
@@ -0,0 +1,287 @@
+//! Operating system specific bits: linking directories and waiting for termination signals
+
+use std::{ffi::c_int, io, path::Path, thread};
+
+pub use signal_hook::consts::{SIGINT, SIGTERM};
+
+/// Whether the native watcher reports files being closed after a write. Elsewhere creation and modification
+/// events are all there is to go by
+pub const REPORTS_CLOSE_WRITE: bool = cfg!(any(target_os = "linux", target_os = "android"));
+
+/// FSEvents coalesces and delays events, without close-write a file needs a moment to be done
+pub const DEFAULT_SETTLE: &str = if cfg!(target_os = "macos") { "1s" } else { "0s" };
+
+/// Offset of local time from UTC in seconds at `secs` since the epoch
+#[cfg(unix)]
+pub fn utc_offset(secs: i64) -> i64 {
+	let time = secs as libc::time_t;
+	// SAFETY: tm is plain old data, all zeroes is a valid value
+	let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+
+	// SAFETY: Both pointers are valid for the call, localtime_r doesn't keep them
+	if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+		return 0;
+	}
+
+	tm.tm_gmtoff as i64
+}
+
+/// Offset of local time from UTC in seconds at `secs` since the epoch
+#[cfg(windows)]
+pub fn utc_offset(secs: i64) -> i64 {
+	let time = secs as libc::time_t;
+	// SAFETY: tm is plain old data, all zeroes is a valid value
+	let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+
+	// SAFETY: Both pointers are valid for the call, localtime_s doesn't keep them
+	if unsafe { libc::localtime_s(&mut tm, &time) } != 0 {
+		return 0;
+	}
+
+	// No tm_gmtoff here, compare against the broken down time instead
+	let date = crate::date::Date { year: tm.tm_year + 1900, month: tm.tm_mon as u32 + 1, day: tm.tm_mday as u32 };
+	let local = date.days() * 24 * 60 * 60 + i64::from(tm.tm_hour * 60 * 60 + tm.tm_min * 60 + tm.tm_sec);
+
+	local - secs
+}
+
+#[cfg(unix)]
+pub fn link_dir(target: &Path, link: &Path) -> io::Result<()> {
+	std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+pub fn link_dir(target: &Path, link: &Path) -> io::Result<()> {
+	// Symlinks need developer mode or elevation, junctions work for everyone
+	if std::os::windows::fs::symlink_dir(target, link).is_ok() {
+		return Ok(());
+	}
+
+	// Junctions can't be relative
+	let target = link.parent().map(|p| p.join(target)).unwrap_or_else(|| target.to_path_buf());
+
+	let status = std::process::Command::new("cmd")
+		.arg("/C")
+		.arg("mklink")
+		.arg("/J")
+		.arg(link)
+		.arg(&target)
+		.stdout(std::process::Stdio::null())
+		.status()?;
+
+	if status.success() {
+		Ok(())
+	} else {
+		Err(io::Error::other(format!("mklink /J failed: {status}")))
+	}
+}
+
+#[cfg(unix)]
+pub fn link_file(target: &Path, link: &Path) -> io::Result<()> {
+	std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+pub fn link_file(target: &Path, link: &Path) -> io::Result<()> {
+	// Like junctions for folders, hard links need no privileges. They can't be relative
+	let target = link.parent().map(|p| p.join(target)).unwrap_or_else(|| target.to_path_buf());
+	std::fs::hard_link(target, link)
+}
+
+/// Opens `path` with the program the desktop uses for it
+pub fn open(path: &Path) -> io::Result<()> {
+	let mut command = if cfg!(windows) {
+		let mut command = std::process::Command::new("cmd");
+		command.args(["/C", "start", ""]); // The first quoted argument would be the window title
+		command
+	} else if cfg!(target_os = "macos") {
+		std::process::Command::new("open")
+	} else {
+		std::process::Command::new("xdg-open")
+	};
+
+	let status = command.arg(path).status()?;
+
+	if status.success() {
+		Ok(())
+	} else {
+		Err(io::Error::other(format!("{:?} failed: {status}", command.get_program())))
+	}
+}
+
+/// Puts `text` on the clipboard
+pub fn copy_text(text: &str) -> io::Result<()> {
+	use std::io::Write;
+
+	let (program, args): (_, &[&str]) = if cfg!(windows) {
+		("clip", &[])
+	} else if cfg!(target_os = "macos") {
+		("pbcopy", &[])
+	} else if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+		("wl-copy", &[])
+	} else {
+		("xclip", &["-selection", "clipboard"])
+	};
+
+	let mut child = std::process::Command::new(program).args(args).stdin(std::process::Stdio::piped()).spawn()?;
+	child.stdin.take().unwrap().write_all(text.as_bytes())?; // Piped above
+	let status = child.wait()?;
+
+	if status.success() {
+		Ok(())
+	} else {
+		Err(io::Error::other(format!("{program} failed: {status}")))
+	}
+}
+
+/// Bytes that can still be written to the filesystem `path` is on, by this user
+#[cfg(unix)]
+pub fn free_space(path: &Path) -> io::Result<u64> {
+	use std::os::unix::ffi::OsStrExt;
+
+	let path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+	// SAFETY: statvfs is plain old data, all zeroes is a valid value
+	let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+
+	// SAFETY: Both pointers are valid for the call, statvfs doesn't keep them
+	if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+		return Err(io::Error::last_os_error());
+	}
+
+	// Blocks reserved for root don't count
+	#[allow(clippy::unnecessary_cast)] // Narrower types on some platforms
+	Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+/// Bytes that can still be written to the filesystem `path` is on, by this user
+#[cfg(windows)]
+pub fn free_space(path: &Path) -> io::Result<u64> {
+	use std::os::windows::ffi::OsStrExt;
+
+	#[link(name = "kernel32")]
+	extern "system" {
+		fn GetDiskFreeSpaceExW(dir: *const u16, available: *mut u64, total: *mut u64, free: *mut u64) -> i32;
+	}
+
+	let dir: Vec<u16> = path.as_os_str().encode_wide().chain([0]).collect();
+	let mut available = 0;
+
+	// SAFETY: The name is NUL terminated, null pointers are allowed for totals not wanted
+	if unsafe { GetDiskFreeSpaceExW(dir.as_ptr(), &mut available, std::ptr::null_mut(), std::ptr::null_mut()) } == 0 {
+		return Err(io::Error::last_os_error());
+	}
+
+	Ok(available)
+}
+
+/// Directories inotify lets a user watch, the watcher needs one for every folder of the tree
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn watch_limit() -> Option<u64> {
+	std::fs::read_to_string("/proc/sys/fs/inotify/max_user_watches").ok()?.trim().parse().ok()
+}
+
+/// Directories inotify lets a user watch, other watchers have no limit of their own
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn watch_limit() -> Option<u64> {
+	None
+}
+
+/// The sysctl of the inotify limit `e` ran into, if it is one. Watches run out with ENOSPC, and instances, one per
+/// program watching, with EMFILE
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn watch_limit_hit(e: &notify::Error) -> Option<&'static str> {
+	match &e.kind {
+		notify::ErrorKind::MaxFilesWatch => Some("fs.inotify.max_user_watches"),
+		notify::ErrorKind::Io(e) => match e.raw_os_error() {
+			Some(libc::ENOSPC) => Some("fs.inotify.max_user_watches"),
+			Some(libc::EMFILE) => Some("fs.inotify.max_user_instances"),
+			_ => None,
+		},
+		_ => None,
+	}
+}
+
+/// The sysctl of the inotify limit `e` ran into, other watchers have none
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn watch_limit_hit(_e: &notify::Error) -> Option<&'static str> {
+	None
+}
+
+/// Makes changes to the entries of `dir` durable
+#[cfg(unix)]
+pub fn sync_dir(dir: &Path) -> io::Result<()> {
+	std::fs::File::open(dir)?.sync_all()
+}
+
+/// Makes changes to the entries of `dir` durable
+#[cfg(windows)]
+pub fn sync_dir(_: &Path) -> io::Result<()> {
+	Ok(()) // Directories can't be opened as files, NTFS journals metadata changes itself
+}
+
+/// Whether `link_dir` failed because the filesystem can't hold links at all, like FAT32 and exFAT
+#[cfg(unix)]
+pub fn links_unsupported(e: &io::Error) -> bool {
+	// vfat and exfat answer EPERM, some FUSE filesystems EOPNOTSUPP
+	matches!(e.kind(), io::ErrorKind::PermissionDenied | io::ErrorKind::Unsupported)
+		|| e.raw_os_error() == Some(libc::EOPNOTSUPP)
+}
+
+/// Whether `link_dir` failed because the filesystem can't hold links at all, like FAT32 and exFAT
+#[cfg(windows)]
+pub fn links_unsupported(_: &io::Error) -> bool {
+	true // Both symlinks and junctions were tried, neither works here
+}
+
+#[cfg(unix)]
+pub fn remove_link(link: &Path) -> io::Result<()> {
+	std::fs::remove_file(link)
+}
+
+#[cfg(windows)]
+pub fn remove_link(link: &Path) -> io::Result<()> {
+	// Directory symlinks and junctions are removed like directories, their target is left alone
+	std::fs::remove_dir(link)
+}
+
+/// Calls `handler` from another thread with the first SIGINT or SIGTERM received
+#[cfg(unix)]
+pub fn on_termination(handler: impl FnOnce(c_int) + Send + 'static) -> io::Result<()> {
+	let mut signals = signal_hook::iterator::Signals::new([SIGINT, SIGTERM])?;
+
+	thread::spawn(move || {
+		if let Some(sig) = signals.forever().next() {
+			handler(sig);
+		}
+	});
+
+	Ok(())
+}
+
+/// Calls `handler` from another thread with the first SIGINT or SIGTERM received
+#[cfg(windows)]
+pub fn on_termination(handler: impl FnOnce(c_int) + Send + 'static) -> io::Result<()> {
+	use std::sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc,
+	};
+
+	// No signal iterator on Windows, poll a flag set from the handler instead
+	let received = Arc::new(AtomicUsize::new(0));
+
+	for sig in [SIGINT, SIGTERM] {
+		signal_hook::flag::register_usize(sig, received.clone(), sig as usize)?;
+	}
+
+	thread::spawn(move || loop {
+		match received.load(Ordering::SeqCst) {
+			0 => thread::sleep(std::time::Duration::from_millis(100)),
+			sig => {
+				handler(sig as c_int);
+				break;
+			}
+		}
+	});
+
+	Ok(())
+}
@@ -0,0 +1,144 @@
+//! Keeping the tree under --max-total while watching, by pruning its oldest files or refusing to sort more into it
+
+use std::{
+	collections::{BTreeMap, HashSet},
+	fs,
+	path::{Path, PathBuf},
+	sync::{
+		atomic::{AtomicBool, AtomicU64, Ordering},
+		Arc, Mutex,
+	},
+};
+
+use crate::{
+	changes, commands,
+	events::{info, Event},
+	format_size, hooks, metrics, tags, Args, OverQuota, MOVED_AWAY,
+};
+
+/// How much of --max-total the tree uses
+struct Quota {
+	max: u64,
+	used: AtomicU64,
+	/// Whether being over it was reported already, it is again once the tree fits
+	alerted: AtomicBool,
+}
+
+/// Quotas of the trees being watched, by their root. One-off commands sort without one
+static QUOTAS: Mutex<BTreeMap<PathBuf, Arc<Quota>>> = Mutex::new(BTreeMap::new());
+
+fn quota(root: &Path) -> Option<Arc<Quota>> {
+	QUOTAS.lock().unwrap().get(root).cloned()
+}
+
+/// Measures the tree in `root` for --max-total, if given, before anything is sorted into it
+pub fn init(args: &Args, root: &Path) -> anyhow::Result<()> {
+	let Some(max) = args.max_total else {
+		return Ok(());
+	};
+
+	let quota = Quota { max, used: AtomicU64::new(0), alerted: AtomicBool::new(false) };
+	QUOTAS.lock().unwrap().insert(root.to_path_buf(), Arc::new(quota));
+
+	refresh(args, root).map(|_| ())
+}
+
+/// Measures the tree again, other programs add and remove files too
+fn refresh(args: &Args, root: &Path) -> anyhow::Result<u64> {
+	let Some(quota) = quota(root) else {
+		return Ok(0);
+	};
+
+	let used = commands::organized_files(args, root)?.iter().filter_map(|f| f.metadata().ok()).map(|m| m.len()).sum();
+	quota.used.store(used, Ordering::SeqCst);
+
+	if used <= quota.max {
+		quota.alerted.store(false, Ordering::SeqCst);
+	}
+
+	Ok(used)
+}
+
+/// Whether `file` may be sorted into the tree in `root`. With --over-quota refuse only while it fits
+pub fn admit(args: &Args, root: &Path, file: &Path) -> bool {
+	let Some(quota) = quota(root) else {
+		return true;
+	};
+
+	let size = file.metadata().map(|m| m.len()).unwrap_or_default();
+	let used = quota.used.fetch_add(size, Ordering::SeqCst) + size;
+
+	if args.over_quota == OverQuota::Prune || used <= quota.max {
+		return true;
+	}
+
+	quota.used.fetch_sub(size, Ordering::SeqCst);
+
+	let reason = format!("the tree would be over {} with it", format_size(quota.max));
+	Event::Skipped { path: file, reason }.emit();
+	alert(args, &quota, "new screenshots are left where they are");
+
+	false
+}
+
+/// Measures the tree and, with --over-quota prune, removes its oldest files that aren't starred until it fits
+pub fn enforce(args: &Args, root: &Path) -> anyhow::Result<()> {
+	let Some(quota) = quota(root) else {
+		return Ok(());
+	};
+
+	let mut used = refresh(args, root)?;
+
+	if used <= quota.max || args.over_quota != OverQuota::Prune {
+		return Ok(());
+	}
+
+	let starred: HashSet<PathBuf> =
+		tags::read(root)?.into_iter().filter(|(_, t)| t.contains(tags::STAR)).map(|(f, _)| f).collect();
+
+	let mut files = vec![];
+
+	for file in commands::organized_files(args, root)? {
+		if starred.contains(&file) {
+			continue;
+		}
+
+		let metadata = file.symlink_metadata()?;
+
+		if metadata.is_file() {
+			files.push((metadata.modified()?, metadata.len(), file));
+		}
+	}
+
+	files.sort();
+
+	for (_, size, file) in files {
+		if used <= quota.max {
+			break;
+		}
+
+		info!("Prune \"{}\", the tree is over {}", file.display(), format_size(quota.max));
+		fs::remove_file(&file)?;
+		MOVED_AWAY.lock().unwrap().insert(file.clone()); // Not removed by someone else
+		changes::forget(root, &file);
+		metrics::pruned();
+
+		used -= size;
+	}
+
+	quota.used.store(used, Ordering::SeqCst);
+
+	if used > quota.max {
+		alert(args, &quota, "everything left is starred");
+	}
+
+	Ok(())
+}
+
+/// Reports the tree being over its quota, once until it fits again
+fn alert(args: &Args, quota: &Quota, consequence: &str) {
+	if !quota.alerted.swap(true, Ordering::SeqCst) {
+		let message = format!("the tree reached its {} quota, {consequence}", format_size(quota.max));
+		hooks::alert(args, "Screenshots are over quota", &message);
+	}
+}
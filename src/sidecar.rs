@@ -0,0 +1,83 @@
+//! `<name>.json` files next to sorted screenshots with what is known about them, written with --sidecars
+
+use std::{
+	fs, io,
+	path::{Path, PathBuf},
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{json, source::Source, tags, windows};
+
+/// Sidecar of `file`, its whole name with ".json" added
+pub fn path(file: &Path) -> PathBuf {
+	let mut name = file.file_name().unwrap_or_default().to_owned();
+	name.push(".json");
+	file.with_file_name(name)
+}
+
+/// Whether `file` is the sidecar of a file next to it
+pub fn is_sidecar(file: &Path) -> bool {
+	let name = file.file_name().unwrap_or_default().to_string_lossy();
+	name.strip_suffix(".json").is_some_and(|owner| file.with_file_name(owner).is_file())
+}
+
+/// Whether `file` is named like a sidecar, its file may be gone already
+pub fn named_like(file: &Path) -> bool {
+	let name = file.file_name().unwrap_or_default().to_string_lossy();
+	name.strip_suffix(".json").is_some_and(|owner| Path::new(owner).extension().is_some())
+}
+
+/// Writes the sidecar of `file` in the tree below `root`, which was sorted from `original`
+pub fn write(root: &Path, file: &Path, original: &Path) -> io::Result<()> {
+	let sorted = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+	write_with(root, file, original, sorted)
+}
+
+fn write_with(root: &Path, file: &Path, original: &Path, sorted: u64) -> io::Result<()> {
+	let optional = |s: Option<&str>| s.map_or("null".to_owned(), json::string);
+	let window = windows::read(root)?.remove(file).unwrap_or_default();
+	let tags = tags::read(root)?.remove(file).unwrap_or_default();
+	let tags: Vec<_> = tags.iter().map(|t| json::string(t)).collect();
+	let original_name = original.file_name().unwrap_or_default().to_string_lossy();
+
+	let contents = format!(
+		"{{\n  \"original\": {},\n  \"sorted\": {sorted},\n  \"app\": {},\n  \"window\": {},\n  \"workspace\": {},\n  \
+		 \"tags\": [{}]\n}}\n",
+		json::string(&original.to_string_lossy()),
+		optional(Source::of(&original_name, None).app),
+		optional(window.title.as_deref()),
+		optional(window.workspace.as_deref()),
+		tags.join(", ")
+	);
+
+	fs::write(path(file), contents)
+}
+
+/// Rewrites the sidecar of `file` if it has one, for tags or a window that changed since
+pub fn refresh(root: &Path, file: &Path) -> io::Result<()> {
+	let contents = match fs::read_to_string(path(file)) {
+		Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+		res => res?,
+	};
+
+	let sidecar = json::parse(&contents).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not JSON"))?;
+	let original = sidecar.get("original").and_then(json::Value::as_str).map_or(file.to_path_buf(), PathBuf::from);
+	let sorted = match sidecar.get("sorted") {
+		Some(json::Value::Number(n)) => *n as u64,
+		_ => 0,
+	};
+
+	write_with(root, file, &original, sorted)
+}
+
+/// Takes the sidecar of `from` along to `to`, if it has one
+pub fn moved(from: &Path, to: &Path) -> io::Result<()> {
+	match fs::rename(path(from), path(to)) {
+		Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+		Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+			fs::copy(path(from), path(to))?;
+			fs::remove_file(path(from))
+		}
+		res => res,
+	}
+}
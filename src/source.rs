@@ -0,0 +1,53 @@
+//! Where a screenshot came from, told by the name it was given
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+	/// Default names of screenshot tools, with the folder name "{app}" becomes for each
+	static ref APPS: Vec<(Regex, &'static str)> = [
+		(r"(?i)^flameshot", "flameshot"),
+		// "Screenshot_20240501_134512.png"
+		(r"^Screenshot_\d{8}_\d{6}", "spectacle"),
+		// GNOME Screenshot, "Screenshot from 2024-05-01 13-45-12.png"
+		(r"^Screenshot from \d{4}-\d\d-\d\d", "gnome-screenshot"),
+		// "Screenshot 2024-05-01 13-45-12.png", macOS adds an "at" in between
+		(r"^Screenshot \d{4}-\d\d-\d\d \d\d-\d\d-\d\d", "obs"),
+	]
+	.into_iter()
+	.map(|(pattern, app)| (Regex::new(pattern).unwrap(), app)) // Known to be valid
+	.collect();
+
+	/// Output names as X11 and Wayland compositors give them, like "DP-1", "HDMI-A-1" or "eDP-1"
+	static ref MONITOR: Regex =
+		Regex::new(r"(?:^|[^A-Za-z])((?:e?DP|HDMI(?:-[A-C])?|DVI(?:-[ADI])?|VGA|LVDS|DSI|Virtual)-\d+)(?:$|[^\d])")
+			.unwrap();
+}
+
+/// What the name of a file says besides its date, and the size of the image in it
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Source<'a> {
+	/// Tool that took the screenshot
+	pub app: Option<&'a str>,
+	/// Output the screenshot is of
+	pub monitor: Option<&'a str>,
+	/// Width and height, only read from the file when the layout needs them
+	pub resolution: Option<(u32, u32)>,
+}
+
+impl Source<'_> {
+	/// `monitor` finds the output in the name, its first group if it has one. Without it names like "DP-1" are
+	/// looked for
+	pub fn of<'a>(filename: &'a str, monitor: Option<&Regex>) -> Source<'a> {
+		let captures = monitor.unwrap_or(&MONITOR).captures(filename);
+		let monitor =
+			captures.and_then(|c| c.get(1).or_else(|| c.get(0))).map(|m| m.as_str()).filter(|m| !m.is_empty());
+
+		Source { app: app(filename), monitor, resolution: None }
+	}
+}
+
+/// Tool that named a file `filename`, if it's one of the known ones
+fn app(filename: &str) -> Option<&'static str> {
+	APPS.iter().find(|(pattern, _)| pattern.is_match(filename)).map(|(_, app)| *app)
+}
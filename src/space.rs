@@ -0,0 +1,47 @@
+//! Free space on the filesystem of the tree: checked before files are copied into it, and watched with --min-free
+
+use std::{
+	collections::BTreeSet,
+	io,
+	path::{Path, PathBuf},
+	sync::Mutex,
+};
+
+use crate::{format_size, hooks, platform, Args};
+
+/// Trees reported to be low on space already, they are again once they had enough
+static LOW: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+/// Fails before writing `needed` bytes into `dir` if they don't fit, rather than halfway through
+pub fn ensure(dir: &Path, needed: u64) -> io::Result<()> {
+	// If it can't be told, the copy may still work
+	let Ok(free) = platform::free_space(dir) else {
+		return Ok(());
+	};
+
+	if free < needed {
+		let message = format!("{} needed but {} free in \"{}\"", format_size(needed), format_size(free), dir.display());
+		return Err(io::Error::new(io::ErrorKind::StorageFull, message));
+	}
+
+	Ok(())
+}
+
+/// Free space in the tree in `root` when it's below --min-free
+pub fn low(args: &Args, root: &Path) -> Option<u64> {
+	let min = args.min_free?;
+	platform::free_space(root).ok().filter(|&free| free < min)
+}
+
+/// Reports the tree getting below --min-free, once until it has enough again
+pub fn check(args: &Args, root: &Path) {
+	let Some(free) = low(args, root) else {
+		LOW.lock().unwrap().remove(root);
+		return;
+	};
+
+	if LOW.lock().unwrap().insert(root.to_path_buf()) {
+		let message = format!("only {} free in \"{}\"", format_size(free), root.display());
+		hooks::alert(args, "Screenshots are low on disk space", &message);
+	}
+}
@@ -0,0 +1,100 @@
+//! Tags given to screenshots with the tag command
+
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	fs, io,
+	path::{Path, PathBuf},
+	sync::Mutex,
+};
+
+use crate::{
+	changes::rebase,
+	journal::{escape, unescape},
+	STATE_DIR,
+};
+
+/// Tag of starred files
+pub const STAR: &str = "starred";
+
+/// Tags of each file, by its path
+pub type Tags = BTreeMap<PathBuf, BTreeSet<String>>;
+
+fn tags_file(root: &Path) -> PathBuf {
+	root.join(STATE_DIR).join("tags")
+}
+
+/// Tags of every file below `root` that has any
+pub fn read(root: &Path) -> io::Result<Tags> {
+	let contents = match fs::read_to_string(tags_file(root)) {
+		Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Tags::new()),
+		res => res?,
+	};
+
+	Ok(contents
+		.lines()
+		.filter_map(|line| {
+			let mut fields = line.split('\t');
+			let file = root.join(unescape(fields.next()?));
+
+			Some((file, fields.map(unescape).collect()))
+		})
+		.collect())
+}
+
+/// Replaces the recorded tags with `tags`, files without any are left out
+pub fn write(root: &Path, tags: &Tags) -> io::Result<()> {
+	let file = tags_file(root);
+	fs::create_dir_all(file.parent().unwrap())?; // Always has one
+
+	let contents: String = tags
+		.iter()
+		.filter(|(_, tags)| !tags.is_empty())
+		.map(|(file, tags)| {
+			let file = file.strip_prefix(root).unwrap_or(file);
+			let fields = std::iter::once(escape(&file.to_string_lossy())).chain(tags.iter().map(|t| escape(t)));
+			fields.collect::<Vec<_>>().join("\t") + "\n"
+		})
+		.collect();
+
+	// Written whole next to it and renamed over it, a crash leaves the old tags and not half of the new ones
+	let temp = file.with_extension("partial");
+	fs::write(&temp, contents)?;
+	fs::rename(temp, file)
+}
+
+/// Held while changing the tags of one file, moves run in parallel and would lose each other's changes
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// Carries the tags of `from` over to `to`, where it was moved. Of every file in it if it's a folder
+pub fn moved(root: &Path, from: &Path, to: &Path) -> io::Result<()> {
+	let _lock = LOCK.lock().unwrap();
+
+	let mut tags = read(root)?;
+	let moved: Vec<_> = tags.keys().filter(|f| f.starts_with(from)).cloned().collect();
+
+	if moved.is_empty() {
+		return Ok(());
+	}
+
+	for file in moved {
+		let file_tags = tags.remove(&file).unwrap_or_default();
+		tags.insert(rebase(&file, from, to).unwrap_or(file), file_tags); // Below `from`, filtered above
+	}
+
+	write(root, &tags)
+}
+
+/// Forgets the tags of `path`, it's gone. Of every file in it if it was a folder
+pub fn removed(root: &Path, path: &Path) -> io::Result<()> {
+	let _lock = LOCK.lock().unwrap();
+
+	let mut tags = read(root)?;
+	let len = tags.len();
+	tags.retain(|file, _| !file.starts_with(path));
+
+	if tags.len() == len {
+		return Ok(());
+	}
+
+	write(root, &tags)
+}
@@ -0,0 +1,210 @@
+//! Thumbnails of the screenshots of the tree for image viewers and galleries: PNGs of their first frame that
+//! ImageMagick makes, kept in the state folder laid out like the tree
+
+use std::{
+	collections::HashSet,
+	ffi::OsString,
+	fs,
+	io::Read,
+	path::{Path, PathBuf},
+};
+
+use crate::{
+	commands::{organized_files, remove_empty, tree_files},
+	events::{info, Event},
+	format_size, magick, metadata, Args, STATE_DIR,
+};
+
+/// Longest sides of a thumbnail, in pixels
+const SIZE: &str = "256x256";
+
+fn thumbs_dir(root: &Path) -> PathBuf {
+	root.join(STATE_DIR).join("thumbs")
+}
+
+/// Thumbnail of `file` of the tree in `root`, named like it with ".png" added so "a.jpg" and "a.png" don't clash
+fn thumb(root: &Path, file: &Path) -> PathBuf {
+	let mut thumb = thumbs_dir(root).join(file.strip_prefix(root).unwrap_or(file)).into_os_string();
+	thumb.push(".png");
+
+	thumb.into()
+}
+
+/// Screenshots of the tree that have thumbnails, the images
+fn images(args: &Args, root: &Path) -> anyhow::Result<Vec<PathBuf>> {
+	let mut files = organized_files(args, root)?;
+	files.retain(|file| metadata::image_type(file).ok().flatten().is_some());
+	files.sort();
+
+	Ok(files)
+}
+
+/// Whether `thumb` is missing or older than `file`, changed or re-encoded since
+fn outdated(file: &Path, thumb: &Path) -> bool {
+	let modified = |f: &Path| f.metadata().and_then(|m| m.modified()).ok();
+	modified(thumb).is_none_or(|made| modified(file).is_some_and(|changed| changed > made))
+}
+
+/// Makes the thumbnail of `file` of the tree in `root`
+fn make(root: &Path, file: &Path) -> anyhow::Result<PathBuf> {
+	let thumb = thumb(root, file);
+	fs::create_dir_all(thumb.parent().unwrap())?; // Always has one
+
+	// Written next to it and renamed over it, a viewer never shows half of one
+	let temp = thumb.with_extension("partial");
+	let mut output = OsString::from("PNG:");
+	output.push(&temp);
+
+	if let Err(e) = magick::convert(file, &["-thumbnail", SIZE, "-strip"], &output) {
+		_ = fs::remove_file(&temp);
+		return Err(e);
+	}
+
+	fs::rename(&temp, &thumb)?;
+
+	Ok(thumb)
+}
+
+/// The thumbnail of the image `file` of the tree in `root`, made first if it's missing or outdated
+pub fn current(root: &Path, file: &Path) -> anyhow::Result<PathBuf> {
+	match thumb(root, file) {
+		thumb if !outdated(file, &thumb) => Ok(thumb),
+		_ => make(root, file),
+	}
+}
+
+/// Makes the thumbnails that are missing or outdated, with `all` every one of them
+pub fn rebuild(args: &Args, root: &Path, all: bool) -> anyhow::Result<()> {
+	let (mut made, mut failed) = (0, 0);
+
+	for file in images(args, root)? {
+		if !all && !outdated(&file, &thumb(root, &file)) {
+			continue;
+		}
+
+		match make(root, &file) {
+			Ok(_) => made += 1,
+			Err(e) => {
+				Event::Error { path: Some(&file), message: format!("making its thumbnail: {e}") }.emit();
+				failed += 1;
+			}
+		}
+	}
+
+	info!("Made {made} thumbnails, {failed} failed");
+
+	match failed {
+		0 => Ok(()),
+		1 => anyhow::bail!("1 thumbnail could not be made"),
+		n => anyhow::bail!("{n} thumbnails could not be made"),
+	}
+}
+
+/// Removes the thumbnails of screenshots that are gone, then with `max_size` the oldest ones until the rest fit
+pub fn prune(args: &Args, root: &Path, max_size: Option<u64>) -> anyhow::Result<()> {
+	let dir = thumbs_dir(root);
+
+	if !dir.is_dir() {
+		info!("There are no thumbnails in \"{}\"", root.display());
+		return Ok(());
+	}
+
+	let wanted: HashSet<_> = images(args, root)?.iter().map(|file| thumb(root, file)).collect();
+	let mut thumbs = vec![];
+	tree_files(&dir, &mut thumbs)?;
+
+	let mut kept = vec![];
+	let mut removed = 0;
+
+	for thumb in thumbs {
+		if wanted.contains(&thumb) {
+			let metadata = thumb.metadata()?;
+			kept.push((metadata.modified()?, metadata.len(), thumb));
+			continue;
+		}
+
+		info!("Remove \"{}\", its screenshot is gone", thumb.display());
+		fs::remove_file(&thumb)?;
+		removed += 1;
+	}
+
+	if let Some(max) = max_size {
+		let mut total: u64 = kept.iter().map(|(_, size, _)| size).sum();
+		kept.sort();
+
+		for (_, size, thumb) in kept {
+			if total <= max {
+				break;
+			}
+
+			info!("Remove \"{}\", the thumbnails are over {}", thumb.display(), format_size(max));
+			fs::remove_file(&thumb)?;
+			total -= size;
+			removed += 1;
+		}
+	}
+
+	// The folders of the tree are made again as thumbnails are
+	for entry in fs::read_dir(&dir)? {
+		let entry = entry?;
+
+		if entry.file_type()?.is_dir() {
+			remove_empty(&entry.path())?;
+		}
+	}
+
+	info!("Removed {removed} thumbnails");
+
+	Ok(())
+}
+
+/// Reports screenshots without a thumbnail or with an outdated or broken one, and thumbnails of screenshots that
+/// are gone. Errors if there are any
+pub fn verify(args: &Args, root: &Path) -> anyhow::Result<()> {
+	let mut problems = vec![];
+	let images = images(args, root)?;
+	let wanted: HashSet<_> = images.iter().map(|file| thumb(root, file)).collect();
+
+	for file in &images {
+		let thumb = thumb(root, file);
+
+		if !thumb.exists() {
+			problems.push(format!("\"{}\" has no thumbnail", file.display()));
+		} else if outdated(file, &thumb) {
+			problems.push(format!("The thumbnail of \"{}\" is older than it", file.display()));
+		} else {
+			// Damaged, or put there by something else
+			let mut magic = vec![];
+			fs::File::open(&thumb)?.take(8).read_to_end(&mut magic)?;
+
+			if magic != metadata::PNG_SIGNATURE {
+				problems.push(format!("The thumbnail of \"{}\" is not a PNG", file.display()));
+			}
+		}
+	}
+
+	let mut thumbs = vec![];
+
+	if thumbs_dir(root).is_dir() {
+		tree_files(&thumbs_dir(root), &mut thumbs)?;
+	}
+
+	thumbs.sort();
+
+	for thumb in thumbs.iter().filter(|t| !wanted.contains(*t)) {
+		problems.push(format!("\"{}\" is the thumbnail of a screenshot that is gone", thumb.display()));
+	}
+
+	for problem in &problems {
+		println!("{problem}");
+	}
+
+	match problems.len() {
+		0 => {
+			println!("All {} thumbnails of \"{}\" are there and up to date", images.len(), root.display());
+			Ok(())
+		}
+		1 => anyhow::bail!("1 problem found"),
+		n => anyhow::bail!("{n} problems found"),
+	}
+}
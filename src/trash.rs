@@ -0,0 +1,107 @@
+//! Putting files in the desktop's trash instead of deleting them for good
+
+#[cfg(all(unix, not(target_os = "macos")))]
+use std::{env, fs, io::Write, path::PathBuf, time::SystemTime};
+use std::{io, path::Path};
+
+#[cfg(all(unix, not(target_os = "macos")))]
+use crate::upload::percent_encode;
+
+/// Trash of the FreeDesktop.org spec, in the data directory of the user
+#[cfg(all(unix, not(target_os = "macos")))]
+fn trash_dir() -> io::Result<PathBuf> {
+	let data = match env::var_os("XDG_DATA_HOME").filter(|d| !d.is_empty()) {
+		Some(data) => PathBuf::from(data),
+		None => {
+			PathBuf::from(env::var_os("HOME").ok_or_else(|| io::Error::other("HOME is not set"))?).join(".local/share")
+		}
+	};
+
+	Ok(data.join("Trash"))
+}
+
+/// Moves `file` to the trash, with the info file the trash needs to put it back
+#[cfg(all(unix, not(target_os = "macos")))]
+pub fn put(file: &Path) -> io::Result<()> {
+	let trash = trash_dir()?;
+	let (files, info) = (trash.join("files"), trash.join("info"));
+	fs::create_dir_all(&files)?;
+	fs::create_dir_all(&info)?;
+
+	let file = std::path::absolute(file)?;
+	let stem = file.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+	let ext = file.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+
+	// Creating the info file claims the name, trashed files with the same one get a number
+	let (name, mut info_file) = (1..)
+		.map(|n| if n == 1 { format!("{stem}{ext}") } else { format!("{stem}.{n}{ext}") })
+		.find_map(|name| {
+			let created =
+				fs::OpenOptions::new().write(true).create_new(true).open(info.join(format!("{name}.trashinfo")));
+
+			match created {
+				Err(e) if e.kind() == io::ErrorKind::AlreadyExists => None,
+				res => Some(res.map(|f| (name, f))),
+			}
+		})
+		.unwrap()?; // Endless
+
+	// Escaped like a URI, but for the slashes between folders
+	let path: Vec<_> = file.to_string_lossy().split('/').map(percent_encode).collect();
+	let (date, time) = crate::tz::Zone::Local.local(SystemTime::now());
+	let contents = format!(
+		"[Trash Info]\nPath={}\nDeletionDate={date}T{:02}:{:02}:{:02}\n",
+		path.join("/"),
+		time.hour,
+		time.minute,
+		time.second
+	);
+	info_file.write_all(contents.as_bytes())?;
+
+	let to = files.join(&name);
+	let res = fs::rename(&file, &to).or_else(|_| fs::copy(&file, &to).and_then(|_| fs::remove_file(&file)));
+
+	if res.is_err() {
+		_ = fs::remove_file(info.join(format!("{name}.trashinfo")));
+	}
+
+	res
+}
+
+/// Moves `file` to the trash of the Finder
+#[cfg(target_os = "macos")]
+pub fn put(file: &Path) -> io::Result<()> {
+	let file = std::path::absolute(file)?;
+	// Given as an argument of the script, not in it, so no name is ever run as AppleScript
+	let script = ["on run argv", "tell application \"Finder\" to delete POSIX file (item 1 of argv)", "end run"];
+	let mut command = std::process::Command::new("osascript");
+
+	for line in script {
+		command.args(["-e", line]);
+	}
+
+	run(command.arg(file))
+}
+
+/// Moves `file` to the Recycle Bin
+#[cfg(windows)]
+pub fn put(file: &Path) -> io::Result<()> {
+	let file = std::path::absolute(file)?;
+	let script = format!(
+		"Add-Type -AssemblyName Microsoft.VisualBasic; [Microsoft.VisualBasic.FileIO.FileSystem]::DeleteFile('{}', \
+		 'OnlyErrorDialogs', 'SendToRecycleBin')",
+		file.display().to_string().replace('\'', "''")
+	);
+	run(std::process::Command::new("powershell").args(["-NoProfile", "-Command", &script]))
+}
+
+#[cfg(any(target_os = "macos", windows))]
+fn run(command: &mut std::process::Command) -> io::Result<()> {
+	let output = command.output()?;
+
+	if output.status.success() {
+		Ok(())
+	} else {
+		Err(io::Error::other(format!("trashing failed: {}", String::from_utf8_lossy(&output.stderr).trim())))
+	}
+}
@@ -0,0 +1,435 @@
+//! Time zones: the system's local one, UTC, or one read from the system tz database
+
+use std::{
+	fs,
+	path::PathBuf,
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+	date::{Date, Time},
+	platform,
+};
+
+const DAY: i64 = 24 * 60 * 60;
+
+#[derive(Clone, Debug)]
+pub enum Zone {
+	Local,
+	Utc,
+	Named(Tzif),
+}
+
+impl Zone {
+	/// Parses "local", "utc" or a tz database name like "Europe/Madrid"
+	pub fn parse(s: &str) -> Result<Zone, String> {
+		match s {
+			"local" => Ok(Zone::Local),
+			"utc" | "UTC" => Ok(Zone::Utc),
+			name => {
+				let dir = std::env::var_os("TZDIR").map(PathBuf::from).unwrap_or_else(|| "/usr/share/zoneinfo".into());
+				let data = fs::read(dir.join(name)).map_err(|e| format!("unknown time zone \"{name}\": {e}"))?;
+				let tzif = Tzif::parse(&data).ok_or_else(|| format!("\"{name}\" is not a valid tz database file"))?;
+
+				Ok(Zone::Named(tzif))
+			}
+		}
+	}
+
+	/// Seconds to add to UTC to get the time in this zone at `secs` since the epoch
+	pub fn offset(&self, secs: i64) -> i64 {
+		match self {
+			Zone::Local => platform::utc_offset(secs),
+			Zone::Utc => 0,
+			Zone::Named(tzif) => tzif.offset(secs),
+		}
+	}
+
+	pub fn wall_clock(&self, secs: i64) -> (Date, Time) {
+		let local = secs + self.offset(secs);
+		(Date::from_days(local.div_euclid(DAY)), Time::from_secs(local.rem_euclid(DAY) as u32))
+	}
+
+	/// Inverse of [`Zone::wall_clock`], times skipped or repeated by DST changes resolve to one of the candidates
+	pub fn instant(&self, date: Date, time: Time) -> i64 {
+		let local = date.days() * DAY + i64::from(time.secs());
+		let guess = local - self.offset(local);
+
+		local - self.offset(guess)
+	}
+
+	pub fn local(&self, time: SystemTime) -> (Date, Time) {
+		let secs = match time.duration_since(UNIX_EPOCH) {
+			Ok(d) => d.as_secs() as i64,
+			Err(e) => -(e.duration().as_secs() as i64),
+		};
+
+		self.wall_clock(secs)
+	}
+}
+
+/// Contents of a TZif file, see RFC 8536
+#[derive(Clone, Debug)]
+pub struct Tzif {
+	transitions: Vec<(i64, i64)>,
+	initial: i64,
+	rule: Option<Rule>,
+}
+
+impl Tzif {
+	fn parse(data: &[u8]) -> Option<Tzif> {
+		let mut r = Reader(data);
+
+		let (version, mut counts) = r.header()?;
+		let mut time_size = 4;
+
+		if version >= b'2' {
+			// The first block only has 32 bit times, skip straight to the full one
+			r.skip(block_len(&counts, 4))?;
+			counts = r.header()?.1;
+			time_size = 8;
+		}
+
+		let [isut, isstd, leap, time, types, chars] = counts;
+
+		let times = (0..time).map(|_| r.int(time_size)).collect::<Option<Vec<_>>>()?;
+		let indices = r.take(time)?.to_vec();
+		let offsets = (0..types)
+			.map(|_| {
+				let offset = r.int(4)?;
+				r.skip(2)?; // DST flag and abbreviation, neither matters here
+				Some(offset)
+			})
+			.collect::<Option<Vec<_>>>()?;
+
+		r.skip(chars + leap * (time_size + 4) + isstd + isut)?;
+
+		let transitions = times
+			.into_iter()
+			.zip(indices)
+			.map(|(t, i)| Some((t, *offsets.get(usize::from(i))?)))
+			.collect::<Option<Vec<_>>>()?;
+
+		// Times after the last transition follow the POSIX TZ string in the footer
+		let rule = if version >= b'2' {
+			std::str::from_utf8(r.0).ok().and_then(|s| parse_rule(s.trim_matches('\n')))
+		} else {
+			None
+		};
+
+		Some(Tzif { transitions, initial: *offsets.first()?, rule })
+	}
+
+	fn offset(&self, secs: i64) -> i64 {
+		match (self.transitions.partition_point(|&(t, _)| t <= secs), &self.rule) {
+			// Without transitions the footer covers all time
+			(n, Some(rule)) if n == self.transitions.len() => rule.offset(secs),
+			(0, _) => self.initial,
+			(n, _) => self.transitions[n - 1].1,
+		}
+	}
+}
+
+fn block_len(counts: &[usize; 6], time_size: usize) -> usize {
+	let [isut, isstd, leap, time, types, chars] = *counts;
+	time * time_size + time + types * 6 + chars + leap * (time_size + 4) + isstd + isut
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+	fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+		let (head, tail) = (self.0.get(..n)?, self.0.get(n..)?);
+		self.0 = tail;
+		Some(head)
+	}
+
+	fn skip(&mut self, n: usize) -> Option<()> {
+		self.take(n).map(|_| ())
+	}
+
+	fn int(&mut self, size: usize) -> Option<i64> {
+		let bytes = self.take(size)?;
+		Some(match size {
+			4 => i64::from(i32::from_be_bytes(bytes.try_into().ok()?)),
+			_ => i64::from_be_bytes(bytes.try_into().ok()?),
+		})
+	}
+
+	/// Version and the six counts of a TZif header
+	fn header(&mut self) -> Option<(u8, [usize; 6])> {
+		if self.take(4)? != b"TZif" {
+			return None;
+		}
+
+		let version = self.take(1)?[0];
+		self.skip(15)?;
+
+		let mut counts = [0; 6];
+		for c in &mut counts {
+			*c = self.int(4)? as usize;
+		}
+
+		Some((version, counts))
+	}
+}
+
+/// POSIX TZ string like "CET-1CEST,M3.5.0,M10.5.0/3"
+#[derive(Clone, Debug)]
+struct Rule {
+	std: i64,
+	dst: Option<(i64, Change, Change)>,
+}
+
+/// When DST starts or ends: a day of the year and a local time of that day in seconds
+#[derive(Clone, Copy, Debug)]
+enum Change {
+	/// 1 to 365, February 29th is never counted
+	Julian(u32, i64),
+	/// 0 to 365, counting February 29th
+	Day(u32, i64),
+	/// Month, week 1 to 5 where 5 is the last, weekday with 0 being Sunday
+	Weekday(u32, u32, u32, i64),
+}
+
+impl Rule {
+	fn offset(&self, secs: i64) -> i64 {
+		let Some((dst, start, end)) = self.dst else {
+			return self.std;
+		};
+
+		let year = Date::from_days((secs + self.std).div_euclid(DAY)).year;
+
+		// Start is given in standard time, end in daylight time
+		let start = start.local_secs(year) - self.std;
+		let end = end.local_secs(year) - dst;
+
+		let in_dst = if start < end { start <= secs && secs < end } else { secs < end || start <= secs };
+
+		if in_dst {
+			dst
+		} else {
+			self.std
+		}
+	}
+}
+
+impl Change {
+	fn local_secs(&self, year: i32) -> i64 {
+		let jan1 = Date { year, month: 1, day: 1 }.days();
+		let leap = Date { year, month: 3, day: 1 }.days() - Date { year, month: 2, day: 28 }.days() == 2;
+
+		let (days, time) = match *self {
+			Change::Julian(n, t) => (jan1 + i64::from(n) - 1 + i64::from(leap && n >= 60), t),
+			Change::Day(n, t) => (jan1 + i64::from(n), t),
+			Change::Weekday(month, week, weekday, t) => {
+				let first = Date { year, month, day: 1 }.days();
+				let next = Date { year: year + i32::from(month == 12), month: month % 12 + 1, day: 1 }.days();
+				// 1970-01-01 was a Thursday
+				let first_weekday = (first + 4).rem_euclid(7);
+				let mut day = first + (i64::from(weekday) - first_weekday).rem_euclid(7) + i64::from(week - 1) * 7;
+
+				while day >= next {
+					day -= 7;
+				}
+
+				(day, t)
+			}
+		};
+
+		days * DAY + time
+	}
+}
+
+fn parse_rule(mut s: &str) -> Option<Rule> {
+	skip_name(&mut s)?;
+	// POSIX offsets are west of Greenwich, the opposite of everything else
+	let std = -parse_offset(&mut s)?;
+
+	if s.is_empty() {
+		return Some(Rule { std, dst: None });
+	}
+
+	skip_name(&mut s)?;
+	let dst = if s.starts_with(',') { std + 60 * 60 } else { -parse_offset(&mut s)? };
+
+	let (start, end) = s.strip_prefix(',')?.split_once(',')?;
+
+	Some(Rule { std, dst: Some((dst, parse_change(start)?, parse_change(end)?)) })
+}
+
+fn skip_name(s: &mut &str) -> Option<()> {
+	let len = if let Some(rest) = s.strip_prefix('<') {
+		rest.find('>')? + 2
+	} else {
+		s.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(s.len())
+	};
+
+	(len > 0).then(|| *s = &s[len..])
+}
+
+/// `[+-]hh[:mm[:ss]]` in seconds
+fn parse_offset(s: &mut &str) -> Option<i64> {
+	let len = s.find(|c: char| !(c.is_ascii_digit() || matches!(c, '+' | '-' | ':'))).unwrap_or(s.len());
+	let (offset, rest) = s.split_at(len);
+	*s = rest;
+
+	let (sign, offset) = match offset.as_bytes().first()? {
+		b'-' => (-1, &offset[1..]),
+		b'+' => (1, &offset[1..]),
+		_ => (1, offset),
+	};
+
+	let mut secs = 0;
+	for (part, scale) in offset.split(':').zip([60 * 60, 60, 1]) {
+		secs += part.parse::<i64>().ok()? * scale;
+	}
+
+	Some(sign * secs)
+}
+
+fn parse_change(s: &str) -> Option<Change> {
+	let (day, time) = match s.split_once('/') {
+		Some((day, mut time)) => (day, parse_offset(&mut time)?),
+		None => (s, 2 * 60 * 60),
+	};
+
+	if let Some(n) = day.strip_prefix('J') {
+		Some(Change::Julian(n.parse().ok()?, time))
+	} else if let Some(mwd) = day.strip_prefix('M') {
+		let mut parts = mwd.split('.').map(|p| p.parse::<u32>().ok());
+		let (month, week, weekday) = (parts.next()??, parts.next()??, parts.next()??);
+
+		((1..=12).contains(&month) && (1..=5).contains(&week) && weekday < 7)
+			.then_some(Change::Weekday(month, week, weekday, time))
+	} else {
+		Some(Change::Day(day.parse().ok()?, time))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const HOUR: i64 = 60 * 60;
+
+	/// Seconds since the epoch of a time in UTC
+	fn utc(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> i64 {
+		Date { year, month, day }.days() * DAY + i64::from(hour) * HOUR + i64::from(minute) * 60
+	}
+
+	fn rule(s: &str) -> Rule {
+		parse_rule(s).unwrap_or_else(|| panic!("\"{s}\" didn't parse"))
+	}
+
+	#[test]
+	fn rules_without_dst() {
+		assert_eq!(rule("UTC0").offset(0), 0);
+		assert_eq!(rule("IST-5:30").offset(0), 5 * HOUR + 30 * 60);
+		assert_eq!(rule("<-03>3").offset(0), -3 * HOUR);
+	}
+
+	#[test]
+	fn central_europe() {
+		let cet = rule("CET-1CEST,M3.5.0,M10.5.0/3");
+
+		// The last Sundays of March and October 2024, at 01:00 UTC
+		assert_eq!(cet.offset(utc(2024, 3, 31, 0, 59)), HOUR);
+		assert_eq!(cet.offset(utc(2024, 3, 31, 1, 0)), 2 * HOUR);
+		assert_eq!(cet.offset(utc(2024, 10, 27, 0, 59)), 2 * HOUR);
+		assert_eq!(cet.offset(utc(2024, 10, 27, 1, 0)), HOUR);
+		assert_eq!(cet.offset(utc(2024, 1, 15, 12, 0)), HOUR);
+	}
+
+	#[test]
+	fn us_eastern() {
+		let eastern = rule("EST5EDT,M3.2.0,M11.1.0");
+
+		// The second Sunday of March and the first of November 2024, at 02:00 local time
+		assert_eq!(eastern.offset(utc(2024, 3, 10, 6, 59)), -5 * HOUR);
+		assert_eq!(eastern.offset(utc(2024, 3, 10, 7, 0)), -4 * HOUR);
+		assert_eq!(eastern.offset(utc(2024, 11, 3, 5, 59)), -4 * HOUR);
+		assert_eq!(eastern.offset(utc(2024, 11, 3, 6, 0)), -5 * HOUR);
+	}
+
+	#[test]
+	fn southern_hemisphere() {
+		// DST spans the new year
+		let sydney = rule("AEST-10AEDT,M10.1.0,M4.1.0/3");
+
+		assert_eq!(sydney.offset(utc(2024, 1, 15, 0, 0)), 11 * HOUR);
+		assert_eq!(sydney.offset(utc(2024, 4, 6, 15, 59)), 11 * HOUR);
+		assert_eq!(sydney.offset(utc(2024, 4, 6, 16, 0)), 10 * HOUR);
+		assert_eq!(sydney.offset(utc(2024, 10, 5, 15, 59)), 10 * HOUR);
+		assert_eq!(sydney.offset(utc(2024, 10, 5, 16, 0)), 11 * HOUR);
+	}
+
+	#[test]
+	fn bad_rules() {
+		assert!(parse_rule("").is_none());
+		assert!(parse_rule("CET-1CEST,M13.5.0,M10.5.0").is_none());
+		assert!(parse_rule("CET-1CEST,M3.6.0,M10.5.0").is_none());
+		assert!(parse_rule("CET-1CEST,M3.5.0").is_none());
+	}
+
+	/// A version 2 TZif file with `transitions` to the types of `offsets`, then following `footer`
+	fn tzif(transitions: &[(i64, u8)], offsets: &[i32], footer: &str) -> Vec<u8> {
+		let header = |times: usize, types: usize| {
+			let mut header = b"TZif2".to_vec();
+			header.extend([0; 15]);
+
+			for count in [0, 0, 0, times, types, types * 4] {
+				header.extend((count as u32).to_be_bytes());
+			}
+
+			header
+		};
+
+		// The version 1 block, skipped over
+		let mut data = header(0, 1);
+		data.extend([0; 6]);
+		data.extend(b"UTC\0");
+
+		data.extend(header(transitions.len(), offsets.len()));
+		transitions.iter().for_each(|(time, _)| data.extend(time.to_be_bytes()));
+		transitions.iter().for_each(|&(_, index)| data.push(index));
+		offsets.iter().for_each(|offset| data.extend(offset.to_be_bytes().into_iter().chain([0, 0])));
+		offsets.iter().for_each(|_| data.extend(b"ABC\0"));
+		data.extend(format!("\n{footer}\n").bytes());
+
+		data
+	}
+
+	#[test]
+	fn tzif_transitions_then_footer() {
+		// Local mean time until 1901, then a fixed hour ahead until 2000, then the rule
+		let (lmt, cet) = (2 * 60 + 16, 60 * 60);
+		let data =
+			tzif(&[(utc(1901, 1, 1, 0, 0), 1), (utc(2000, 1, 1, 0, 0), 1)], &[lmt, cet], "CET-1CEST,M3.5.0,M10.5.0/3");
+		let zone = Tzif::parse(&data).unwrap();
+
+		assert_eq!(zone.offset(utc(1900, 6, 1, 0, 0)), i64::from(lmt));
+		assert_eq!(zone.offset(utc(1990, 7, 1, 0, 0)), HOUR);
+		assert_eq!(zone.offset(utc(2024, 7, 1, 0, 0)), 2 * HOUR);
+		assert_eq!(zone.offset(utc(2024, 12, 1, 0, 0)), HOUR);
+	}
+
+	#[test]
+	fn tzif_garbage() {
+		assert!(Tzif::parse(b"").is_none());
+		assert!(Tzif::parse(b"TZif2 but cut short").is_none());
+		assert!(Tzif::parse(&tzif(&[(0, 5)], &[0], "UTC0")).is_none());
+	}
+
+	#[test]
+	fn wall_clock_and_back() {
+		let zone =
+			Zone::Named(Tzif { transitions: vec![], initial: 0, rule: Some(rule("CET-1CEST,M3.5.0,M10.5.0/3")) });
+		let (date, time) = zone.wall_clock(utc(2024, 7, 1, 22, 30));
+
+		assert_eq!(date, Date { year: 2024, month: 7, day: 2 });
+		assert_eq!(time, Time { hour: 0, minute: 30, second: 0 });
+		assert_eq!(zone.instant(date, time), utc(2024, 7, 1, 22, 30));
+		assert_eq!(Zone::Utc.instant(date, time), utc(2024, 7, 2, 0, 30));
+	}
+}
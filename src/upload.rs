@@ -0,0 +1,123 @@
+//! Uploading new screenshots with --upload to share them, their URL goes on the clipboard
+
+use std::{path::Path, process::Command, sync::Mutex, thread::JoinHandle};
+
+use crate::{
+	events::{info, Event},
+	platform,
+};
+
+/// Where screenshots are uploaded to
+#[derive(Clone, Debug)]
+pub enum Target {
+	/// Sent as the "file" field of a form, the answer is its URL. Like 0x0.st
+	Form(String),
+	/// Put at the URL, with "{name}" replaced by its name. Like a bucket, the URL is that without the query
+	Put(String),
+}
+
+impl Target {
+	/// Parses "0x0.st", a URL for forms or "put:" and a URL
+	pub fn parse(s: &str) -> Result<Target, String> {
+		let is_url = |url: &str| url.starts_with("https://") || url.starts_with("http://");
+
+		match s.strip_prefix("put:") {
+			_ if s == "0x0.st" => Ok(Target::Form("https://0x0.st".to_owned())),
+			Some(url) if is_url(url) && url.contains("{name}") => Ok(Target::Put(url.to_owned())),
+			Some(url) if is_url(url) => Err(format!("\"{url}\" needs a \"{{name}}\" for the name of each file")),
+			None if is_url(s) => Ok(Target::Form(s.to_owned())),
+			_ => Err(format!("\"{s}\" is not 0x0.st, a URL or put: and a URL")),
+		}
+	}
+}
+
+/// Uploads that haven't finished yet
+static UPLOADS: Mutex<Vec<JoinHandle<()>>> = Mutex::new(vec![]);
+
+/// Uploads `file` to `target` from another thread, then puts its URL on the clipboard. `notify` shows it too
+pub fn start(target: &Target, file: &Path, notify: bool) {
+	let (target, file) = (target.clone(), file.to_path_buf());
+
+	let upload = std::thread::spawn(move || match upload(&target, &file) {
+		Ok(url) => {
+			info!("Uploaded \"{}\" to {url}", file.display());
+
+			if let Err(e) = platform::copy_text(&url) {
+				Event::Error { path: Some(&file), message: format!("copying its URL: {e}") }.emit();
+			}
+
+			if notify {
+				let notification = Command::new("notify-send")
+					.args(["--app-name", "screenshot-manager", "Screenshot uploaded", &url])
+					.status();
+
+				if let Err(e) = notification {
+					eprintln!("Error running notify-send: {e}");
+				}
+			}
+		}
+		Err(e) => Event::Error { path: Some(&file), message: format!("uploading: {e}") }.emit(),
+	});
+
+	let mut uploads = UPLOADS.lock().unwrap();
+	uploads.retain(|u| !u.is_finished());
+	uploads.push(upload);
+}
+
+/// Waits for the uploads started, before the program can end
+pub fn wait() {
+	for upload in std::mem::take(&mut *UPLOADS.lock().unwrap()) {
+		_ = upload.join();
+	}
+}
+
+/// Uploads `file` with curl, returns its URL
+fn upload(target: &Target, file: &Path) -> anyhow::Result<String> {
+	let mut curl = Command::new("curl");
+	curl.args(["--fail", "--silent", "--show-error"]);
+
+	let url = match target {
+		Target::Form(url) => {
+			// Quoted, curl would read commas and semicolons in the name as more options
+			let path = file.to_string_lossy().replace('\\', "\\\\").replace('"', "\\\"");
+			curl.arg("--form").arg(format!("file=@\"{path}\"")).arg(url);
+			None
+		}
+		Target::Put(url) => {
+			let name = percent_encode(&file.file_name().unwrap_or_default().to_string_lossy());
+			let url = url.replace("{name}", &name);
+			curl.arg("--upload-file").arg(file).arg(&url);
+			Some(url.split_once('?').map_or(url.as_str(), |(url, _)| url).to_owned())
+		}
+	};
+
+	let output = curl.output().map_err(|e| anyhow::anyhow!("running curl: {e}"))?;
+
+	if !output.status.success() {
+		anyhow::bail!("curl failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+	}
+
+	match url {
+		Some(url) => Ok(url),
+		None => {
+			let answer = String::from_utf8_lossy(&output.stdout);
+			let url = answer.lines().next().unwrap_or_default().trim();
+
+			if url.is_empty() {
+				anyhow::bail!("the upload was answered without a URL");
+			}
+
+			Ok(url.to_owned())
+		}
+	}
+}
+
+/// `s` with everything but unreserved URI characters escaped
+pub fn percent_encode(s: &str) -> String {
+	s.bytes()
+		.map(|b| match b {
+			b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+			b => format!("%{b:02X}"),
+		})
+		.collect()
+}
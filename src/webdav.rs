@@ -0,0 +1,274 @@
+//! Mirroring the tree to a WebDAV server like Nextcloud with curl, remembering what was uploaded in a state file
+
+use std::{
+	collections::{BTreeMap, HashSet},
+	fs,
+	io::{self, Write},
+	path::{Path, PathBuf},
+	process::{Command, Stdio},
+	time::UNIX_EPOCH,
+};
+
+use crate::{
+	events::{info, Event},
+	journal::{escape, unescape},
+	upload::percent_encode,
+	STATE_DIR,
+};
+
+/// A file as it was uploaded
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Synced {
+	size: u64,
+	modified: u64,
+	/// What the server called this version, to only change or delete it if nobody else did
+	etag: String,
+}
+
+/// Files uploaded to one server folder, by their path in the tree
+struct State {
+	url: String,
+	files: BTreeMap<PathBuf, Synced>,
+}
+
+fn state_file(root: &Path) -> PathBuf {
+	root.join(STATE_DIR).join("webdav")
+}
+
+impl State {
+	/// What was uploaded to `url`, nothing if the last sync went somewhere else
+	fn read(root: &Path, url: &str) -> io::Result<State> {
+		let mut state = State { url: url.to_owned(), files: BTreeMap::new() };
+		let contents = match fs::read_to_string(state_file(root)) {
+			Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(state),
+			res => res?,
+		};
+
+		let mut lines = contents.lines();
+
+		if lines.next().and_then(|l| l.strip_prefix("url\t")).map(unescape).as_deref() != Some(url) {
+			return Ok(state);
+		}
+
+		state.files = lines
+			.filter_map(|line| {
+				let mut fields = line.split('\t');
+				let file = PathBuf::from(unescape(fields.next()?));
+				let (size, modified) = (fields.next()?.parse().ok()?, fields.next()?.parse().ok()?);
+
+				Some((file, Synced { size, modified, etag: unescape(fields.next()?) }))
+			})
+			.collect();
+
+		Ok(state)
+	}
+
+	fn write(&self, root: &Path) -> io::Result<()> {
+		let file = state_file(root);
+		fs::create_dir_all(file.parent().unwrap())?; // Always has one
+
+		let mut contents = format!("url\t{}\n", escape(&self.url));
+
+		for (path, synced) in &self.files {
+			let path = escape(&path.to_string_lossy());
+			contents.push_str(&format!("{path}\t{}\t{}\t{}\n", synced.size, synced.modified, escape(&synced.etag)));
+		}
+
+		// Like the tags, a crash leaves the old state whole
+		let temp = file.with_extension("partial");
+		fs::write(&temp, contents)?;
+		fs::rename(temp, file)
+	}
+}
+
+struct Response {
+	status: u16,
+	etag: Option<String>,
+}
+
+struct Client<'a> {
+	url: &'a str,
+	user: Option<&'a str>,
+}
+
+impl Client<'_> {
+	/// URL of `path` in the tree on the server
+	fn url(&self, path: &Path) -> String {
+		let mut url = self.url.to_owned();
+
+		for component in path.iter() {
+			url.push('/');
+			url.push_str(&percent_encode(&component.to_string_lossy()));
+		}
+
+		url
+	}
+
+	fn request(&self, method: &str, path: &Path, headers: &[String], body: Option<&Path>) -> anyhow::Result<Response> {
+		let mut curl = Command::new("curl");
+		curl.args(["--silent", "--show-error", "--netrc-optional", "--dump-header", "-", "--output"]);
+		curl.arg(if cfg!(windows) { "NUL" } else { "/dev/null" });
+		curl.arg("--request").arg(method);
+
+		for header in headers {
+			curl.arg("--header").arg(header);
+		}
+
+		if let Some(body) = body {
+			curl.arg("--upload-file").arg(body);
+		}
+
+		// Read from stdin, a password in the arguments would be there for everyone to see
+		if self.user.is_some() {
+			curl.args(["--config", "-"]);
+		}
+
+		let mut child = curl
+			.arg(self.url(path))
+			.stdin(Stdio::piped())
+			.stdout(Stdio::piped())
+			.stderr(Stdio::piped())
+			.spawn()
+			.map_err(|e| anyhow::anyhow!("running curl: {e}"))?;
+
+		let mut stdin = child.stdin.take().unwrap(); // Piped above
+
+		if let Some(user) = self.user {
+			let password = std::env::var("WEBDAV_PASSWORD").unwrap_or_default();
+			let quote = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+			writeln!(stdin, "user = \"{}:{}\"", quote(user), quote(&password))?;
+		}
+
+		drop(stdin);
+		let output = child.wait_with_output()?;
+
+		if !output.status.success() {
+			anyhow::bail!("curl failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+		}
+
+		// Headers of every response, "100 Continue" can come before the real one
+		let headers = String::from_utf8_lossy(&output.stdout);
+		let mut response = Response { status: 0, etag: None };
+
+		for line in headers.lines() {
+			if line.starts_with("HTTP/") {
+				response.status = line.split_whitespace().nth(1).and_then(|s| s.parse().ok()).unwrap_or_default();
+				response.etag = None;
+			} else if let Some((name, value)) = line.split_once(':') {
+				if name.trim().eq_ignore_ascii_case("etag") {
+					response.etag = Some(value.trim().to_owned());
+				}
+			}
+		}
+
+		if matches!(response.status, 401 | 403) {
+			anyhow::bail!("the server refused the login ({})", response.status);
+		}
+
+		Ok(response)
+	}
+
+	/// Creates the folders down to `dir` that aren't in `created` yet
+	fn create_dirs(&self, dir: &Path, created: &mut HashSet<PathBuf>) -> anyhow::Result<()> {
+		let mut ancestors: Vec<_> = dir.ancestors().filter(|d| !d.as_os_str().is_empty()).collect();
+		ancestors.reverse();
+
+		for dir in ancestors {
+			if created.contains(dir) {
+				continue;
+			}
+
+			// 405 is what servers answer for folders that exist already
+			match self.request("MKCOL", dir, &[], None)?.status {
+				200..=299 | 405 => created.insert(dir.to_path_buf()),
+				status => anyhow::bail!("creating \"{}\" on the server failed ({status})", dir.display()),
+			};
+		}
+
+		Ok(())
+	}
+}
+
+/// Uploads the files of `root` that are new or changed since the last sync to `url`, and deletes those that are
+/// gone. Files changed on the server meanwhile are left alone and reported. Returns how many were
+pub fn mirror(root: &Path, files: &[PathBuf], url: &str, user: Option<&str>) -> anyhow::Result<usize> {
+	// The same folder, with or without the slash
+	let url = url.trim_end_matches('/');
+	let client = Client { url, user };
+	let mut state = State::read(root, url)?;
+	let mut created = HashSet::new();
+	let (mut uploaded, mut deleted, mut conflicts) = (0, 0, 0);
+
+	let mut conflict = |file: &Path, what: &str| {
+		let message = format!("changed on the server since the last sync, not {what}");
+		Event::Error { path: Some(file), message }.emit();
+		conflicts += 1;
+	};
+
+	let mut present = HashSet::new();
+
+	for file in files {
+		let relative = file.strip_prefix(root).unwrap_or(file).to_path_buf();
+		let metadata = file.metadata()?;
+		let modified = metadata.modified()?.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+		present.insert(relative.clone());
+
+		let known = state.files.get(&relative);
+
+		if known.is_some_and(|s| s.size == metadata.len() && s.modified == modified) {
+			continue;
+		}
+
+		// A new file must not replace one already there, a changed one only the version uploaded last
+		let precondition = match known {
+			Some(synced) if !synced.etag.is_empty() => Some(format!("If-Match: {}", synced.etag)),
+			Some(_) => None, // The server gave no version to check against
+			None => Some("If-None-Match: *".to_owned()),
+		};
+
+		if let Some(dir) = relative.parent() {
+			client.create_dirs(dir, &mut created)?;
+		}
+
+		info!("Upload \"{}\"", relative.display());
+		let response = client.request("PUT", &relative, precondition.as_slice(), Some(file))?;
+
+		match response.status {
+			200..=299 => {
+				let etag = response.etag.unwrap_or_default();
+				state.files.insert(relative, Synced { size: metadata.len(), modified, etag });
+				uploaded += 1;
+			}
+			412 => conflict(file, "overwritten"),
+			status => anyhow::bail!("uploading \"{}\" failed ({status})", relative.display()),
+		}
+
+		// Saved as it goes, an interrupted sync doesn't upload everything again
+		state.write(root)?;
+	}
+
+	let gone: Vec<_> = state.files.keys().filter(|f| !present.contains(*f)).cloned().collect();
+
+	for relative in gone {
+		let synced = &state.files[&relative];
+		let precondition: Vec<_> =
+			Some(&synced.etag).filter(|e| !e.is_empty()).map(|e| format!("If-Match: {e}")).into_iter().collect();
+
+		info!("Delete \"{}\" from the server", relative.display());
+
+		match client.request("DELETE", &relative, &precondition, None)?.status {
+			200..=299 | 404 => {
+				state.files.remove(&relative);
+				deleted += 1;
+			}
+			412 => conflict(&root.join(&relative), "deleted"),
+			status => anyhow::bail!("deleting \"{}\" failed ({status})", relative.display()),
+		}
+
+		state.write(root)?;
+	}
+
+	info!("Uploaded {uploaded} files, deleted {deleted}");
+
+	Ok(conflicts)
+}
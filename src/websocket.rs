@@ -0,0 +1,121 @@
+//! WebSockets for pushing events to web pages live, just enough of RFC 6455 to send text to clients
+
+use crate::browse::base64;
+
+/// Appended to the key of a handshake before hashing it, the same for every server
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// The Sec-WebSocket-Accept answering the Sec-WebSocket-Key `key` of a client
+pub fn accept_key(key: &str) -> String {
+	base64(&sha1(format!("{key}{GUID}").as_bytes()))
+}
+
+/// `text` as a frame from the server, those aren't masked
+pub fn text_frame(text: &str) -> Vec<u8> {
+	let len = text.len();
+	// Final fragment of a text message
+	let mut frame = vec![0x81];
+
+	match len {
+		0..=125 => frame.push(len as u8),
+		126..=0xffff => {
+			frame.push(126);
+			frame.extend((len as u16).to_be_bytes());
+		}
+		_ => {
+			frame.push(127);
+			frame.extend((len as u64).to_be_bytes());
+		}
+	}
+
+	frame.extend(text.as_bytes());
+	frame
+}
+
+/// SHA-1 of `data`. It's broken for anything that has to be secure, the handshake only uses it to tell it apart
+/// from a plain HTTP request
+fn sha1(data: &[u8]) -> [u8; 20] {
+	let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+	// Padded with a 1 bit, zeroes and the length in bits to a multiple of 64 bytes
+	let mut message = data.to_vec();
+	message.push(0x80);
+
+	while message.len() % 64 != 56 {
+		message.push(0);
+	}
+
+	message.extend((data.len() as u64 * 8).to_be_bytes());
+
+	for block in message.chunks(64) {
+		let mut w = [0u32; 80];
+
+		for i in 0..80 {
+			w[i] = match i {
+				0..16 => u32::from_be_bytes(block[i * 4..i * 4 + 4].try_into().unwrap()),
+				_ => (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1),
+			};
+		}
+
+		let [mut a, mut b, mut c, mut d, mut e] = h;
+
+		for (i, &word) in w.iter().enumerate() {
+			let (f, k) = match i {
+				0..20 => ((b & c) | (!b & d), 0x5A827999),
+				20..40 => (b ^ c ^ d, 0x6ED9EBA1),
+				40..60 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+				_ => (b ^ c ^ d, 0xCA62C1D6),
+			};
+
+			let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(word);
+			(e, d, c, b, a) = (d, c, b.rotate_left(30), a, temp);
+		}
+
+		for (h, v) in h.iter_mut().zip([a, b, c, d, e]) {
+			*h = h.wrapping_add(v);
+		}
+	}
+
+	let mut digest = [0; 20];
+
+	for (bytes, word) in digest.chunks_mut(4).zip(h) {
+		bytes.copy_from_slice(&word.to_be_bytes());
+	}
+
+	digest
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn hex(bytes: &[u8]) -> String {
+		bytes.iter().map(|b| format!("{b:02x}")).collect()
+	}
+
+	#[test]
+	fn accept_key_of_rfc_6455() {
+		assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+	}
+
+	// From FIPS 180-2, the last two need one and two blocks of padding
+	#[test]
+	fn sha1_vectors() {
+		assert_eq!(hex(&sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+		assert_eq!(hex(&sha1(b"abc")), "a9993e364706816aba3e25717850c26c9cd0d89d");
+		assert_eq!(
+			hex(&sha1(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")),
+			"84983e441c3bd26ebaae4aa1f95129e5e54670f1"
+		);
+		assert_eq!(hex(&sha1(&[b'a'; 1_000_000])), "34aa973cd4c4daa4f61eeb2bdbad27316534016f");
+	}
+
+	#[test]
+	fn frame_lengths() {
+		assert_eq!(text_frame("hi"), [0x81, 2, b'h', b'i']);
+		assert_eq!(text_frame(&"a".repeat(125))[..2], [0x81, 125]);
+		assert_eq!(text_frame(&"a".repeat(126))[..4], [0x81, 126, 0, 126]);
+		assert_eq!(text_frame(&"a".repeat(0x10000))[..10], [0x81, 127, 0, 0, 0, 0, 0, 1, 0, 0]);
+		assert_eq!(text_frame(&"a".repeat(0x10000)).len(), 10 + 0x10000);
+	}
+}
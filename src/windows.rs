@@ -0,0 +1,64 @@
+//! Which window each screenshot was taken in, recorded with --window-info
+
+use std::{
+	collections::HashMap,
+	fs,
+	io::{self, Write},
+	path::{Path, PathBuf},
+};
+
+use crate::{
+	changes::rebase,
+	compositor::Window,
+	journal::{escape, unescape},
+	STATE_DIR,
+};
+
+fn index_file(root: &Path) -> PathBuf {
+	root.join(STATE_DIR).join("windows")
+}
+
+/// Notes the window `file` in the tree below `root` was taken in
+pub fn record(root: &Path, file: &Path, window: &Window) -> io::Result<()> {
+	let index = index_file(root);
+	fs::create_dir_all(index.parent().unwrap())?; // Always has one
+
+	let file = file.strip_prefix(root).unwrap_or(file);
+	let field = |s: &Option<String>| escape(s.as_deref().unwrap_or_default());
+	let line = [escape(&file.to_string_lossy()), field(&window.title), field(&window.workspace)].join("\t") + "\n";
+
+	// A single write, like the journal
+	fs::OpenOptions::new().create(true).append(true).open(index)?.write_all(line.as_bytes())
+}
+
+/// Windows of every file recorded, by their path below `root`. Later records of a path win
+pub fn read(root: &Path) -> io::Result<HashMap<PathBuf, Window>> {
+	let contents = match fs::read_to_string(index_file(root)) {
+		Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+		res => res?,
+	};
+
+	let field = |s: &str| Some(unescape(s)).filter(|s| !s.is_empty());
+
+	Ok(contents
+		.lines()
+		.filter_map(|line| {
+			let mut fields = line.split('\t');
+			let file = root.join(unescape(fields.next()?));
+			let (title, workspace) = (field(fields.next()?), field(fields.next()?));
+
+			Some((file, Window { title, workspace }))
+		})
+		.collect())
+}
+
+/// Records the window of `from` again for `to`, where it was moved. Of every file in it if it's a folder
+pub fn moved(root: &Path, from: &Path, to: &Path) -> io::Result<()> {
+	for (file, window) in read(root)? {
+		if let Some(moved) = rebase(&file, from, to) {
+			record(root, &moved, &window)?;
+		}
+	}
+
+	Ok(())
+}